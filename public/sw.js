@@ -0,0 +1,10 @@
+// Shows the change alerts sent by the server with Web Push
+self.addEventListener("push", (event) => {
+    const message = event.data ? event.data.json() : {};
+    event.waitUntil(self.registration.showNotification(message.title || "Stundenplan", { body: message.body }));
+});
+
+self.addEventListener("notificationclick", (event) => {
+    event.notification.close();
+    event.waitUntil(clients.openWindow("/app"));
+});
@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::{
+    alerts::{Alert, AlertError, AlertSender, AlertTarget},
+    api::{generate_api_token, hash_api_token},
+    i18n::Locale,
+};
+
+pub struct EmailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    /// Where the server is reached from the internet, for the confirmation links
+    public_url: String,
+}
+
+/// Random token for a confirmation link, like an API token, and the hash the store keeps
+pub fn confirmation_token() -> (String, String) {
+    let (token, _) = generate_api_token();
+    let hash = hash_api_token(&token);
+    (token, hash)
+}
+
+impl EmailSender {
+    /// Configured by `SMTP_HOST`, `SMTP_PORT`, `SMTP_USER`, `SMTP_PASSWORD`, `SMTP_FROM` and
+    /// `PUBLIC_URL`, e.g. `https://stundenplan.example.org`. `SMTP_INSECURE=1` sends without TLS,
+    /// for test servers on localhost. Without `SMTP_HOST`, email alerts are disabled.
+    pub fn from_env() -> Option<EmailSender> {
+        let host = std::env::var("SMTP_HOST").ok()?;
+        let from = std::env::var("SMTP_FROM")
+            .expect("SMTP_FROM is required with SMTP_HOST")
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid SMTP_FROM: {e}"));
+        let public_url = std::env::var("PUBLIC_URL")
+            .expect("PUBLIC_URL is required with SMTP_HOST")
+            .trim_end_matches('/')
+            .to_owned();
+
+        let mut builder = match std::env::var("SMTP_INSECURE").as_deref() {
+            Ok("1") => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+            _ => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+                .unwrap_or_else(|e| panic!("invalid SMTP_HOST {host}: {e}")),
+        };
+        if let Some(port) = std::env::var("SMTP_PORT").ok().and_then(|p| p.parse().ok()) {
+            builder = builder.port(port);
+        }
+        if let (Ok(user), Ok(password)) =
+            (std::env::var("SMTP_USER"), std::env::var("SMTP_PASSWORD"))
+        {
+            builder = builder.credentials(Credentials::new(user, password));
+        }
+
+        Some(EmailSender {
+            transport: builder.build(),
+            from,
+            public_url,
+        })
+    }
+
+    /// Asks the owner of `address` to open `/email/confirm` with the token
+    pub async fn send_confirmation(
+        &self,
+        address: &str,
+        token: &str,
+        locale: Locale,
+    ) -> Result<(), AlertError> {
+        let strings = locale.strings();
+        let link = format!("{}/email/confirm?token={token}", self.public_url);
+        self.send_mail(
+            address,
+            strings.email_confirm_subject,
+            format!("{}\n\n{link}", strings.email_confirm_body),
+        )
+        .await
+    }
+
+    async fn send_mail(
+        &self,
+        address: &str,
+        subject: &str,
+        body: String,
+    ) -> Result<(), AlertError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(address
+                .parse()
+                .map_err(|e| AlertError::InvalidTarget(format!("invalid address: {e}")))?)
+            .subject(subject)
+            .body(body)
+            .map_err(|e| AlertError::Delivery(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AlertError::Delivery(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AlertSender for EmailSender {
+    async fn send(&self, target: &AlertTarget, alert: &Alert) -> Result<(), AlertError> {
+        let AlertTarget::Email(address) = target else {
+            return Err(AlertError::Unsupported);
+        };
+
+        self.send_mail(address, &alert.title, alert.changes.join("\n"))
+            .await
+    }
+}
@@ -0,0 +1,425 @@
+//! Background checks for timetable changes of users who opted in, with alerts sent to their
+//! webhook, email address and browsers.
+//!
+//! Every sender implements [`AlertSender`], so they can be replaced by local stand-ins, e.g. a
+//! sender that only records alerts or an SMTP server on localhost.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use cookie::Key;
+use log::{error, info};
+
+use crate::{
+    api::unseal_token,
+    components::changes::{describe_change, describe_period_change},
+    i18n::Locale,
+    sources::{
+        holidays::SchoolCalendar, load_configured, load_current_week, today, SourceConfig,
+        SourceError,
+    },
+    store::{SettingsStore, StoreError},
+    types::{
+        diff::diff,
+        settings::{PushSubscription, UserSettings},
+        timetable::Timetable,
+    },
+};
+
+pub mod email;
+pub mod push;
+pub mod webhook;
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Alert {
+    pub title: String,
+    /// One line per change
+    pub changes: Vec<String>,
+}
+
+impl Alert {
+    /// Describes the changes between two timetables of the same week
+    pub fn new(old: &Timetable, new: &Timetable, locale: Locale) -> Alert {
+        let diff = diff(old, new);
+        let changes = diff
+            .lessons
+            .iter()
+            .map(|change| describe_change(change, locale))
+            .chain(
+                diff.periods
+                    .iter()
+                    .map(|change| describe_period_change(change, locale)),
+            )
+            .collect();
+
+        Alert {
+            title: locale.strings().alert_title.to_owned(),
+            changes,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        format!("{}\n{}", self.title, self.changes.join("\n"))
+    }
+}
+
+/// Where an alert is delivered to
+#[derive(PartialEq, Clone, Debug)]
+pub enum AlertTarget {
+    Webhook(String),
+    Email(String),
+    Push(PushSubscription),
+}
+
+impl AlertTarget {
+    pub fn of(settings: &UserSettings) -> Vec<AlertTarget> {
+        let notifications = &settings.notifications;
+        let mut targets = vec![];
+        if let Some(url) = &notifications.webhook_url {
+            targets.push(AlertTarget::Webhook(url.clone()));
+        }
+        if let Some(email) = &notifications.email {
+            targets.push(AlertTarget::Email(email.clone()));
+        }
+        for subscription in &notifications.push_subscriptions {
+            targets.push(AlertTarget::Push(subscription.clone()));
+        }
+        targets
+    }
+}
+
+#[derive(Debug)]
+pub enum AlertError {
+    Source(SourceError),
+    Store(StoreError),
+    /// The user enabled alerts, but there is no usable refresh token
+    Unauthenticated,
+    /// No sender is configured for this kind of target
+    Unsupported,
+    /// The target may not be used, e.g. a webhook on the local network
+    InvalidTarget(String),
+    Delivery(String),
+}
+
+impl From<SourceError> for AlertError {
+    fn from(error: SourceError) -> Self {
+        AlertError::Source(error)
+    }
+}
+
+impl From<StoreError> for AlertError {
+    fn from(error: StoreError) -> Self {
+        AlertError::Store(error)
+    }
+}
+
+#[async_trait]
+pub trait AlertSender: Send + Sync {
+    async fn send(&self, target: &AlertTarget, alert: &Alert) -> Result<(), AlertError>;
+}
+
+/// Senders for each kind of target. Email and push need configuration and are optional.
+pub struct Senders {
+    pub webhook: Box<dyn AlertSender>,
+    pub email: Option<Box<dyn AlertSender>>,
+    pub push: Option<Box<dyn AlertSender>>,
+}
+
+impl Senders {
+    pub fn from_env() -> Senders {
+        Senders {
+            webhook: Box::new(webhook::WebhookSender::new()),
+            email: email::EmailSender::from_env()
+                .map(|sender| Box::new(sender) as Box<dyn AlertSender>),
+            push: push::PushSender::from_env()
+                .map(|sender| Box::new(sender) as Box<dyn AlertSender>),
+        }
+    }
+
+    async fn send(&self, target: &AlertTarget, alert: &Alert) -> Result<(), AlertError> {
+        let sender = match target {
+            AlertTarget::Webhook(_) => Some(&self.webhook),
+            AlertTarget::Email(_) => self.email.as_ref(),
+            AlertTarget::Push(_) => self.push.as_ref(),
+        };
+        sender
+            .ok_or(AlertError::Unsupported)?
+            .send(target, alert)
+            .await
+    }
+}
+
+/// Periodically fetches the timetables of users with `notifications.changes` and compares them
+/// with the last snapshot
+pub struct ChangeWatcher {
+    pub store: SettingsStore,
+    pub sources: Arc<Vec<SourceConfig>>,
+    pub calendar: Arc<SchoolCalendar>,
+    pub senders: Senders,
+    /// Opens the sealed refresh tokens of users
+    pub key: Key,
+}
+
+impl ChangeWatcher {
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.check_all().await;
+        }
+    }
+
+    pub async fn check_all(&self) {
+        for (user_id, settings) in self.store.users().await {
+            if !settings.notifications.changes {
+                continue;
+            }
+            if let Err(e) = self.check(user_id, &settings).await {
+                error!("Checking the timetable of user {user_id} for changes failed: {:?}", e);
+            }
+        }
+    }
+
+    /// Access token from the sealed refresh token of the user
+    async fn access_token(&self, user_id: u32) -> Result<String, AlertError> {
+        let refresh_token = self
+            .store
+            .alert_token(user_id)
+            .await
+            .and_then(|token| unseal_token(&self.key, &token))
+            .ok_or(AlertError::Unauthenticated)?;
+        let access_token = homeworker::auth::refresh_token(
+            std::env::var("HW_CLIENT_ID").unwrap(),
+            std::env::var("HW_SECRET").unwrap(),
+            refresh_token,
+        )
+        .await
+        .map_err(|_| {
+            crate::telemetry::token_refresh("alerts", false);
+            AlertError::Unauthenticated
+        })?
+        .access_token;
+        crate::telemetry::token_refresh("alerts", true);
+        Ok(access_token)
+    }
+
+    async fn check(&self, user_id: u32, settings: &UserSettings) -> Result<(), AlertError> {
+        // Other sources don't need a login
+        let access_token = match self
+            .sources
+            .iter()
+            .any(|config| matches!(config, SourceConfig::Homeworker { .. }))
+        {
+            true => Some(self.access_token(user_id).await?),
+            false => None,
+        };
+
+        let load = |week| {
+            load_configured(&self.sources, &self.calendar, access_token.clone(), settings, week)
+        };
+        let (_, timetable) = load_current_week(today(), load).await?;
+
+        // A new week isn't a change
+        let previous = self.store.snapshot(user_id).await;
+        if let Some(previous) = previous.filter(|previous| {
+            previous.date_range() == timetable.date_range() && *previous != timetable
+        }) {
+            let alert = Alert::new(&previous, &timetable, settings.locale.unwrap_or_default());
+            info!("Timetable of user {user_id} changed, sending alerts");
+            for target in AlertTarget::of(settings) {
+                if let Err(e) = self.senders.send(&target, &alert).await {
+                    error!("Sending an alert to user {user_id} failed: {:?}", e);
+                }
+            }
+        }
+
+        self.store.set_snapshot(user_id, timetable).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{test_util::temp_dir, types::settings::PushKeys};
+
+    /// Local stand-in that records alerts instead of delivering them
+    #[derive(Clone, Default)]
+    struct RecordingSender(Arc<Mutex<Vec<AlertTarget>>>);
+
+    #[async_trait]
+    impl AlertSender for RecordingSender {
+        async fn send(&self, target: &AlertTarget, _alert: &Alert) -> Result<(), AlertError> {
+            self.0.lock().unwrap().push(target.clone());
+            Ok(())
+        }
+    }
+
+    /// Manual timetable with a single lesson on mondays
+    fn monday_lesson(subject: &str) -> String {
+        format!(
+            r#"
+[subjects.MA]
+name = "Mathematik"
+
+[subjects.PH]
+name = "Physik"
+
+[[lessons]]
+day = "monday"
+periods = [1]
+subject = "{subject}"
+"#
+        )
+    }
+
+    /// Watcher for one user with change alerts to a webhook, an email address and a browser, all
+    /// recorded by `sender`. The timetable is the manual file at the returned path.
+    async fn watcher(name: &str, sender: &RecordingSender) -> (ChangeWatcher, String) {
+        let dir = temp_dir(name);
+        let timetable_path = dir.join("timetable.toml").to_string_lossy().into_owned();
+        std::fs::write(&timetable_path, monday_lesson("MA")).unwrap();
+
+        let store = SettingsStore::open(dir.join("settings.json"))
+            .await
+            .unwrap();
+        store
+            .update(1, |settings| {
+                let notifications = &mut settings.notifications;
+                notifications.changes = true;
+                notifications.webhook_url = Some("https://1.1.1.1/hook".to_owned());
+                notifications.email = Some("someone@example.org".to_owned());
+                notifications.push_subscriptions = vec![PushSubscription {
+                    endpoint: "https://push.example.org/1".to_owned(),
+                    keys: PushKeys {
+                        p256dh: "key".to_owned(),
+                        auth: "auth".to_owned(),
+                    },
+                }];
+            })
+            .await
+            .unwrap();
+
+        let watcher = ChangeWatcher {
+            store,
+            sources: Arc::new(vec![SourceConfig::Manual {
+                name: None,
+                path: timetable_path.clone(),
+            }]),
+            calendar: Arc::default(),
+            senders: Senders {
+                webhook: Box::new(sender.clone()),
+                email: Some(Box::new(sender.clone())),
+                push: Some(Box::new(sender.clone())),
+            },
+            key: Key::from(&[7u8; 64][..]),
+        };
+        (watcher, timetable_path)
+    }
+
+    #[tokio::test]
+    async fn changes_are_sent_to_every_target() {
+        let sender = RecordingSender::default();
+        let (watcher, timetable_path) = watcher("alerts-changes", &sender).await;
+
+        // The first check only takes a snapshot
+        watcher.check_all().await;
+        assert!(sender.0.lock().unwrap().is_empty());
+        watcher.check_all().await;
+        assert!(sender.0.lock().unwrap().is_empty());
+
+        std::fs::write(&timetable_path, monday_lesson("PH")).unwrap();
+        watcher.check_all().await;
+        let settings = watcher.store.get(1).await;
+        assert_eq!(*sender.0.lock().unwrap(), AlertTarget::of(&settings));
+        assert_eq!(sender.0.lock().unwrap().len(), 3);
+
+        // The change is in the snapshot now
+        watcher.check_all().await;
+        assert_eq!(sender.0.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_new_week_is_not_a_change() {
+        let sender = RecordingSender::default();
+        let (watcher, timetable_path) = watcher("alerts-new-week", &sender).await;
+        watcher.check_all().await;
+
+        // As if the snapshot was taken a week ago
+        let mut last_week = watcher.store.snapshot(1).await.unwrap();
+        for column in &mut last_week.columns {
+            column.date = column.date.map(|date| date - time::Duration::weeks(1));
+        }
+        watcher
+            .store
+            .set_snapshot(1, last_week.clone())
+            .await
+            .unwrap();
+        std::fs::write(&timetable_path, monday_lesson("PH")).unwrap();
+        watcher.check_all().await;
+
+        assert!(sender.0.lock().unwrap().is_empty());
+        let snapshot = watcher.store.snapshot(1).await.unwrap();
+        assert_ne!(snapshot.date_range(), last_week.date_range());
+    }
+
+    fn empty_timetable() -> Timetable {
+        Timetable {
+            times: vec![],
+            columns: vec![],
+            week_type: None,
+        }
+    }
+
+    #[test]
+    fn alerts_are_written_in_the_locale_of_the_user() {
+        let timetable = empty_timetable();
+        assert_eq!(Alert::new(&timetable, &timetable, Locale::English).title, "Timetable changes");
+        assert_eq!(
+            Alert::new(&timetable, &timetable, Locale::German).title,
+            "Änderungen im Stundenplan"
+        );
+    }
+
+    #[test]
+    fn targets_go_to_the_sender_of_their_kind() {
+        let webhook = RecordingSender::default();
+        let email = RecordingSender::default();
+        let senders = Senders {
+            webhook: Box::new(webhook.clone()),
+            email: Some(Box::new(email.clone())),
+            push: None,
+        };
+
+        let mut settings = UserSettings::default();
+        settings.notifications.webhook_url = Some("https://1.1.1.1/hook".to_owned());
+        settings.notifications.email = Some("someone@example.org".to_owned());
+        let targets = AlertTarget::of(&settings);
+        let alert = Alert::new(&empty_timetable(), &empty_timetable(), Locale::German);
+        for target in &targets {
+            block_on(senders.send(target, &alert)).unwrap();
+        }
+
+        assert_eq!(*webhook.0.lock().unwrap(), vec![targets[0].clone()]);
+        assert_eq!(*email.0.lock().unwrap(), vec![targets[1].clone()]);
+    }
+
+    #[test]
+    fn targets_without_a_sender_are_unsupported() {
+        let senders = Senders {
+            webhook: Box::new(RecordingSender::default()),
+            email: None,
+            push: None,
+        };
+        let alert = Alert::new(&empty_timetable(), &empty_timetable(), Locale::German);
+
+        let result =
+            block_on(senders.send(&AlertTarget::Email("a@example.org".to_owned()), &alert));
+
+        assert!(matches!(result, Err(AlertError::Unsupported)));
+    }
+}
@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use web_push::{
+    request_builder, ContentEncoding, SubscriptionInfo, VapidSignatureBuilder,
+    WebPushMessageBuilder, URL_SAFE_NO_PAD,
+};
+
+use crate::alerts::{
+    webhook::{pinned_client, public_webhook},
+    Alert, AlertError, AlertSender, AlertTarget,
+};
+
+/// Sends Web Push messages, which `public/sw.js` shows as notifications. The endpoints come from
+/// the browser, so they are checked like webhooks before anything is sent.
+pub struct PushSender {
+    /// VAPID key pair in url-safe base64. Browsers subscribe with the public key.
+    private_key: String,
+}
+
+#[derive(Serialize)]
+struct PushMessage<'a> {
+    title: &'a str,
+    body: String,
+}
+
+impl PushSender {
+    /// Enabled when `VAPID_PRIVATE_KEY` is set. `VAPID_PUBLIC_KEY` has to be set as well, for the
+    /// subscription in the browser.
+    pub fn from_env() -> Option<PushSender> {
+        Some(PushSender {
+            private_key: std::env::var("VAPID_PRIVATE_KEY").ok()?,
+        })
+    }
+}
+
+#[async_trait]
+impl AlertSender for PushSender {
+    async fn send(&self, target: &AlertTarget, alert: &Alert) -> Result<(), AlertError> {
+        let AlertTarget::Push(subscription) = target else {
+            return Err(AlertError::Unsupported);
+        };
+        let to_error = |e: web_push::WebPushError| AlertError::Delivery(e.to_string());
+        // Checked again on every alert, as DNS may have changed since the subscription was saved
+        let (url, address) = public_webhook(&subscription.endpoint).await?;
+
+        let info = SubscriptionInfo::new(
+            &subscription.endpoint,
+            &subscription.keys.p256dh,
+            &subscription.keys.auth,
+        );
+        let signature =
+            VapidSignatureBuilder::from_base64(&self.private_key, URL_SAFE_NO_PAD, &info)
+                .map_err(to_error)?
+                .build()
+                .map_err(to_error)?;
+
+        let payload = serde_json::to_vec(&PushMessage {
+            title: &alert.title,
+            body: alert.changes.join("\n"),
+        })
+        .unwrap();
+        let mut message = WebPushMessageBuilder::new(&info).map_err(to_error)?;
+        message.set_payload(ContentEncoding::Aes128Gcm, &payload);
+        message.set_vapid_signature(signature);
+
+        let request: http::Request<Vec<u8>> =
+            request_builder::build_request(message.build().map_err(to_error)?);
+        let (parts, body) = request.into_parts();
+        pinned_client(&url, address)?
+            .request(parts.method, url)
+            .headers(parts.headers)
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AlertError::Delivery(e.to_string()))?;
+        Ok(())
+    }
+}
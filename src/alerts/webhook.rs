@@ -0,0 +1,170 @@
+use std::net::{IpAddr, SocketAddr};
+
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::alerts::{Alert, AlertError, AlertSender, AlertTarget};
+
+/// Posts alerts as JSON. `content` is read by Discord, `text` by Matrix hookshot webhooks.
+pub struct WebhookSender;
+
+#[derive(Serialize)]
+struct WebhookMessage<'a> {
+    username: &'a str,
+    content: &'a str,
+    text: &'a str,
+}
+
+impl WebhookSender {
+    pub fn new() -> WebhookSender {
+        WebhookSender
+    }
+}
+
+/// Addresses on the internet, without loopback, private, link-local, shared (CGNAT),
+/// documentation and multicast ranges
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    // Unique local fc00::/7 and link-local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Webhooks are posted from the server, so users could otherwise make it reach services on its
+/// own network. Only HTTPS URLs whose host resolves to public addresses are accepted. Returns the
+/// address to connect to, so the host can't resolve to a different one afterwards.
+pub async fn public_webhook(url: &str) -> Result<(Url, SocketAddr), AlertError> {
+    let invalid = |reason: &str| AlertError::InvalidTarget(format!("{url}: {reason}"));
+    let url = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    if url.scheme() != "https" {
+        return Err(invalid("only https is allowed"));
+    }
+    let host = url.host_str().ok_or_else(|| invalid("missing host"))?;
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addresses: Vec<SocketAddr> = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| invalid(&e.to_string()))?
+            .collect(),
+    };
+    match addresses.first() {
+        Some(_) if addresses.iter().any(|address| !is_public(address.ip())) => {
+            Err(invalid("not a public address"))
+        }
+        Some(address) => Ok((url, *address)),
+        None => Err(invalid("host not found")),
+    }
+}
+
+/// Client that only connects to `address`, the one checked by [`public_webhook`]. Redirects aren't
+/// followed, they could point anywhere.
+pub fn pinned_client(url: &Url, address: SocketAddr) -> Result<reqwest::Client, AlertError> {
+    reqwest::Client::builder()
+        .resolve(url.host_str().unwrap_or_default(), address)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| AlertError::Delivery(e.to_string()))
+}
+
+#[async_trait]
+impl AlertSender for WebhookSender {
+    async fn send(&self, target: &AlertTarget, alert: &Alert) -> Result<(), AlertError> {
+        let AlertTarget::Webhook(url) = target else {
+            return Err(AlertError::Unsupported);
+        };
+
+        // Checked again on every alert, as DNS may have changed since the URL was saved
+        let (url, address) = public_webhook(url).await?;
+        let client = pinned_client(&url, address)?;
+
+        let text = alert.text();
+        client
+            .post(url)
+            .json(&WebhookMessage {
+                username: "Stundenplan",
+                content: &text,
+                text: &text,
+            })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AlertError::Delivery(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.178.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["1.1.1.1", "162.159.128.233", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn webhooks_need_https_and_a_public_host() {
+        for url in [
+            "http://1.1.1.1/hook",
+            "https://127.0.0.1/hook",
+            "https://[::1]:8448/hook",
+            "https://169.254.169.254/latest",
+            "not a url",
+        ] {
+            assert!(
+                matches!(block_on(public_webhook(url)), Err(AlertError::InvalidTarget(_))),
+                "{url}"
+            );
+        }
+
+        let (url, address) = block_on(public_webhook("https://1.1.1.1:8443/hook")).unwrap();
+        assert_eq!(url.path(), "/hook");
+        assert_eq!(address, "1.1.1.1:8443".parse().unwrap());
+    }
+}
@@ -0,0 +1,152 @@
+//! Schema of the public JSON API. Other tools should only rely on what is documented here.
+//!
+//! # Version 1
+//!
+//! `GET /api/v1/timetable?course=<id>&week=<YYYY-Www>`
+//!
+//! Both parameters are optional. `course` defaults to the first course membership, `week` to the
+//! current week, or on weekends to the next one once it has lessons. The timetable is loaded from
+//! the sources configured on the server, like the dashboard. Weeks without any days result in a
+//! timetable without columns.
+//!
+//! Authentication is done with the session cookie of the web app or with a personal API token in
+//! the `Authorization: Bearer <token>` header. Tokens are created and revoked on `/app/api`.
+//!
+//! On success, the response is a [`TimetableResponse`]. Dates are formatted as `YYYY-MM-DD`, times
+//! as `HH:MM:SS.f`. Errors are returned as [`ErrorResponse`] with a matching status code:
+//! `400` for invalid parameters, `401` for missing or invalid authentication, `404` if the user has
+//! no course membership and `502` if a source could not be loaded, e.g. Homeworker could not be
+//! reached.
+//!
+//! New fields may be added within a version. Removing or changing fields requires a new version.
+
+use serde::{Deserialize, Serialize};
+use time::{Date, Weekday};
+
+use crate::types::timetable::Timetable;
+
+pub const API_VERSION: u32 = 1;
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct TimetableResponse {
+    /// Always [`API_VERSION`]
+    pub version: u32,
+    /// Only set if the timetable comes from a Homeworker course
+    pub course_id: Option<u32>,
+    /// Name of the Homeworker course, or of the configured sources without one
+    pub course_name: String,
+    /// Monday of the returned week
+    pub week: Date,
+    pub timetable: Timetable,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub version: u32,
+    pub error: String,
+}
+
+impl ErrorResponse {
+    pub fn new(error: &str) -> ErrorResponse {
+        ErrorResponse {
+            version: API_VERSION,
+            error: error.to_owned(),
+        }
+    }
+}
+
+/// Encrypts a refresh token with the cookie key before it is stored on the server, e.g. for API
+/// tokens and change alerts.
+#[cfg(feature = "ssr")]
+pub fn seal_token(key: &cookie::Key, refresh_token: String) -> String {
+    let mut jar = cookie::CookieJar::new();
+    jar.private_mut(key)
+        .add(cookie::Cookie::new("api-token", refresh_token));
+    jar.get("api-token").unwrap().value().to_owned()
+}
+
+#[cfg(feature = "ssr")]
+pub fn unseal_token(key: &cookie::Key, token: &str) -> Option<String> {
+    let mut jar = cookie::CookieJar::new();
+    jar.add_original(cookie::Cookie::new("api-token", token.to_owned()));
+    jar.private(key)
+        .get("api-token")
+        .map(|c| c.value().to_owned())
+}
+
+/// API tokens are random and only their hash is stored, next to the sealed refresh token they
+/// stand for. This returns the token for the user and its short id for the list on `/app/api`.
+#[cfg(feature = "ssr")]
+pub fn generate_api_token() -> (String, String) {
+    use base64::Engine;
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(rng.gen::<[u8; 32]>());
+    let id = format!("{:08x}", rng.gen::<u32>());
+    (token, id)
+}
+
+#[cfg(feature = "ssr")]
+pub fn hash_api_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Shown on `/app/api`, without the token itself
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ApiTokenInfo {
+    pub id: String,
+    pub created: Date,
+}
+
+/// Formats the week of `date` like `2023-W08`, the inverse of `parse_iso_week`
+pub fn format_iso_week(date: Date) -> String {
+    let (year, week, _) = date.to_iso_week_date();
+    format!("{year}-W{week:02}")
+}
+
+/// Parses an ISO week like `2023-W08` and returns its monday
+pub fn parse_iso_week(week: &str) -> Option<Date> {
+    let (year, week) = week.split_once("-W")?;
+    Date::from_iso_week_date(year.parse().ok()?, week.parse().ok()?, Weekday::Monday).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn weeks_are_formatted_with_their_iso_year() {
+        assert_eq!(format_iso_week(date!(2023 - 02 - 22)), "2023-W08");
+        // Belongs to the last week of the year before
+        assert_eq!(format_iso_week(date!(2021 - 01 - 01)), "2020-W53");
+        // Belongs to the first week of the next year
+        assert_eq!(format_iso_week(date!(2024 - 12 - 30)), "2025-W01");
+    }
+
+    #[test]
+    fn weeks_are_parsed_to_their_monday() {
+        assert_eq!(parse_iso_week("2023-W08"), Some(date!(2023 - 02 - 20)));
+        assert_eq!(parse_iso_week("2020-W53"), Some(date!(2020 - 12 - 28)));
+        assert_eq!(parse_iso_week("2025-W01"), Some(date!(2024 - 12 - 30)));
+        assert_eq!(parse_iso_week("2023-W8"), Some(date!(2023 - 02 - 20)));
+        assert_eq!(
+            parse_iso_week(&format_iso_week(date!(2021 - 01 - 01))),
+            Some(date!(2020 - 12 - 28))
+        );
+    }
+
+    #[test]
+    fn invalid_weeks_are_rejected() {
+        for week in [
+            "2023-W00", "2021-W53", "2023-W54", "2023-08", "2023-W", "-W08", "abcd-W08",
+            "2023-Wxx", "",
+        ] {
+            assert_eq!(parse_iso_week(week), None, "{week}");
+        }
+    }
+}
@@ -2,9 +2,17 @@ use leptos::*;
 use leptos_meta::*;
 use leptos_router::*;
 
+use crate::routes::api_token::*;
 use crate::routes::dashboard::*;
+use crate::routes::display::*;
+use crate::routes::free::*;
 use crate::routes::home::*;
+use crate::routes::lessons::*;
 use crate::routes::login::*;
+use crate::routes::print::*;
+use crate::routes::settings::*;
+use crate::routes::subjects::*;
+use crate::routes::widget::*;
 
 #[derive(Clone, Copy)]
 pub struct HomeworkerContext {
@@ -33,6 +41,14 @@ pub fn app(cx: Scope) -> impl IntoView {
                 <Route path="/" view=move |cx| view! { cx, <HomePage/> } />
                 <Route path="/login" view=move |cx| view! { cx, <LoginPage/> } />
                 <Route path="/app" view=move |cx| view! { cx, <DashboardPage/> } />
+                <Route path="/app/lessons" view=move |cx| view! { cx, <MyLessonsPage/> } />
+                <Route path="/app/free" view=move |cx| view! { cx, <FreePeriodsPage/> } />
+                <Route path="/app/api" view=move |cx| view! { cx, <ApiTokenPage/> } />
+                <Route path="/app/print" view=move |cx| view! { cx, <PrintPage/> } />
+                <Route path="/app/subjects" view=move |cx| view! { cx, <SubjectsPage/> } />
+                <Route path="/settings" view=move |cx| view! { cx, <SettingsPage/> } />
+                <Route path="/display" view=move |cx| view! { cx, <DisplayPage/> } />
+                <Route path="/widget" view=move |cx| view! { cx, <WidgetPage/> } />
             </Routes>
         </Router>
     }
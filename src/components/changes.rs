@@ -0,0 +1,125 @@
+use leptos::*;
+
+use crate::{
+    i18n::Locale,
+    types::diff::{LessonChange, PeriodChange, Slot, TimetableDiff},
+};
+
+fn describe_slot(slot: &Slot, locale: Locale) -> String {
+    let day = match slot.date {
+        Some(date) => format!("{} {}", locale.weekday(date.weekday()), locale.format_date(date)),
+        None => slot.column.clone(),
+    };
+    // Positions are counted from 1 for people
+    let positions = match slot.positions.len() {
+        1 => format!("{}", slot.positions.start + 1),
+        _ => format!("{}–{}", slot.positions.start + 1, slot.positions.end),
+    };
+    format!("{day}, {} {positions}", locale.strings().period)
+}
+
+/// One line for alerts and the changes panel
+pub fn describe_change(change: &LessonChange, locale: Locale) -> String {
+    let strings = locale.strings();
+    match change {
+        LessonChange::Added { slot, lesson } => format!(
+            "{}: {} ({})",
+            describe_slot(slot, locale),
+            lesson.subject.full_name,
+            strings.change_added
+        ),
+        LessonChange::Removed { slot, lesson } => format!(
+            "{}: {} ({})",
+            describe_slot(slot, locale),
+            lesson.subject.full_name,
+            strings.change_removed
+        ),
+        LessonChange::Moved { from, to, lesson } => format!(
+            "{}: {} → {}",
+            lesson.subject.full_name,
+            describe_slot(from, locale),
+            describe_slot(to, locale)
+        ),
+        LessonChange::Cancelled { slot, lesson } => format!(
+            "{}: {} ({})",
+            describe_slot(slot, locale),
+            lesson.subject.full_name,
+            strings.notification_cancelled
+        ),
+        LessonChange::Replaced {
+            slot,
+            lesson,
+            replacement,
+        } => format!(
+            "{}: {} → {} {} ({} {})",
+            describe_slot(slot, locale),
+            lesson.subject.full_name,
+            strings.notification_replaced,
+            replacement.full_name,
+            strings.room,
+            replacement.room
+        ),
+        LessonChange::Restored { slot, lesson } => format!(
+            "{}: {} ({})",
+            describe_slot(slot, locale),
+            lesson.subject.full_name,
+            strings.change_restored
+        ),
+        LessonChange::Changed { slot, old, new } => format!(
+            "{}: {} ({} {} → {}, {} → {})",
+            describe_slot(slot, locale),
+            new.subject.full_name,
+            strings.room,
+            old.subject.room,
+            new.subject.room,
+            old.subject.teacher,
+            new.subject.teacher
+        ),
+    }
+}
+
+pub fn describe_period_change(change: &PeriodChange, locale: Locale) -> String {
+    let format = |period: Option<crate::types::timetable::Period>| match period {
+        Some(period) => {
+            format!("{} - {}", locale.format_time(period.start), locale.format_time(period.end))
+        }
+        None => "–".to_owned(),
+    };
+    format!(
+        "{} {}: {} → {}",
+        locale.strings().period,
+        change.position + 1,
+        format(change.old),
+        format(change.new)
+    )
+}
+
+/// Lists what changed since the user last saw the timetable. Renders nothing without changes.
+#[component]
+pub fn changes_panel(cx: Scope, changes: TimetableDiff, locale: Locale) -> impl IntoView {
+    if changes.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<_> = changes
+        .lessons
+        .iter()
+        .map(|change| describe_change(change, locale))
+        .chain(
+            changes
+                .periods
+                .iter()
+                .map(|change| describe_period_change(change, locale)),
+        )
+        .map(|line| view! { cx, <li>{line}</li> })
+        .collect();
+
+    Some(view! { cx,
+        <div class="w-[32rem] p-4 border rounded-xl border-orange-500">
+            <h2 class="mb-2">{locale.strings().changes_title}</h2>
+            <ul class="list-disc pl-4 text-sm">
+                {lines}
+            </ul>
+        </div>
+    })
+}
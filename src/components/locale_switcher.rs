@@ -0,0 +1,25 @@
+use leptos::*;
+
+use crate::i18n::Locale;
+
+/// Links to the server route that stores the locale override in a cookie
+#[component]
+pub fn locale_switcher(cx: Scope, current: Locale) -> impl IntoView {
+    let links: Vec<_> = Locale::ALL
+        .iter()
+        .filter(|locale| **locale != current)
+        .map(|locale| {
+            view! { cx,
+                <a href=format!("/locale?lang={}", locale.code()) rel="external" class="underline">
+                    {locale.name()}
+                </a>
+            }
+        })
+        .collect();
+
+    view! { cx,
+        <div class="mt-4 flex justify-center gap-4 text-sm">
+            {links}
+        </div>
+    }
+}
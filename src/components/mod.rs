@@ -1 +1,4 @@
+pub mod changes;
+pub mod locale_switcher;
+pub mod notifier;
 pub mod timetable;
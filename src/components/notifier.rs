@@ -0,0 +1,199 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    rc::Rc,
+};
+
+use leptos::*;
+use time::{Date, Duration, PrimitiveDateTime};
+use wasm_bindgen::{prelude::Closure, JsCast};
+use web_sys::{Notification, NotificationOptions, NotificationPermission};
+
+use crate::{
+    components::changes::describe_change,
+    i18n::Locale,
+    routes::dashboard::{refetch_timetable, DashboardTimetable},
+    sources::german_now,
+    types::{
+        diff::{diff, LessonChange},
+        settings::NotificationSettings,
+        timetable::{Lesson, LessonStatus, Timetable},
+    },
+};
+
+/// How often due reminders are checked
+const TICK_MILLIS: i32 = 30_000;
+/// How often the timetable is fetched again to find substitutions
+const REFETCH_MILLIS: i32 = 5 * 60_000;
+
+/// First period of a block of lessons. Double lessons only have one start.
+#[derive(PartialEq, Clone, Debug)]
+pub struct LessonStart {
+    pub column: usize,
+    pub position: usize,
+    pub start: PrimitiveDateTime,
+    pub lesson: Lesson,
+}
+
+pub fn lesson_starts(timetable: &Timetable, date: Date) -> Vec<LessonStart> {
+    let periods = timetable.times.concat();
+    let mut starts = vec![];
+
+    for (column_index, column) in timetable.columns.iter().enumerate() {
+        if column.date != Some(date) {
+            continue;
+        }
+        for (position, lesson) in column.lessons.iter().enumerate() {
+            let (Some(lesson), Some(period)) = (lesson, periods.get(position)) else {
+                continue;
+            };
+            if position > 0 && column.lessons[position - 1].as_ref() == Some(lesson) {
+                continue;
+            }
+            starts.push(LessonStart {
+                column: column_index,
+                position,
+                start: PrimitiveDateTime::new(date, period.start),
+                lesson: lesson.clone(),
+            });
+        }
+    }
+    starts
+}
+
+fn notify(title: &str, body: &str) {
+    let mut options = NotificationOptions::new();
+    options.body(body);
+    if let Err(e) = Notification::new_with_options(title, &options) {
+        log::error!("Showing a notification failed: {:?}", e);
+    }
+}
+
+struct NotifierState {
+    timetable: Timetable,
+    /// Day, column and position of the lessons that already had their reminder
+    notified: HashSet<(Date, usize, usize)>,
+}
+
+/// Shows browser notifications while the page is open: before every lesson and when a refetch
+/// finds a new cancellation or substitution. Refetched timetables are passed to `on_update`, the
+/// refetches don't count as visits.
+///
+/// Does nothing unless enabled in the settings and permitted by the browser. Renders nothing.
+#[component]
+pub fn lesson_notifier(
+    cx: Scope,
+    timetable: Resource<(), Option<DashboardTimetable>>,
+    settings: Resource<(), NotificationSettings>,
+    locale: Resource<(), Locale>,
+    on_update: WriteSignal<Option<Timetable>>,
+) -> impl IntoView {
+    let started = Rc::new(Cell::new(false));
+
+    // Effects only run in the browser
+    create_effect(cx, move |_| {
+        let (Some(Some(initial)), Some(settings), Some(locale)) =
+            (timetable.read(), settings.read(), locale.read())
+        else {
+            return;
+        };
+        if !settings.browser
+            || started.get()
+            || Notification::permission() != NotificationPermission::Granted
+        {
+            return;
+        }
+        started.set(true);
+
+        let strings = locale.strings();
+        let lead = Duration::minutes(settings.minutes_before as i64);
+        let state = Rc::new(RefCell::new(NotifierState {
+            timetable: initial.timetable,
+            notified: HashSet::new(),
+        }));
+
+        let tick_state = state.clone();
+        let tick = Closure::<dyn Fn()>::new(move || {
+            // The timetable is in school time, not in the time zone of the browser
+            let now = german_now();
+            let mut state = tick_state.borrow_mut();
+
+            for start in lesson_starts(&state.timetable, now.date()) {
+                let key = (now.date(), start.column, start.position);
+                if start.start - lead <= now
+                    && now < start.start
+                    && start.lesson.status != LessonStatus::Cancelled
+                    && state.notified.insert(key)
+                {
+                    let subject = match start.lesson.status {
+                        LessonStatus::Replaced(subject) => subject,
+                        _ => start.lesson.subject,
+                    };
+                    let body = match subject.room.is_empty() {
+                        true => locale.format_time(start.start.time()),
+                        false => format!(
+                            "{} · {} {}",
+                            locale.format_time(start.start.time()),
+                            strings.room,
+                            subject.room
+                        ),
+                    };
+                    notify(&subject.full_name, &body);
+                }
+            }
+        });
+
+        let refetch = Closure::<dyn Fn()>::new(move || {
+            let state = state.clone();
+            spawn_local(async move {
+                let timetable = match refetch_timetable(cx).await {
+                    Ok(Some(timetable)) => timetable,
+                    Ok(None) => return,
+                    Err(e) => {
+                        log::error!("Refetching the timetable failed: {:?}", e);
+                        return;
+                    }
+                };
+
+                let changes = diff(&state.borrow().timetable, &timetable).lessons;
+                for change in changes {
+                    let title = match &change {
+                        LessonChange::Cancelled { lesson, .. } => format!(
+                            "{}: {}",
+                            strings.notification_cancelled, lesson.subject.full_name
+                        ),
+                        LessonChange::Replaced { lesson, .. } => format!(
+                            "{}: {}",
+                            strings.notification_replaced, lesson.subject.full_name
+                        ),
+                        _ => continue,
+                    };
+                    notify(&title, &describe_change(&change, locale));
+                }
+
+                state.borrow_mut().timetable = timetable.clone();
+                on_update(Some(timetable));
+            });
+        });
+
+        let window = window();
+        let handles = [
+            window.set_interval_with_callback_and_timeout_and_arguments_0(
+                tick.as_ref().unchecked_ref(),
+                TICK_MILLIS,
+            ),
+            window.set_interval_with_callback_and_timeout_and_arguments_0(
+                refetch.as_ref().unchecked_ref(),
+                REFETCH_MILLIS,
+            ),
+        ];
+
+        on_cleanup(cx, move || {
+            for handle in handles.into_iter().flatten() {
+                window.clear_interval_with_handle(handle);
+            }
+            // Keeps the callbacks alive as long as the intervals
+            drop((tick, refetch));
+        });
+    });
+}
@@ -1,6 +1,12 @@
 use leptos::*;
 
-use crate::types::timetable::{GroupedTimetableColumn, Lesson, Timetable};
+use crate::{
+    i18n::Locale,
+    types::{
+        overrides::format_color,
+        timetable::{Appointment, GroupedTimetableColumn, Lesson, Timetable},
+    },
+};
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum BlockPosition {
@@ -11,19 +17,36 @@ pub enum BlockPosition {
 }
 
 #[component]
-pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
+pub fn timetable(cx: Scope, state: Timetable, locale: Locale) -> impl IntoView {
     let (timetable, _) = create_signal(cx, state);
 
-    let column_names = move || -> Vec<String> {
+    // One header per day, spanning its parallel columns
+    let day_names = move || -> Vec<(String, usize)> {
         timetable()
-            .columns
-            .iter()
-            .map(|column| column.name.clone())
+            .days()
+            .into_iter()
+            .map(|(column, lanes)| {
+                let name = match column.date {
+                    Some(date) => {
+                        format!("{} {}", locale.weekday(date.weekday()), locale.format_date(date))
+                    }
+                    None => column.name.clone(),
+                };
+                (name, lanes)
+            })
             .collect()
     };
 
     let time_groups = move || timetable().times;
 
+    let week_badge = move || {
+        timetable().week_type.map(|week_type| view! { cx,
+            <div class="px-2 h-10 flex justify-center items-center rounded-lg bg-sky-500 text-white">
+                {locale.week_type(week_type)}
+            </div>
+        })
+    };
+
     let lesson_group_sizes = move || {
         time_groups()
             .iter()
@@ -36,45 +59,76 @@ pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
             .columns
             .into_iter()
             .map(|column| {
-                let mut groups: Vec<Vec<Option<Lesson>>> = vec![];
-
-                let mut pos = 0;
-                for group_size in lesson_group_sizes() {
-                    if pos >= column.lessons.len() {
-                        break;
-                    } else if pos + group_size >= column.lessons.len() {
-                        groups.push(column.lessons[pos..column.lessons.len()].to_vec());
-                        break;
-                    } else {
-                        groups.push(column.lessons[pos..(pos + group_size)].to_vec());
-                        pos += group_size;
-                    }
-                }
+                // Columns have one slot per period, so they split evenly into the groups
+                let mut lessons = column.lessons.into_iter();
+                let groups: Vec<Vec<Option<Lesson>>> = lesson_group_sizes()
+                    .into_iter()
+                    .map(|group_size| lessons.by_ref().take(group_size).collect())
+                    .collect();
 
                 GroupedTimetableColumn {
                     name: column.name,
+                    date: column.date,
                     lessons: groups,
+                    appointments: column.appointments,
+                    holiday: column.holiday,
                 }
             })
             .collect::<Vec<GroupedTimetableColumn>>()
     };
 
+    let appointment_columns = move || -> Vec<(usize, Vec<Appointment>)> {
+        timetable()
+            .columns
+            .into_iter()
+            .map(|column| column.appointments)
+            .enumerate()
+            .collect()
+    };
+
     view! { cx,
         <div class="grid gap-4">
+            // ========== Week type ==========
+            <div class="row-start-1 col-start-1 flex justify-center">
+                {week_badge}
+            </div>
             // ========== Days ==========
             <div class="row-start-1 col-start-2 flex gap-4">
                 <For
-                    each=column_names
+                    each=day_names
                     key=|s| s.clone()
-                    view=move |name: String| view! { cx,
-                        <div class="w-44 h-10 flex justify-center items-center border border-black dark:border-zinc-400 rounded-lg">
+                    view=move |(name, lanes): (String, usize)| view! { cx,
+                        <div style=format!("width: calc({lanes} * 11rem + {} * 1rem);", lanes - 1) class="h-10 flex justify-center items-center border border-black dark:border-zinc-400 rounded-lg">
                             {name}
                         </div>
                     }
                 />
             </div>
             // ========== Appointments ==========
-            <div class="row-start-2 col-start-2 h-8 bg-orange-500">
+            <div class="row-start-2 col-start-2 flex items-start gap-4">
+                <For
+                    each=appointment_columns
+                    key=|c| c.clone()
+                    view=move |(_, appointments): (usize, Vec<Appointment>)| {
+                        let items: Vec<_> = appointments.into_iter().map(|appointment| {
+                            let time = match appointment.start {
+                                Some(start) => format!("{} ", locale.format_time(start)),
+                                None => "".to_owned(),
+                            };
+                            view! { cx,
+                                <div class="px-2 py-1 truncate text-sm rounded-lg bg-orange-500" title=appointment.location>
+                                    {time}{appointment.title}
+                                </div>
+                            }
+                        }).collect();
+
+                        view! { cx,
+                            <div class="w-44 flex flex-col gap-1">
+                                {items}
+                            </div>
+                        }
+                    }
+                />
             </div>
             // ========== Times ==========
             <div class="row-start-3 col-start-1 flex flex-col gap-4">
@@ -83,7 +137,7 @@ pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
                     key=|g| g.clone()
                     view=move |group| {
                         let mut counter = 0;
-                        let items: Vec<_> = group.iter().map(|time| {
+                        let items: Vec<_> = group.iter().map(|period| {
                             let border_style = if group.len() == 1 {
                                 BlockPosition::Alone
                             } else if counter == 0 {
@@ -113,7 +167,7 @@ pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
                             let class = format!("min-w-[6rem] {height} p-3 flex justify-center items-center border border-black dark:border-zinc-400 {border}");
                             view! { cx,
                                 <div class=class>
-                                    {time}
+                                    {format!("{} - {}", locale.format_time(period.start), locale.format_time(period.end))}
                                 </div>
                             }
                         }).collect();
@@ -132,8 +186,11 @@ pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
                     each=grouped_lesson_columns
                     key=|c| c.name.clone()
                     view=move |column| {
+                        // Days without lessons are labelled instead of looking broken, holidays by name
+                        let empty = column.lessons.iter().flatten().all(Option::is_none);
+                        let holiday = column.holiday.clone();
                         let lesson_group_elements: Vec<_> = column.lessons.iter().map(|group| {
-                            let mut batched_lessons: Vec<(Option<&Lesson>, u8)> = vec![];
+                            let mut batched_lessons: Vec<(Option<&Lesson>, usize)> = vec![];
 
                             for lesson in group {
                                 if !batched_lessons.is_empty() && batched_lessons.last().unwrap().0 == lesson.as_ref() {
@@ -163,22 +220,16 @@ pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
                                             BlockPosition::Middle
                                         };
 
-                                        let adjacent_count = (batched_lessons.len() - 1).clamp(0, 2) as u8;
+                                        let adjacent_count = batched_lessons.len() - 1;
 
                                         lesson_elements.push(view! { cx,
                                             <Lesson lesson=lesson.clone() length=batched_lesson.1 border=border adjacent_count=adjacent_count/>
                                         }.into_view(cx));
                                     }
                                     None => {
-                                        let adjacent_count = (batched_lessons.len() - 1).clamp(0, 2) as u8;
-                                        let gap = match adjacent_count {
-                                            0 => "0rem",
-                                            1 => "0.125rem",
-                                            2 => "0.25rem",
-                                            _ => "0rem",
-                                        };
+                                        let gap = block_gap(batched_lessons.len() - 1);
                                         lesson_elements.push(view! { cx,
-                                            <div style=format!("height: calc({} * 3.5rem - {});", batched_lesson.1, gap) class="w-44"/>
+                                            <div style=format!("height: calc({} * 3.5rem - {gap}rem);", batched_lesson.1) class="w-44"/>
                                         }.into_view(cx));
                                     }
                                 };
@@ -192,8 +243,20 @@ pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
                         }).collect();
 
                         view! { cx,
-                            <div class="flex flex-col gap-4">
+                            <div class="relative flex flex-col gap-4">
                                 {lesson_group_elements}
+                                {match holiday {
+                                    Some(name) => Some(view! { cx,
+                                        <div class="absolute inset-0 p-2 flex justify-center items-center text-center border border-black dark:border-zinc-400 rounded-lg">
+                                            {name}
+                                        </div>
+                                    }),
+                                    None => empty.then(|| view! { cx,
+                                        <div class="absolute inset-0 flex justify-center items-center text-sm opacity-60">
+                                            {locale.strings().no_lessons}
+                                        </div>
+                                    }),
+                                }}
                             </div>
                         }
                     }
@@ -203,20 +266,21 @@ pub fn timetable(cx: Scope, state: Timetable) -> impl IntoView {
     }
 }
 
+/// How much shorter each block of a period group gets, so the blocks and the gaps between them add
+/// up to the height of the group. The gaps are 0.25rem, one less than there are blocks.
+fn block_gap(adjacent_count: usize) -> f32 {
+    0.25 * adjacent_count as f32 / (adjacent_count + 1) as f32
+}
+
 #[component]
 fn lesson(
     cx: Scope,
     lesson: Lesson,
-    length: u8,
+    length: usize,
     border: BlockPosition,
-    adjacent_count: u8,
+    adjacent_count: usize,
 ) -> impl IntoView {
-    let gap = match adjacent_count {
-        0 => "0rem",
-        1 => "0.125rem",
-        2 => "0.25rem",
-        _ => "0rem",
-    };
+    let gap = block_gap(adjacent_count);
 
     let border_class = match border {
         BlockPosition::Alone => "rounded-lg",
@@ -226,21 +290,37 @@ fn lesson(
     };
 
     let common_class = format!("w-44 border border-black dark:border-zinc-400 {border_class}");
+    let title = lesson.course.clone().unwrap_or_default();
+
+    // White is what Homeworker uses for subjects without a color, so it is left out
+    let color_style = match lesson.subject.color {
+        (255, 255, 255) => String::new(),
+        color => format!(" border-left: 0.375rem solid {};", format_color(color)),
+    };
+    let style = format!("height: calc({length} * 3.5rem - {gap}rem);{color_style}");
 
-    match length {
-        1 => view! { cx,
-            <div style=format!("height: calc({length} * 3.5rem - {gap});") class=format!("{common_class} grid grid-cols-2 place-items-center")>
+    match (length, lesson.course) {
+        (1, None) => view! { cx,
+            <div style=style.clone() class=format!("{common_class} grid grid-cols-2 place-items-center")>
+                <p>{lesson.subject.short_name}</p>
+                <p>{lesson.subject.room}</p>
+            </div>
+        },
+        (1, Some(course)) => view! { cx,
+            <div style=style.clone() title=title class=format!("{common_class} grid grid-cols-2 grid-rows-[auto_auto] place-items-center")>
                 <p>{lesson.subject.short_name}</p>
                 <p>{lesson.subject.room}</p>
+                <p class="col-span-full text-xs truncate">{course}</p>
             </div>
         },
-        2 => view! { cx,
-            <div style=format!("height: calc({length} * 3.5rem - {gap});") class=format!("{common_class} grid grid-cols-2 grid-rows-2 place-items-center")>
+        // Double lessons and longer ones, e.g. project days, have room for the teacher
+        (_, course) => view! { cx,
+            <div style=style.clone() title=title class=format!("{common_class} grid grid-cols-2 grid-rows-2 place-items-center")>
                 <p>{lesson.subject.short_name}</p>
                 <p>{lesson.subject.room}</p>
                 <p class="col-span-full">{lesson.subject.teacher}</p>
+                {course.map(|course| view! { cx, <p class="col-span-full text-xs truncate">{course}</p> })}
             </div>
         },
-        _ => panic!("Unecpected lesson length: {length}. Lesson lengths must be either 1 or 2"),
     }
 }
@@ -0,0 +1,198 @@
+use base64::Engine;
+
+use crate::{
+    export::layout::{BlockKind, GridLayout},
+    i18n::Locale,
+    types::{overrides::format_color, timetable::Timetable},
+};
+
+const MARGIN: f32 = 24.0;
+const TITLE_HEIGHT: f32 = 40.0;
+/// Relative to `site_root`, like in the stylesheet of the app
+const FONT_DIR: &str = "fonts";
+const FONT_FILE: &str = "rubik-v23-latin-regular.woff2";
+/// The same font for the PNG renderer, which can't read WOFF2
+const TTF_FONT_FILE: &str = "rubik-v23-latin-regular.ttf";
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn parse(theme: &str) -> Option<Theme> {
+        match theme {
+            "light" => Some(Theme::Light),
+            "dark" => Some(Theme::Dark),
+            _ => None,
+        }
+    }
+
+    /// Background, text and border colour, the same as the tailwind classes of the app
+    fn colors(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Theme::Light => ("#ffffff", "#000000", "#000000"),
+            Theme::Dark => ("#18181b", "#ffffff", "#a1a1aa"),
+        }
+    }
+}
+
+/// Size in pixels
+#[derive(Clone, Copy, Debug)]
+pub struct ImageOptions {
+    pub width: u32,
+    pub height: u32,
+    pub theme: Theme,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Svg(usvg::Error),
+    /// The size is zero or too large to allocate
+    Size,
+    /// Message of the PNG encoder
+    Png(String),
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the timetable like the `Timetable` component, with the colour of each subject on the
+/// left edge of its lessons. The Rubik font of `site_root` is embedded, so the SVG looks the same
+/// wherever it is opened.
+pub fn timetable_svg(
+    timetable: &Timetable,
+    locale: Locale,
+    title: &str,
+    options: ImageOptions,
+    site_root: &str,
+) -> String {
+    let (width, height) = (options.width as f32, options.height as f32);
+    let (background, text, border) = options.theme.colors();
+
+    let grid = GridLayout::new(
+        timetable,
+        locale,
+        width - 2.0 * MARGIN,
+        height - 2.0 * MARGIN - TITLE_HEIGHT,
+    );
+    let top = MARGIN + TITLE_HEIGHT;
+    let font_size = (grid.height / 40.0).clamp(8.0, 32.0);
+
+    let font_face = match std::fs::read(format!("{site_root}/{FONT_DIR}/{FONT_FILE}")) {
+        Ok(font) => format!(
+            "@font-face {{ font-family: 'Rubik'; src: url(data:font/woff2;base64,{}) format('woff2'); }}",
+            base64::engine::general_purpose::STANDARD.encode(font)
+        ),
+        Err(e) => {
+            log::warn!("Couldn't embed the font in the SVG export: {e}");
+            String::new()
+        }
+    };
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="Rubik, sans-serif" fill="{text}">"#
+    );
+    svg += &format!("<style>{font_face}</style>");
+    svg += &format!(r#"<rect width="100%" height="100%" fill="{background}"/>"#);
+    svg += &format!(
+        r#"<text x="{MARGIN}" y="{}" font-size="{}">{}</text>"#,
+        MARGIN + TITLE_HEIGHT * 0.6,
+        TITLE_HEIGHT * 0.6,
+        escape(title)
+    );
+
+    let radius = font_size * 0.5;
+    for block in &grid.blocks {
+        let (x, y) = (MARGIN + block.x, top + block.y);
+        let (w, h) = (block.width, block.height);
+
+        match block.kind {
+            BlockKind::Header => {
+                svg += &format!(
+                    r##"<rect x="{x}" y="{y}" width="{w}" height="{h}" rx="{radius}" fill="#0ea5e9"/>"##
+                )
+            }
+            BlockKind::Time => {}
+            BlockKind::Lesson { color, cancelled } => {
+                // Same as in the app, cancelled lessons are dashed and white means no colour
+                let dash = match cancelled {
+                    true => r#" stroke-dasharray="6 4""#,
+                    false => "",
+                };
+                svg += &format!(
+                    r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" rx="{radius}" fill="none" stroke="{border}"{dash}/>"#
+                );
+                if color != (255, 255, 255) {
+                    svg += &format!(
+                        r#"<rect x="{x}" y="{y}" width="{}" height="{h}" rx="{}" fill="{}"/>"#,
+                        font_size * 0.4,
+                        font_size * 0.2,
+                        format_color(color)
+                    );
+                }
+            }
+        }
+
+        let size = match block.kind {
+            BlockKind::Time => font_size * 0.75,
+            _ => font_size,
+        };
+        let line_height = size * 1.25;
+        let text_top = y + (h - line_height * block.lines.len() as f32) / 2.0;
+        let fill = match block.kind {
+            BlockKind::Header => "#ffffff",
+            _ => text,
+        };
+
+        for (i, line) in block.lines.iter().enumerate() {
+            svg += &format!(
+                r#"<text x="{}" y="{}" font-size="{}" fill="{fill}" text-anchor="middle">{}</text>"#,
+                x + w / 2.0,
+                text_top + line_height * (i as f32 + 0.8),
+                match i {
+                    0 => size,
+                    _ => size * 0.8,
+                },
+                escape(line)
+            );
+        }
+    }
+
+    svg += "</svg>";
+    svg
+}
+
+/// Rasterises an SVG from `timetable_svg`. Rubik is read from the TrueType copy in `site_root`,
+/// as the renderer can't read the WOFF2 file embedded in the SVG. System fonts are the fallback.
+pub fn timetable_png(
+    svg: &str,
+    options: ImageOptions,
+    site_root: &str,
+) -> Result<Vec<u8>, ImageError> {
+    use usvg::{TreeParsing, TreeTextToPath};
+
+    let mut fonts = usvg::fontdb::Database::new();
+    let font_path = format!("{site_root}/{FONT_DIR}/{TTF_FONT_FILE}");
+    if let Err(e) = fonts.load_font_file(&font_path) {
+        log::warn!("Couldn't load {font_path} for the PNG export: {e}");
+    }
+    fonts.load_system_fonts();
+
+    let mut tree = usvg::Tree::from_str(svg, &usvg::Options::default()).map_err(ImageError::Svg)?;
+    tree.convert_text(&fonts);
+
+    let mut pixmap =
+        tiny_skia::Pixmap::new(options.width, options.height).ok_or(ImageError::Size)?;
+    resvg::render(&tree, usvg::FitTo::Original, tiny_skia::Transform::default(), pixmap.as_mut())
+        .ok_or(ImageError::Size)?;
+
+    pixmap
+        .encode_png()
+        .map_err(|e| ImageError::Png(e.to_string()))
+}
@@ -0,0 +1,159 @@
+use crate::{
+    i18n::Locale,
+    types::timetable::{Lesson, LessonStatus, Timetable},
+};
+
+/// Lays out the timetable grid for the non-browser exports. It follows the `Timetable` component:
+/// day headers on top, period times on the left, period groups separated by a gap and consecutive
+/// equal lessons merged into one block.
+pub struct GridLayout {
+    pub width: f32,
+    pub height: f32,
+    pub blocks: Vec<Block>,
+}
+
+pub struct Block {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub kind: BlockKind,
+    pub lines: Vec<String>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum BlockKind {
+    Header,
+    Time,
+    Lesson {
+        color: (u8, u8, u8),
+        cancelled: bool,
+    },
+}
+
+impl GridLayout {
+    /// Coordinates start in the top left corner and use the unit of `width` and `height`
+    pub fn new(timetable: &Timetable, locale: Locale, width: f32, height: f32) -> GridLayout {
+        let mut blocks = vec![];
+
+        let gap = width.min(height) * 0.015;
+        let header_height = height * 0.07;
+        let time_width = width * 0.12;
+
+        let rows: usize = timetable.times.iter().map(Vec::len).sum();
+        let group_count = timetable.times.len();
+        let column_count = timetable.columns.len().max(1);
+
+        let row_height = (height - header_height - gap * group_count as f32) / rows.max(1) as f32;
+        let column_width = (width - time_width - gap * column_count as f32) / column_count as f32;
+
+        // Vertical offset of every period, including the gaps between groups
+        let mut row_offsets = vec![];
+        let mut y = header_height + gap;
+        for group in &timetable.times {
+            for period in group {
+                blocks.push(Block {
+                    x: 0.0,
+                    y,
+                    width: time_width,
+                    height: row_height,
+                    kind: BlockKind::Time,
+                    lines: vec![format!(
+                        "{} - {}",
+                        locale.format_time(period.start),
+                        locale.format_time(period.end)
+                    )],
+                });
+                row_offsets.push(y);
+                y += row_height;
+            }
+            y += gap;
+        }
+
+        // Lessons don't get merged across the gaps between groups
+        let group_starts: Vec<usize> = timetable
+            .times
+            .iter()
+            .scan(0, |start, group| {
+                let current = *start;
+                *start += group.len();
+                Some(current)
+            })
+            .collect();
+
+        // One header per day, spanning its parallel columns
+        let mut column_index = 0;
+        for (column, lanes) in timetable.days() {
+            blocks.push(Block {
+                x: time_width + gap + column_index as f32 * (column_width + gap),
+                y: 0.0,
+                width: lanes as f32 * (column_width + gap) - gap,
+                height: header_height,
+                kind: BlockKind::Header,
+                lines: vec![match column.date {
+                    Some(date) => {
+                        format!("{} {}", locale.weekday(date.weekday()), locale.format_date(date))
+                    }
+                    None => column.name.clone(),
+                }],
+            });
+            column_index += lanes;
+        }
+
+        for (column_index, column) in timetable.columns.iter().enumerate() {
+            let x = time_width + gap + column_index as f32 * (column_width + gap);
+
+            let mut position = 0;
+            while position < column.lessons.len().min(rows) {
+                let Some(lesson) = &column.lessons[position] else {
+                    position += 1;
+                    continue;
+                };
+                let length = column.lessons[position..rows.min(column.lessons.len())]
+                    .iter()
+                    .enumerate()
+                    .take_while(|(i, other)| {
+                        other.as_ref() == Some(lesson)
+                            && (*i == 0 || !group_starts.contains(&(position + i)))
+                    })
+                    .count();
+
+                blocks.push(Block {
+                    x,
+                    y: row_offsets[position],
+                    width: column_width,
+                    height: row_height * length as f32,
+                    kind: BlockKind::Lesson {
+                        color: lesson.subject.color,
+                        cancelled: lesson.status == LessonStatus::Cancelled,
+                    },
+                    lines: lesson_lines(lesson, length),
+                });
+
+                position += length;
+            }
+        }
+
+        GridLayout {
+            width,
+            height,
+            blocks,
+        }
+    }
+}
+
+fn lesson_lines(lesson: &Lesson, length: usize) -> Vec<String> {
+    let subject = match &lesson.status {
+        LessonStatus::Replaced(subject) => subject,
+        _ => &lesson.subject,
+    };
+
+    let mut lines = vec![format!("{}  {}", subject.short_name, subject.room)];
+    if length > 1 {
+        lines.push(subject.teacher.clone());
+    }
+    if let Some(course) = &lesson.course {
+        lines.push(course.clone());
+    }
+    lines
+}
@@ -0,0 +1,5 @@
+#[cfg(feature = "ssr")]
+pub mod image;
+pub mod layout;
+#[cfg(feature = "ssr")]
+pub mod pdf;
@@ -0,0 +1,112 @@
+use printpdf::*;
+
+use crate::{
+    export::layout::{BlockKind, GridLayout},
+    i18n::Locale,
+    types::timetable::Timetable,
+};
+
+const PAGE_WIDTH: f32 = 297.0;
+const PAGE_HEIGHT: f32 = 210.0;
+const MARGIN: f32 = 10.0;
+const TITLE_HEIGHT: f32 = 12.0;
+
+/// Renders the timetable as a black and white A4 landscape page, like the print route
+pub fn timetable_pdf(
+    timetable: &Timetable,
+    locale: Locale,
+    title: &str,
+) -> Result<Vec<u8>, printpdf::Error> {
+    let (document, page, layer) =
+        PdfDocument::new(title, Mm(PAGE_WIDTH as f64), Mm(PAGE_HEIGHT as f64), "Timetable");
+    let layer = document.get_page(page).get_layer(layer);
+    let font = document.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold_font = document.add_builtin_font(BuiltinFont::HelveticaBold)?;
+
+    // PDF coordinates start at the bottom left
+    let to_pdf = |x: f32, y: f32| Point::new(Mm((MARGIN + x) as f64), Mm((PAGE_HEIGHT - y) as f64));
+
+    layer.use_text(
+        title,
+        14.0,
+        Mm(MARGIN as f64),
+        Mm((PAGE_HEIGHT - MARGIN - 6.0) as f64),
+        &bold_font,
+    );
+
+    let grid = GridLayout::new(
+        timetable,
+        locale,
+        PAGE_WIDTH - 2.0 * MARGIN,
+        PAGE_HEIGHT - 2.0 * MARGIN - TITLE_HEIGHT,
+    );
+    let top = MARGIN + TITLE_HEIGHT;
+
+    layer.set_outline_color(Color::Greyscale(Greyscale::new(0.0, None)));
+    layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+
+    for block in &grid.blocks {
+        let (x, y) = (block.x, top + block.y);
+        let cancelled = matches!(
+            block.kind,
+            BlockKind::Lesson {
+                cancelled: true,
+                ..
+            }
+        );
+
+        // Cancelled lessons are dashed, so they stay recognisable without colour
+        layer.set_outline_thickness(if block.kind == BlockKind::Header {
+            1.0
+        } else {
+            0.5
+        });
+        layer.set_line_dash_pattern(match cancelled {
+            true => LineDashPattern {
+                dash_1: Some(3),
+                ..Default::default()
+            },
+            false => LineDashPattern::default(),
+        });
+        layer.add_shape(Line {
+            points: vec![
+                (to_pdf(x, y), false),
+                (to_pdf(x + block.width, y), false),
+                (to_pdf(x + block.width, y + block.height), false),
+                (to_pdf(x, y + block.height), false),
+            ],
+            is_closed: true,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+
+        let font_size = match block.kind {
+            BlockKind::Header => 11.0,
+            _ => 9.0,
+        };
+        let line_height = font_size as f32 * 0.45;
+        let text_top = y + (block.height - line_height * block.lines.len() as f32) / 2.0;
+
+        for (i, line) in block.lines.iter().enumerate() {
+            // Builtin fonts have no metrics available here, so the width is estimated
+            let text_width = line.chars().count() as f32 * font_size as f32 * 0.18;
+            let text_x = x + ((block.width - text_width) / 2.0).max(1.0);
+            let text_y = text_top + line_height * (i + 1) as f32 - 0.8;
+            let font = match (block.kind, i) {
+                (BlockKind::Header, _) | (BlockKind::Lesson { .. }, 0) => &bold_font,
+                _ => &font,
+            };
+
+            layer.use_text(
+                line.as_str(),
+                font_size,
+                Mm((MARGIN + text_x) as f64),
+                Mm((PAGE_HEIGHT - text_y) as f64),
+                font,
+            );
+        }
+    }
+
+    document.save_to_bytes()
+}
@@ -0,0 +1,113 @@
+//! Checks behind `/readyz`, so deployments only get traffic once they can serve the app.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use leptos::LeptosOptions;
+use serde::Serialize;
+
+use crate::sources::SourceConfig;
+
+#[derive(PartialEq, Clone, Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn from_result(result: Result<(), String>) -> Check {
+        match result {
+            Ok(()) => Check {
+                ok: true,
+                error: None,
+            },
+            Err(error) => Check {
+                ok: false,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, Check>,
+}
+
+/// How long the result of [`check_config`] is reused, so frequent probes don't parse the files
+const CONFIG_CHECK_TTL: Duration = Duration::from_secs(60);
+
+static CONFIG_CHECK: Mutex<Option<(Instant, Check)>> = Mutex::new(None);
+
+/// The manual timetables can be edited while the server runs, so they are read again once the
+/// last result is older than [`CONFIG_CHECK_TTL`]
+pub async fn check_config(configs: &[SourceConfig]) -> Check {
+    if let Some((checked, check)) = CONFIG_CHECK.lock().unwrap().as_ref() {
+        if checked.elapsed() < CONFIG_CHECK_TTL {
+            return check.clone();
+        }
+    }
+    let check = validate_config(configs).await;
+    *CONFIG_CHECK.lock().unwrap() = Some((Instant::now(), check.clone()));
+    check
+}
+
+async fn validate_config(configs: &[SourceConfig]) -> Check {
+    for variable in ["HW_CLIENT_ID", "HW_SECRET"] {
+        if std::env::var(variable).is_err() {
+            return Check::from_result(Err(format!("{variable} is not set")));
+        }
+    }
+    for config in configs {
+        if let Err(e) = config.validate().await {
+            return Check::from_result(Err(e));
+        }
+    }
+    Check::from_result(Ok(()))
+}
+
+/// Without the hydration script and the wasm in `pkg`, pages render but stay static
+pub fn check_assets(options: &LeptosOptions) -> Check {
+    let script = Path::new(&options.site_root)
+        .join(&options.site_pkg_dir)
+        .join(format!("{}.js", options.output_name));
+    Check::from_result(match script.is_file() {
+        true => Ok(()),
+        false => Err(format!("{} doesn't exist", script.display())),
+    })
+}
+
+/// Any response counts, only network errors and server errors of Homeworker make it fail
+pub async fn check_homeworker(url: &str) -> Check {
+    let client = reqwest::Client::new();
+    let response = client.get(url).timeout(Duration::from_secs(5)).send().await;
+    Check::from_result(match response {
+        Ok(response) if response.status().is_server_error() => {
+            Err(format!("Homeworker responded with {}", response.status()))
+        }
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    })
+}
+
+/// Homeworker is only probed with `HEALTH_PROBE_URL` set, e.g. to its API, as an outage of
+/// Homeworker would otherwise take all instances out of rotation at once.
+pub async fn readiness(configs: &[SourceConfig], options: &LeptosOptions) -> Readiness {
+    let mut checks = BTreeMap::new();
+    checks.insert("config", check_config(configs).await);
+    checks.insert("assets", check_assets(options));
+    if let Ok(url) = std::env::var("HEALTH_PROBE_URL") {
+        checks.insert("homeworker", check_homeworker(&url).await);
+    }
+
+    Readiness {
+        ready: checks.values().all(|check| check.ok),
+        checks,
+    }
+}
@@ -0,0 +1,408 @@
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use time::{Date, Time, Weekday};
+
+use crate::types::timetable::WeekType;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    German,
+    English,
+}
+
+#[derive(Clone, Copy)]
+pub struct LocaleContext {
+    pub locale: ReadSignal<Locale>,
+}
+
+/// All UI strings of one language. Every locale has to provide every string.
+pub struct Strings {
+    pub home_title: &'static str,
+    pub home_dashboard_link: &'static str,
+    pub login_title: &'static str,
+    pub login_description: &'static str,
+    pub login_button: &'static str,
+    pub login_redirect: &'static str,
+    pub timetable_title: &'static str,
+    pub print_button: &'static str,
+    pub api_token_title: &'static str,
+    pub api_token_description: &'static str,
+    pub api_token_create: &'static str,
+    pub api_token_created: &'static str,
+    pub api_token_list: &'static str,
+    pub api_token_revoke: &'static str,
+    pub calendar_upload: &'static str,
+    pub calendar_upload_error: &'static str,
+    pub line: &'static str,
+    pub subjects_title: &'static str,
+    pub subject_full_name: &'static str,
+    pub subject_short_name: &'static str,
+    pub subject_color: &'static str,
+    pub subject_hidden: &'static str,
+    pub save: &'static str,
+    pub settings_title: &'static str,
+    pub settings_default_course: &'static str,
+    pub settings_first_course: &'static str,
+    pub settings_saved: &'static str,
+    pub settings_notifications: &'static str,
+    pub settings_minutes_before: &'static str,
+    pub room: &'static str,
+    pub notification_cancelled: &'static str,
+    pub notification_replaced: &'static str,
+    pub period: &'static str,
+    pub change_added: &'static str,
+    pub change_removed: &'static str,
+    pub change_restored: &'static str,
+    pub changes_title: &'static str,
+    pub alert_title: &'static str,
+    pub settings_change_alerts: &'static str,
+    pub settings_webhook: &'static str,
+    pub settings_email: &'static str,
+    pub settings_email_confirm_sent: &'static str,
+    pub settings_save_failed: &'static str,
+    pub settings_webhook_invalid: &'static str,
+    pub settings_email_failed: &'static str,
+    pub email_confirm_subject: &'static str,
+    pub email_confirm_body: &'static str,
+    pub settings_push: &'static str,
+    pub settings_push_enabled: &'static str,
+    pub widget_now: &'static str,
+    pub widget_next: &'static str,
+    pub widget_break_in: &'static str,
+    pub widget_later: &'static str,
+    pub widget_done: &'static str,
+    pub display_key_label: &'static str,
+    pub display_key_button: &'static str,
+    pub free_title: &'static str,
+    pub free_courses: &'static str,
+    pub free_heatmap: &'static str,
+    pub free_rooms: &'static str,
+    pub free_rooms_hint: &'static str,
+    pub week_previous: &'static str,
+    pub week_next: &'static str,
+    pub settings_weekdays: &'static str,
+    pub no_lessons: &'static str,
+    pub week_a: &'static str,
+    pub week_b: &'static str,
+    pub subject_weeks: &'static str,
+    pub subject_every_week: &'static str,
+}
+
+const GERMAN: Strings = Strings {
+    home_title: "Total tolle homepage",
+    home_dashboard_link: "Zum Dashboard",
+    login_title: "Anmelden mit Homeworker",
+    login_description: "Um den Stundenplan abzurufen, musst du dich mit Homeworker anmelden",
+    login_button: "Anmelden",
+    login_redirect: "Weiterleiten",
+    timetable_title: "Stundenplan",
+    print_button: "Drucken",
+    api_token_title: "API-Token",
+    api_token_description: "Mit einem persönlichen Token können andere Programme deinen Stundenplan über /api/v1/timetable abrufen",
+    api_token_create: "Neues Token erstellen",
+    api_token_created: "Dein neues Token. Es wird nur einmal angezeigt:",
+    api_token_list: "Deine Tokens, nach Erstellungsdatum",
+    api_token_revoke: "Widerrufen",
+    calendar_upload: "Kalender hinzufügen (.ics)",
+    calendar_upload_error: "Der Kalender konnte nicht gelesen werden",
+    line: "Zeile",
+    subjects_title: "Fächer anpassen",
+    subject_full_name: "Name",
+    subject_short_name: "Kürzel",
+    subject_color: "Farbe",
+    subject_hidden: "Ausblenden",
+    save: "Speichern",
+    settings_title: "Einstellungen",
+    settings_default_course: "Standardkurs",
+    settings_first_course: "Erster Kurs",
+    settings_saved: "Gespeichert",
+    settings_notifications: "Benachrichtigungen im Browser",
+    settings_minutes_before: "Minuten vor der Stunde",
+    room: "Raum",
+    notification_cancelled: "Entfällt",
+    notification_replaced: "Vertretung",
+    period: "Stunde",
+    change_added: "neu",
+    change_removed: "gestrichen",
+    change_restored: "findet wieder statt",
+    changes_title: "Änderungen seit deinem letzten Besuch",
+    alert_title: "Änderungen im Stundenplan",
+    settings_change_alerts: "Bei Änderungen benachrichtigen",
+    settings_webhook: "Webhook (Discord, Matrix)",
+    settings_email: "E-Mail",
+    settings_email_confirm_sent: "Gespeichert. Bitte bestätige deine E-Mail-Adresse mit dem Link, den wir dir geschickt haben",
+    settings_save_failed: "Nicht gespeichert, bitte versuche es später noch einmal",
+    settings_webhook_invalid: "Nicht gespeichert. Webhooks brauchen eine öffentliche https-Adresse",
+    settings_email_failed: "Nicht gespeichert. Die Bestätigungs-E-Mail konnte nicht gesendet werden",
+    email_confirm_subject: "E-Mail-Adresse bestätigen",
+    email_confirm_body: "Öffne diesen Link, um Änderungen im Stundenplan an diese Adresse zu bekommen. Wenn du das nicht warst, ignoriere diese E-Mail einfach.",
+    settings_push: "Push in diesem Browser aktivieren",
+    settings_push_enabled: "Push ist aktiviert",
+    widget_now: "Jetzt",
+    widget_next: "Als Nächstes",
+    widget_break_in: "Pause in",
+    widget_later: "Heute noch",
+    widget_done: "Heute keine Stunden mehr",
+    display_key_label: "Anzeigeschlüssel",
+    display_key_button: "Speichern",
+    free_title: "Freistunden und Räume",
+    free_courses: "Kurse",
+    free_heatmap: "Wer hat frei?",
+    free_rooms: "Freie Räume",
+    free_rooms_hint: "Nur Räume, die in den gewählten Kursen diese Woche vorkommen",
+    week_previous: "Vorherige Woche",
+    week_next: "Nächste Woche",
+    settings_weekdays: "Tage im Stundenplan",
+    no_lessons: "Kein Unterricht",
+    week_a: "A-Woche",
+    week_b: "B-Woche",
+    subject_weeks: "Wochen",
+    subject_every_week: "Jede",
+};
+
+const ENGLISH: Strings = Strings {
+    home_title: "Totally great homepage",
+    home_dashboard_link: "Go to dashboard",
+    login_title: "Sign in with Homeworker",
+    login_description: "To load your timetable, you have to sign in with Homeworker",
+    login_button: "Sign in",
+    login_redirect: "Redirect",
+    timetable_title: "Timetable",
+    print_button: "Print",
+    api_token_title: "API token",
+    api_token_description:
+        "With a personal token, other programs can load your timetable from /api/v1/timetable",
+    api_token_create: "Create new token",
+    api_token_created: "Your new token. It is only shown once:",
+    api_token_list: "Your tokens, by creation date",
+    api_token_revoke: "Revoke",
+    calendar_upload: "Add calendar (.ics)",
+    calendar_upload_error: "The calendar could not be read",
+    line: "line",
+    subjects_title: "Customise subjects",
+    subject_full_name: "Name",
+    subject_short_name: "Short name",
+    subject_color: "Colour",
+    subject_hidden: "Hide",
+    save: "Save",
+    settings_title: "Settings",
+    settings_default_course: "Default course",
+    settings_first_course: "First course",
+    settings_saved: "Saved",
+    settings_notifications: "Browser notifications",
+    settings_minutes_before: "Minutes before the lesson",
+    room: "Room",
+    notification_cancelled: "Cancelled",
+    notification_replaced: "Substitution",
+    period: "Period",
+    change_added: "new",
+    change_removed: "removed",
+    change_restored: "takes place again",
+    changes_title: "Changes since your last visit",
+    alert_title: "Timetable changes",
+    settings_change_alerts: "Alert me about changes",
+    settings_webhook: "Webhook (Discord, Matrix)",
+    settings_email: "Email",
+    settings_email_confirm_sent: "Saved. Please confirm your email address with the link we sent you",
+    settings_save_failed: "Not saved, please try again later",
+    settings_webhook_invalid: "Not saved. Webhooks need a public https address",
+    settings_email_failed: "Not saved. The confirmation email couldn't be sent",
+    email_confirm_subject: "Confirm your email address",
+    email_confirm_body: "Open this link to receive timetable changes at this address. If this wasn't you, just ignore this email.",
+    settings_push: "Enable push in this browser",
+    settings_push_enabled: "Push is enabled",
+    widget_now: "Now",
+    widget_next: "Next",
+    widget_break_in: "Break in",
+    widget_later: "Later today",
+    widget_done: "No more lessons today",
+    display_key_label: "Display key",
+    display_key_button: "Save",
+    free_title: "Free periods and rooms",
+    free_courses: "Courses",
+    free_heatmap: "Who is free?",
+    free_rooms: "Free rooms",
+    free_rooms_hint: "Only rooms used by the selected courses this week",
+    week_previous: "Previous week",
+    week_next: "Next week",
+    settings_weekdays: "Days in the timetable",
+    no_lessons: "No lessons",
+    week_a: "Week A",
+    week_b: "Week B",
+    subject_weeks: "Weeks",
+    subject_every_week: "Every",
+};
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::German, Locale::English];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::German => "de",
+            Locale::English => "en",
+        }
+    }
+
+    /// Display name of the locale in its own language
+    pub fn name(self) -> &'static str {
+        match self {
+            Locale::German => "Deutsch",
+            Locale::English => "English",
+        }
+    }
+
+    /// Parses a language tag like `de`, `en-GB` or `EN_us`. Only the primary subtag is considered.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let primary = code.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        match primary.as_str() {
+            "de" => Some(Locale::German),
+            "en" => Some(Locale::English),
+            _ => None,
+        }
+    }
+
+    /// Picks the supported locale with the highest quality value from an `Accept-Language` header
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut candidates: Vec<(Locale, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let locale = Locale::from_code(parts.next()?)?;
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map(|q| q.parse::<f32>().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                Some((locale, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+
+        // Stable sort, so the header order decides between equal quality values
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.first().map(|(locale, _)| *locale)
+    }
+
+    /// Badge for A/B weeks, e.g. "A-Woche"
+    pub fn week_type(self, week_type: WeekType) -> &'static str {
+        match week_type {
+            WeekType::A => self.strings().week_a,
+            WeekType::B => self.strings().week_b,
+        }
+    }
+
+    pub fn strings(self) -> &'static Strings {
+        match self {
+            Locale::German => &GERMAN,
+            Locale::English => &ENGLISH,
+        }
+    }
+
+    pub fn weekday(self, weekday: Weekday) -> &'static str {
+        let names = match self {
+            Locale::German => [
+                "Montag",
+                "Dienstag",
+                "Mittwoch",
+                "Donnerstag",
+                "Freitag",
+                "Samstag",
+                "Sonntag",
+            ],
+            Locale::English => [
+                "Monday",
+                "Tuesday",
+                "Wednesday",
+                "Thursday",
+                "Friday",
+                "Saturday",
+                "Sunday",
+            ],
+        };
+        names[weekday.number_days_from_monday() as usize]
+    }
+
+    /// Short date without the year, as used in column headers
+    pub fn format_date(self, date: Date) -> String {
+        match self {
+            Locale::German => format!("{}.{}.", date.day(), date.month() as u8),
+            Locale::English => format!("{}/{}", date.month() as u8, date.day()),
+        }
+    }
+
+    /// Title used by the print view and the exports, e.g. "Stundenplan 10a, 20.2. - 24.2."
+    pub fn timetable_title(self, course: &str, range: Option<(Date, Date)>) -> String {
+        match range {
+            Some((from, to)) => format!(
+                "{} {}, {} - {}",
+                self.strings().timetable_title,
+                course,
+                self.format_date(from),
+                self.format_date(to)
+            ),
+            None => format!("{} {}", self.strings().timetable_title, course),
+        }
+    }
+
+    pub fn format_time(self, time: Time) -> String {
+        match self {
+            Locale::German => format!("{}:{:02}", time.hour(), time.minute()),
+            Locale::English => {
+                let hour = match time.hour() % 12 {
+                    0 => 12,
+                    hour => hour,
+                };
+                let suffix = if time.hour() < 12 { "AM" } else { "PM" };
+                format!("{}:{:02} {}", hour, time.minute(), suffix)
+            }
+        }
+    }
+}
+
+/// Makes the locale chosen on the server available during hydration. Works the same way as the
+/// other resources that read from server-provided contexts.
+pub fn use_locale(cx: Scope) -> Resource<(), Locale> {
+    // Default used when no context is provided during hydration
+    let locale_context = use_context::<LocaleContext>(cx).unwrap_or(LocaleContext {
+        locale: create_signal(cx, Locale::default()).0,
+    });
+
+    create_resource(cx, || (), async move |_| (locale_context.locale)())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_quality_wins() {
+        assert_eq!(Locale::from_accept_language("en-US,en;q=0.9,de;q=0.8"), Some(Locale::English));
+        assert_eq!(Locale::from_accept_language("en;q=0.5, de;q=0.7"), Some(Locale::German));
+    }
+
+    #[test]
+    fn region_subtags_are_ignored() {
+        assert_eq!(Locale::from_accept_language("de-AT"), Some(Locale::German));
+        assert_eq!(Locale::from_accept_language("fr-FR, en_GB;q=0.3"), Some(Locale::English));
+    }
+
+    #[test]
+    fn equal_quality_keeps_header_order() {
+        assert_eq!(Locale::from_accept_language("de, en"), Some(Locale::German));
+        assert_eq!(Locale::from_accept_language("en, de"), Some(Locale::English));
+    }
+
+    #[test]
+    fn unsupported_or_empty_headers_have_no_locale() {
+        assert_eq!(Locale::from_accept_language("*"), None);
+        assert_eq!(Locale::from_accept_language(""), None);
+        assert_eq!(Locale::from_accept_language("fr, es;q=0.5"), None);
+    }
+
+    #[test]
+    fn zero_or_malformed_quality_is_skipped() {
+        assert_eq!(Locale::from_accept_language("de;q=abc, en;q=0.1"), Some(Locale::English));
+        assert_eq!(Locale::from_accept_language("de;q=0, en;q=0.1"), Some(Locale::English));
+        assert_eq!(Locale::from_accept_language("de;q=oops"), None);
+    }
+}
@@ -6,9 +6,27 @@ use wasm_bindgen::prelude::*;
 
 use crate::app::*;
 
+#[cfg(feature = "ssr")]
+pub mod alerts;
+pub mod api;
 pub mod app;
 pub mod components;
+pub mod export;
+#[cfg(feature = "ssr")]
+pub mod health;
+pub mod i18n;
+#[cfg(feature = "ssr")]
+pub mod listener;
 pub mod routes;
+#[cfg(feature = "ssr")]
+pub mod security;
+pub mod sources;
+#[cfg(feature = "ssr")]
+pub mod store;
+#[cfg(feature = "ssr")]
+pub mod telemetry;
+#[cfg(test)]
+mod test_util;
 pub mod types;
 
 #[wasm_bindgen]
@@ -0,0 +1,211 @@
+//! Where the server listens and how it stops. On SIGTERM or Ctrl+C, no new connections are
+//! accepted and open requests, including streamed SSR responses, get `SHUTDOWN_TIMEOUT_SECONDS`
+//! (30 by default) to finish.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::unix::net::UnixStream as StdUnixStream,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use hyper::server::accept::Accept;
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{info, warn};
+
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(SocketAddr),
+    /// PEM files of the certificate chain and the private key
+    Tls {
+        address: SocketAddr,
+        cert: PathBuf,
+        key: PathBuf,
+    },
+    /// For a reverse proxy on the same host
+    Unix(PathBuf),
+}
+
+impl Listener {
+    /// `UNIX_SOCKET` takes precedence over `TLS_CERT` and `TLS_KEY`. Without any of them, plain TCP
+    /// is used on `site_address`.
+    pub fn from_env(site_address: SocketAddr) -> Listener {
+        if let Ok(path) = std::env::var("UNIX_SOCKET") {
+            return Listener::Unix(path.into());
+        }
+        match (std::env::var("TLS_CERT"), std::env::var("TLS_KEY")) {
+            (Ok(cert), Ok(key)) => Listener::Tls {
+                address: site_address,
+                cert: cert.into(),
+                key: key.into(),
+            },
+            (Err(_), Err(_)) => Listener::Tcp(site_address),
+            _ => panic!("TLS_CERT and TLS_KEY have to be set together"),
+        }
+    }
+
+    /// Plain TCP on a loopback address, so requests come from the same machine without TLS
+    pub fn is_local(&self) -> bool {
+        matches!(self, Listener::Tcp(address) if address.ip().is_loopback())
+    }
+
+    /// Requests `/healthz` the way clients reach this server, for `--healthcheck` in containers
+    pub async fn healthcheck(&self) -> Result<(), String> {
+        let timeout = Duration::from_secs(5);
+        match self {
+            Listener::Tcp(address) | Listener::Tls { address, .. } => {
+                let (scheme, host) = match self {
+                    Listener::Tls { .. } => ("https", "localhost".to_owned()),
+                    _ => ("http", local_address(*address).ip().to_string()),
+                };
+                let host = match host.contains(':') {
+                    true => format!("[{host}]"),
+                    false => host,
+                };
+                // The certificate is for the public name, not for localhost
+                let client = reqwest::Client::builder()
+                    .danger_accept_invalid_certs(true)
+                    .resolve("localhost", local_address(*address))
+                    .timeout(timeout)
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                let url = format!("{scheme}://{host}:{}/healthz", address.port());
+                let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+                match response.status().is_success() {
+                    true => Ok(()),
+                    false => Err(format!("/healthz responded with {}", response.status())),
+                }
+            }
+            Listener::Unix(path) => {
+                let mut stream = StdUnixStream::connect(path).map_err(|e| e.to_string())?;
+                stream
+                    .set_read_timeout(Some(timeout))
+                    .map_err(|e| e.to_string())?;
+                stream
+                    .write_all(
+                        b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                    )
+                    .map_err(|e| e.to_string())?;
+                let mut response = String::new();
+                stream
+                    .read_to_string(&mut response)
+                    .map_err(|e| e.to_string())?;
+                let status = response.lines().next().unwrap_or_default();
+                match status.split(' ').nth(1) {
+                    Some("200") => Ok(()),
+                    _ => Err(format!("/healthz responded with {status:?}")),
+                }
+            }
+        }
+    }
+
+    /// Returns once the server has shut down
+    pub async fn serve(self, router: Router) -> std::io::Result<()> {
+        let timeout = shutdown_timeout();
+        match self {
+            Listener::Tcp(address) => {
+                axum_server::bind(address)
+                    .handle(graceful_handle(timeout))
+                    .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+            }
+            Listener::Tls { address, cert, key } => {
+                let config = RustlsConfig::from_pem_file(cert, key).await?;
+                axum_server::bind_rustls(address, config)
+                    .handle(graceful_handle(timeout))
+                    .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+            }
+            Listener::Unix(path) => {
+                // Left over if the server didn't stop cleanly
+                match tokio::fs::remove_file(&path).await {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+                let server = axum::Server::builder(UnixAccept(UnixListener::bind(&path)?))
+                    .serve(router.into_make_service())
+                    .with_graceful_shutdown(async {
+                        shutdown_signal().await;
+                        info!("Shutting down, waiting for open requests");
+                    });
+
+                // Hyper waits for open connections without a limit
+                tokio::select! {
+                    result = server => result.map_err(|e| std::io::Error::new(ErrorKind::Other, e)),
+                    _ = async {
+                        shutdown_signal().await;
+                        tokio::time::sleep(timeout).await;
+                    } => {
+                        warn!("Open requests didn't finish within {timeout:?}");
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Servers on all interfaces are reached through loopback
+fn local_address(address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        ip if ip.is_unspecified() && ip.is_ipv4() => (Ipv4Addr::LOCALHOST, address.port()).into(),
+        ip if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, address.port()).into(),
+        _ => address,
+    }
+}
+
+fn shutdown_timeout() -> Duration {
+    let seconds = std::env::var("SHUTDOWN_TIMEOUT_SECONDS")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(30);
+    Duration::from_secs(seconds)
+}
+
+/// Starts the graceful shutdown of axum-server on the first signal
+fn graceful_handle(timeout: Duration) -> Handle {
+    let handle = Handle::new();
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down, waiting for open requests");
+        shutdown.graceful_shutdown(Some(timeout));
+    });
+    handle
+}
+
+/// SIGTERM is what container runtimes send, Ctrl+C is for running it in a terminal
+pub async fn shutdown_signal() {
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("couldn't listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+struct UnixAccept(UnixListener);
+
+impl Accept for UnixAccept {
+    type Conn = UnixStream;
+    type Error = std::io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<UnixStream, std::io::Error>>> {
+        self.0
+            .poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _)| stream)))
+    }
+}
@@ -1,83 +1,384 @@
 use axum::{
     body::{Body, BoxBody},
-    extract::{FromRef, Query, State},
-    response::{IntoResponse, Redirect, Response},
+    extract::{FromRef, Path, Query, State},
+    handler::Handler,
+    middleware,
+    response::{Html, IntoResponse, Redirect, Response},
     routing::{get, post},
-    Router,
+    Form, Json, Router,
 };
 use axum_extra::extract::{
     cookie::{Cookie, Expiration, Key, SameSite},
     PrivateCookieJar,
 };
-use desktop_timetable::app::*;
-use http::{Request, StatusCode, Uri};
+use desktop_timetable::{
+    alerts::{ChangeWatcher, Senders},
+    api::{
+        generate_api_token, hash_api_token, parse_iso_week, seal_token, unseal_token,
+        ErrorResponse, TimetableResponse, API_VERSION,
+    },
+    app::*,
+    export::{
+        image::{timetable_png, timetable_svg, ImageOptions, Theme},
+        pdf::timetable_pdf,
+    },
+    health::readiness,
+    i18n::*,
+    listener::Listener,
+    routes::{
+        api_token::ApiTokenContext,
+        dashboard::{FetchTimetable, RefetchTimetable},
+        display::DisplayContext,
+        free::FetchCourseTimetables,
+        settings::{
+            GetSettings, PushPublicKey, SavePushSubscription, SaveSettings, SettingsContext,
+        },
+        widget::FetchWidgetTimetable,
+    },
+    security::{self, RateLimiter, SecurityConfig},
+    sources::{
+        configured_course, german_now,
+        holidays::{HolidayConfig, SchoolCalendar},
+        homeworker::HomeworkerSource,
+        load_configured, load_current_week, parse_date, sources_title, today, SourceConfig,
+        SourceError, SourcesContext,
+    },
+    store::{ApiToken, SettingsStore, StoreContext},
+    telemetry,
+    types::{
+        overrides::{parse_color, SubjectOverride, SubjectOverrides},
+        settings::UserSettings,
+        timetable::{Timetable, WeekType},
+    },
+};
+use http::{header, HeaderMap, Request, StatusCode, Uri};
 use leptos::{create_signal, get_configuration, provide_context, view, LeptosOptions};
-use leptos_axum::{generate_route_list, handle_server_fns};
-use log::*;
+use leptos_axum::{generate_route_list, handle_server_fns_with_context};
+use metrics_exporter_prometheus::PrometheusHandle;
 use serde::Deserialize;
-use time::{Duration, OffsetDateTime};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use subtle::ConstantTimeEq;
+use time::{Duration, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+use tokio::sync::Mutex;
 use tower::util::ServiceExt;
 use tower_http::services::ServeDir;
+use tracing::{debug, error, info, warn};
 
 #[derive(Clone, FromRef)]
 pub struct AppState {
     leptos_options: LeptosOptions,
     cookie_key: Key,
+    display_token: DisplayToken,
+    sources: SourceConfigs,
+    store: SettingsStore,
+    metrics: PrometheusHandle,
+}
+
+/// Sources and school calendar used for the dashboard, loaded once on startup
+#[derive(Clone)]
+pub struct SourceConfigs {
+    configs: Arc<Vec<SourceConfig>>,
+    calendar: Arc<SchoolCalendar>,
+}
+
+impl SourceConfigs {
+    /// Reads the TOML file from `TIMETABLE_SOURCES`. Without that variable, the Homeworker course of
+    /// the user is used and there are no holidays.
+    ///
+    /// `ab_reference = "2023-02-20"` at the top of the file enables A/B weeks for all sources, with
+    /// the week of that day as an A-week.
+    async fn load() -> SourceConfigs {
+        #[derive(Deserialize)]
+        struct SourcesFile {
+            source: Vec<SourceConfig>,
+            holidays: Option<HolidayConfig>,
+            ab_reference: Option<String>,
+        }
+
+        let (configs, holidays, ab_reference) = match std::env::var("TIMETABLE_SOURCES") {
+            Ok(path) => {
+                let file = std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("couldn't read source config {path}: {e}"));
+                let file = toml::from_str::<SourcesFile>(&file)
+                    .unwrap_or_else(|e| panic!("invalid source config {path}: {e}"));
+                let ab_reference = file.ab_reference.map(|date| {
+                    parse_date(&date).unwrap_or_else(|| {
+                        panic!("invalid ab_reference {date:?} in {path}, expected YYYY-MM-DD")
+                    })
+                });
+                (file.source, file.holidays, ab_reference)
+            }
+            Err(_) => (SourceConfig::defaults(), None, None),
+        };
+
+        for config in &configs {
+            if let Err(e) = config.validate().await {
+                panic!("invalid timetable file {e}");
+            }
+        }
+
+        let holidays = match holidays {
+            Some(config) => config
+                .load()
+                .unwrap_or_else(|e| panic!("invalid holidays {}: {:?}", config.path, e)),
+            None => vec![],
+        };
+
+        SourceConfigs {
+            configs: Arc::new(configs),
+            calendar: Arc::new(SchoolCalendar {
+                holidays,
+                ab_reference,
+            }),
+        }
+    }
+}
+
+/// Access token of the account used by `/display`, refreshed from `HW_DISPLAY_REFRESH_TOKEN`
+#[derive(Clone, Default)]
+pub struct DisplayToken(Arc<Mutex<Option<(String, OffsetDateTime)>>>);
+
+impl DisplayToken {
+    async fn access_token(&self) -> Option<String> {
+        let mut cached = self.0.lock().await;
+        if let Some((token, expires)) = cached.as_ref() {
+            if *expires > OffsetDateTime::now_utc() {
+                telemetry::cache_lookup("display_token", true);
+                return Some(token.clone());
+            }
+        }
+        telemetry::cache_lookup("display_token", false);
+
+        match homeworker::auth::refresh_token(
+            std::env::var("HW_CLIENT_ID").unwrap(),
+            std::env::var("HW_SECRET").unwrap(),
+            std::env::var("HW_DISPLAY_REFRESH_TOKEN").ok()?,
+        )
+        .await
+        {
+            Ok(response) => {
+                // Refresh a minute early, so a token never expires during a render
+                let expires =
+                    OffsetDateTime::now_utc() + Duration::seconds(response.expires_in as i64 - 60);
+                *cached = Some((response.access_token.clone(), expires));
+                telemetry::token_refresh("display", true);
+                Some(response.access_token)
+            }
+            Err(e) => {
+                telemetry::token_refresh("display", false);
+                error!("Display token refresh failed: {:?}", e);
+                None
+            }
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    simple_logger::init_with_level(log::Level::Info).expect("couldn't initialize logging");
+    // For HEALTHCHECK in the container, which has to follow TLS_CERT, UNIX_SOCKET and the port
+    if std::env::args().any(|arg| arg == "--healthcheck") {
+        let conf = get_configuration(None).await.unwrap();
+        let listener = Listener::from_env(conf.leptos_options.site_address);
+        match listener.healthcheck().await {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("unhealthy: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let metrics = telemetry::init();
+    if std::env::var("METRICS_TOKEN").map_or(true, |token| token.is_empty()) {
+        info!("METRICS_TOKEN isn't set, /metrics is disabled");
+    }
 
     let conf = get_configuration(None).await.unwrap();
     let leptos_options = conf.leptos_options;
     let routes = generate_route_list(|cx| view! { cx, <App/> }).await;
 
+    _ = GetSettings::register();
+    _ = SaveSettings::register();
+    _ = FetchTimetable::register();
+    _ = RefetchTimetable::register();
+    _ = FetchCourseTimetables::register();
+    _ = PushPublicKey::register();
+    _ = SavePushSubscription::register();
+    _ = FetchWidgetTimetable::register();
+
+    let settings_path = std::env::var("SETTINGS_PATH").unwrap_or("settings.json".to_owned());
+    let store = SettingsStore::open(&settings_path)
+        .await
+        .unwrap_or_else(|e| panic!("couldn't open settings store {settings_path}: {e:?}"));
+    let cookie_key = Key::from(std::env::var("HW_SECRET").unwrap().as_ref());
+    let sources = SourceConfigs::load().await;
+
+    let alert_minutes = std::env::var("ALERT_INTERVAL_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .unwrap_or(15);
+    let watcher = ChangeWatcher {
+        store: store.clone(),
+        sources: sources.configs.clone(),
+        calendar: sources.calendar.clone(),
+        senders: Senders::from_env(),
+        key: cookie_key.clone(),
+    };
+    tokio::spawn(watcher.run(std::time::Duration::from_secs(alert_minutes * 60)));
+
+    // Logins and server functions reach Homeworker, so they are limited per client
+    let auth_limit = middleware::from_fn_with_state(
+        RateLimiter::per_minute("AUTH_RATE_LIMIT", 20),
+        security::rate_limit,
+    );
+    let serverfn_limit = middleware::from_fn_with_state(
+        RateLimiter::per_minute("SERVERFN_RATE_LIMIT", 120),
+        security::rate_limit,
+    );
+    // Exports are rendered on the server, one limit for all formats
+    let export_limit = middleware::from_fn_with_state(
+        RateLimiter::per_minute("EXPORT_RATE_LIMIT", 20),
+        security::rate_limit,
+    );
+
     let mut router = Router::new()
-        .route("/auth", get(oauth_token_exchange_handler))
-        .route("/serverfn/*path", post(handle_server_fns))
+        .route("/auth", get(oauth_token_exchange_handler).layer(auth_limit.clone()))
+        .route("/locale", get(locale_handler))
+        .route("/export/timetable.pdf", get(pdf_export_handler).layer(export_limit.clone()))
+        .route("/export/timetable.svg", get(svg_export_handler).layer(export_limit.clone()))
+        .route("/export/timetable.png", get(png_export_handler).layer(export_limit))
+        .route("/api/v1/timetable", get(api_timetable_handler))
+        .route("/api/v1/tokens", post(api_token_creation_handler))
+        .route("/api/v1/tokens/revoke", post(api_token_revoke_handler))
+        .route("/app/subjects", post(subject_overrides_handler))
+        .route("/email/confirm", get(email_confirmation_handler))
+        .route("/serverfn/*path", post(server_fn_handler).layer(serverfn_limit))
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(health_handler))
+        .route("/readyz", get(readiness_handler))
         .fallback(file_handler);
 
     // Add leptos routes. The default implementation (impl LeptosRoutes) doesn't provide custom scopes.
     for path in routes.iter() {
-        router = router.route(path, get(leptos_route_handler));
+        router = match path.as_str() {
+            // The display key is long-lived, so guessing it is limited like logins
+            "/display" => router.route(
+                path,
+                get(display_route_handler).post(display_key_handler.layer(auth_limit.clone())),
+            ),
+            _ => router.route(path, get(leptos_route_handler)),
+        };
     }
 
-    info!("Listening on {}", leptos_options.site_address.clone());
-    axum::Server::bind(&leptos_options.site_address)
-        .serve(
-            router
-                .with_state(AppState {
-                    leptos_options: leptos_options.clone(),
-                    cookie_key: Key::from(std::env::var("HW_SECRET").unwrap().as_ref()),
-                })
-                .into_make_service(),
-        )
+    // After all routes are added, so every request knows its route
+    router = router.route_layer(middleware::from_fn(telemetry::track_request));
+
+    let listener = Listener::from_env(leptos_options.site_address);
+    if matches!(listener, Listener::Unix(_))
+        && std::env::var("TRUST_PROXY").as_deref() != Ok("true")
+    {
+        warn!("Requests on a Unix socket have no client IP, rate limiting needs TRUST_PROXY=true");
+    }
+    if std::env::var("DEV_INSECURE_COOKIES").as_deref() == Ok("true") {
+        match listener.is_local() {
+            true => {
+                warn!("Cookies are sent without the secure flag, only use this for development");
+                INSECURE_COOKIES.store(true, Ordering::Relaxed);
+            }
+            false => {
+                warn!("DEV_INSECURE_COOKIES is ignored, as the server isn't only on localhost")
+            }
+        }
+    }
+
+    let security = Arc::new(SecurityConfig::from_env(secure_cookies()));
+    router = router
+        .layer(middleware::from_fn_with_state(security.clone(), security::limit_body))
+        .layer(middleware::from_fn_with_state(security, security::security_headers));
+
+    info!("Listening on {:?}", listener);
+    listener
+        .serve(router.with_state(AppState {
+            leptos_options: leptos_options.clone(),
+            cookie_key,
+            display_token: DisplayToken::default(),
+            sources,
+            store,
+            metrics,
+        }))
         .await
-        .unwrap();
+        .unwrap_or_else(|e| panic!("server error: {e}"));
+    info!("Stopped");
+}
+
+/// Set on startup with `DEV_INSECURE_COOKIES=true`, so logins work over plain HTTP on localhost
+static INSECURE_COOKIES: AtomicBool = AtomicBool::new(false);
+
+fn secure_cookies() -> bool {
+    !INSECURE_COOKIES.load(Ordering::Relaxed)
 }
 
 async fn leptos_route_handler(
     State(leptos_options): State<LeptosOptions>,
+    State(sources): State<SourceConfigs>,
+    State(store): State<SettingsStore>,
     mut cookies: PrivateCookieJar,
     request: Request<Body>,
 ) -> impl IntoResponse {
-    if cookies.get("access-token") == None && cookies.get("refresh-token") != None {
-        cookies = refresh_token(
-            cookies,
-            std::env::var("HW_CLIENT_ID").unwrap(),
-            std::env::var("HW_SECRET").unwrap(),
-        )
-        .await;
-    }
+    cookies = refresh_access_token(cookies).await;
 
     let client_id = std::env::var("HW_CLIENT_ID").unwrap();
     let access_token = cookies.get("access-token").map(|c| c.value().to_owned());
     let refresh_token = cookies.get("refresh-token").map(|c| c.value().to_owned());
+    let locale = request_locale(&cookies, request.headers());
+    let (mut cookies, user_id, settings) =
+        user_settings(&store, cookies, access_token.as_deref()).await;
+
+    // Only shown once, right after the redirect from the token creation
+    let new_api_token = cookies.get("new-api-token").map(|c| c.value().to_owned());
+    if new_api_token.is_some() {
+        cookies = cookies.remove(Cookie::named("new-api-token"));
+    }
+    let api_tokens = match (user_id, request.uri().path()) {
+        (Some(user_id), "/app/api") => store.api_tokens(user_id).await,
+        _ => vec![],
+    };
 
     let handler = leptos_axum::render_app_to_stream_with_context(
         leptos_options.clone(),
         move |cx| {
+            // For server functions called during the render, which don't save settings
+            provide_context(
+                cx,
+                StoreContext {
+                    store: store.clone(),
+                    user_id,
+                    alert_token: None,
+                },
+            );
+            provide_context(
+                cx,
+                SourcesContext {
+                    configs: create_signal(cx, sources.configs.as_ref().clone()).0,
+                    calendar: create_signal(cx, sources.calendar.as_ref().clone()).0,
+                },
+            );
+            provide_context(
+                cx,
+                SettingsContext {
+                    settings: create_signal(cx, settings.clone()).0,
+                },
+            );
+            provide_context(
+                cx,
+                ApiTokenContext {
+                    new_token: create_signal(cx, new_api_token.clone()).0,
+                    tokens: create_signal(cx, api_tokens.clone()).0,
+                },
+            );
             provide_context(
                 cx,
                 HomeworkerContext {
@@ -86,6 +387,12 @@ async fn leptos_route_handler(
                     refresh_token: create_signal(cx, refresh_token.clone()).0,
                 },
             );
+            provide_context(
+                cx,
+                LocaleContext {
+                    locale: create_signal(cx, locale).0,
+                },
+            );
         },
         |cx| view! { cx, <App/> },
     );
@@ -93,11 +400,751 @@ async fn leptos_route_handler(
     (cookies, handler(request).await)
 }
 
-async fn refresh_token(
+#[derive(Deserialize)]
+struct DisplayQueryParams {
+    /// Comma separated course ids
+    courses: Option<String>,
+    refresh: Option<u32>,
+}
+
+/// Compares with `DISPLAY_KEY` in constant time. The display is disabled without one.
+fn display_key_valid(key: Option<&str>) -> bool {
+    match (std::env::var("DISPLAY_KEY"), key) {
+        (Ok(expected), Some(key)) if !expected.is_empty() => {
+            key.as_bytes().ct_eq(expected.as_bytes()).into()
+        }
+        _ => false,
+    }
+}
+
+/// Asks for the display key once, so it doesn't have to be in the URL of the kiosk browser
+fn display_key_form(locale: Locale) -> Response {
+    let strings = locale.strings();
+    let form = format!(
+        r#"<!DOCTYPE html><html lang="{}"><body><form method="post">
+        <label>{} <input type="password" name="key" autofocus></label>
+        <button>{}</button>
+        </form></body></html>"#,
+        locale.code(),
+        strings.display_key_label,
+        strings.display_key_button
+    );
+    (StatusCode::UNAUTHORIZED, Html(form)).into_response()
+}
+
+#[derive(Deserialize)]
+struct DisplayKeyForm {
+    key: String,
+}
+
+/// Keeps a valid display key in a cookie and reloads the display with the same parameters
+async fn display_key_handler(
+    uri: Uri,
+    headers: HeaderMap,
     cookies: PrivateCookieJar,
-    client_id: String,
-    client_secret: String,
-) -> PrivateCookieJar {
+    Form(form): Form<DisplayKeyForm>,
+) -> Response {
+    if !display_key_valid(Some(&form.key)) {
+        return display_key_form(request_locale(&cookies, &headers));
+    }
+
+    let cookies = cookies.add(
+        Cookie::build("display-key", form.key)
+            .http_only(true)
+            .secure(secure_cookies())
+            .same_site(SameSite::Lax)
+            // Browsers limit cookies to 400 days
+            .max_age(Duration::days(400))
+            .finish(),
+    );
+    (cookies, Redirect::to(&uri.to_string())).into_response()
+}
+
+/// Renders `/display` with the server-side display account instead of user cookies. Requires the
+/// `X-Display-Key` header or the cookie from the key form to match `DISPLAY_KEY`.
+async fn display_route_handler(
+    State(leptos_options): State<LeptosOptions>,
+    State(display_token): State<DisplayToken>,
+    query: Query<DisplayQueryParams>,
+    cookies: PrivateCookieJar,
+    request: Request<Body>,
+) -> Response {
+    let key = request
+        .headers()
+        .get("x-display-key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .or_else(|| {
+            cookies
+                .get("display-key")
+                .map(|cookie| cookie.value().to_owned())
+        });
+    if !display_key_valid(key.as_deref()) {
+        return display_key_form(request_locale(&cookies, request.headers()));
+    }
+
+    let access_token = display_token.access_token().await;
+    let courses: Vec<u32> = query
+        .0
+        .courses
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect();
+    let refresh_seconds = query.0.refresh.unwrap_or(300).max(30);
+
+    // German time with daylight saving time by default, as the server usually runs in UTC
+    let now = match std::env::var("DISPLAY_UTC_OFFSET_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .and_then(|hours| UtcOffset::from_hms(hours, 0, 0).ok())
+    {
+        Some(utc_offset) => {
+            let now = OffsetDateTime::now_utc().to_offset(utc_offset);
+            PrimitiveDateTime::new(now.date(), now.time())
+        }
+        None => german_now(),
+    };
+
+    let locale = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Locale::from_accept_language)
+        .unwrap_or_default();
+
+    let handler = leptos_axum::render_app_to_stream_with_context(
+        leptos_options.clone(),
+        move |cx| {
+            provide_context(
+                cx,
+                DisplayContext {
+                    access_token: create_signal(cx, access_token.clone()).0,
+                    courses: create_signal(cx, courses.clone()).0,
+                    now: create_signal(cx, Some(now)).0,
+                    refresh_seconds: create_signal(cx, refresh_seconds).0,
+                },
+            );
+            provide_context(
+                cx,
+                LocaleContext {
+                    locale: create_signal(cx, locale).0,
+                },
+            );
+        },
+        |cx| view! { cx, <App/> },
+    );
+
+    handler(request).await.into_response()
+}
+
+/// Server functions get the settings store and the id of the user making the request, plus the
+/// same contexts as a page render
+async fn server_fn_handler(
+    State(cookie_key): State<Key>,
+    State(sources): State<SourceConfigs>,
+    State(store): State<SettingsStore>,
+    mut cookies: PrivateCookieJar,
+    path: Path<String>,
+    headers: HeaderMap,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    cookies = refresh_access_token(cookies).await;
+
+    let client_id = std::env::var("HW_CLIENT_ID").unwrap();
+    let access_token = cookies.get("access-token").map(|c| c.value().to_owned());
+    let refresh_token = cookies.get("refresh-token").map(|c| c.value().to_owned());
+    let alert_token = refresh_token
+        .clone()
+        .map(|token| seal_token(&cookie_key, token));
+    let locale = request_locale(&cookies, &headers);
+    let (cookies, user_id, settings) =
+        user_settings(&store, cookies, access_token.as_deref()).await;
+
+    let response = handle_server_fns_with_context(
+        path,
+        headers,
+        move |cx| {
+            provide_context(
+                cx,
+                StoreContext {
+                    store: store.clone(),
+                    user_id,
+                    alert_token: alert_token.clone(),
+                },
+            );
+            provide_context(
+                cx,
+                SourcesContext {
+                    configs: create_signal(cx, sources.configs.as_ref().clone()).0,
+                    calendar: create_signal(cx, sources.calendar.as_ref().clone()).0,
+                },
+            );
+            provide_context(
+                cx,
+                SettingsContext {
+                    settings: create_signal(cx, settings.clone()).0,
+                },
+            );
+            provide_context(
+                cx,
+                HomeworkerContext {
+                    client_id: create_signal(cx, client_id.clone()).0,
+                    access_token: create_signal(cx, access_token.clone()).0,
+                    refresh_token: create_signal(cx, refresh_token.clone()).0,
+                },
+            );
+            provide_context(
+                cx,
+                LocaleContext {
+                    locale: create_signal(cx, locale).0,
+                },
+            );
+        },
+        request,
+    )
+    .await;
+    (cookies, response)
+}
+
+/// Settings of the logged in user. The user id is kept in a session cookie, so Homeworker is only
+/// asked for it once per session.
+async fn user_settings(
+    store: &SettingsStore,
+    mut cookies: PrivateCookieJar,
+    access_token: Option<&str>,
+) -> (PrivateCookieJar, Option<u32>, UserSettings) {
+    telemetry::cache_lookup("user_id", cookies.get("user-id").is_some());
+    let user_id = match (cookies.get("user-id"), access_token) {
+        (Some(cookie), _) => cookie.value().parse().ok(),
+        (None, Some(access_token)) => {
+            match HomeworkerSource::new(access_token.to_owned(), None)
+                .user_id()
+                .await
+            {
+                Ok(user_id) => {
+                    cookies = cookies.add(
+                        Cookie::build("user-id", user_id.to_string())
+                            .http_only(true)
+                            .secure(secure_cookies())
+                            .same_site(SameSite::Lax)
+                            .finish(),
+                    );
+                    Some(user_id)
+                }
+                Err(e) => {
+                    error!("Looking up the user id failed: {:?}", e);
+                    None
+                }
+            }
+        }
+        (None, None) => None,
+    };
+
+    let Some(user_id) = user_id else {
+        return (cookies, None, UserSettings::default());
+    };
+    telemetry::record_user(user_id);
+
+    // Overrides were stored in a cookie before there was a store
+    if let Some(cookie) = cookies.get("subject-overrides") {
+        if let Ok(overrides) = serde_json::from_str::<SubjectOverrides>(cookie.value()) {
+            let imported = store
+                .update(user_id, |settings| {
+                    if settings.overrides.0.is_empty() {
+                        settings.overrides = overrides;
+                    }
+                })
+                .await;
+            if let Err(e) = imported {
+                error!("Importing the override cookie failed: {:?}", e);
+                return (cookies, Some(user_id), store.get(user_id).await);
+            }
+        }
+        cookies = cookies.remove(Cookie::named("subject-overrides"));
+    }
+
+    let settings = store.get(user_id).await;
+    (cookies, Some(user_id), settings)
+}
+
+/// Receives the form of `/app/subjects`. Fields are suffixed with the row index, checkboxes are only
+/// sent when checked and empty text fields mean no override.
+async fn subject_overrides_handler(
+    State(store): State<SettingsStore>,
+    mut cookies: PrivateCookieJar,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    cookies = refresh_access_token(cookies).await;
+
+    let field = |name: &str, i: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| *key == format!("{name}_{i}"))
+            .map(|(_, value)| value.trim())
+    };
+    let non_empty = |value: Option<&str>| value.filter(|v| !v.is_empty()).map(str::to_owned);
+
+    // Only the submitted rows change, subjects that aren't listed keep their overrides
+    let mut rows = Vec::new();
+    for (key, subject) in fields.iter().filter(|(key, _)| key.starts_with("key_")) {
+        let i = &key["key_".len()..];
+        let color = field("color", i).filter(|color| Some(*color) != field("original_color", i));
+
+        let subject_override = SubjectOverride {
+            full_name: non_empty(field("full_name", i)),
+            short_name: non_empty(field("short_name", i)),
+            color: color.and_then(parse_color),
+            hidden: field("hidden", i).is_some(),
+            week_type: field("week_type", i).and_then(WeekType::parse),
+        };
+        rows.push((subject.clone(), subject_override));
+    }
+
+    let access_token = cookies.get("access-token").map(|c| c.value().to_owned());
+    let (cookies, user_id, _) = user_settings(&store, cookies, access_token.as_deref()).await;
+    let user_id = user_id.ok_or((StatusCode::UNAUTHORIZED, "Not logged in".to_owned()))?;
+
+    store
+        .update(user_id, |settings| {
+            for (subject, subject_override) in rows {
+                match subject_override == SubjectOverride::default() {
+                    true => settings.overrides.0.remove(&subject),
+                    false => settings.overrides.0.insert(subject, subject_override),
+                };
+            }
+        })
+        .await
+        .map_err(|e| {
+            error!("Saving the overrides failed: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error while saving".to_owned())
+        })?;
+    Ok((cookies, Redirect::to("/app")))
+}
+
+#[derive(Deserialize)]
+struct EmailConfirmationQueryParams {
+    token: String,
+}
+
+/// Opened from the link sent by `save_settings`. Doesn't need a login, as the email may be read on
+/// another device.
+async fn email_confirmation_handler(
+    State(store): State<SettingsStore>,
+    query: Query<EmailConfirmationQueryParams>,
+) -> Result<Redirect, (StatusCode, String)> {
+    match store
+        .confirm_email(&hash_api_token(&query.0.token), today())
+        .await
+    {
+        Ok(Some(_)) => Ok(Redirect::to("/settings")),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Invalid or expired link".to_owned())),
+        Err(e) => {
+            error!("Confirming an email address failed: {:?}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Error while saving".to_owned()))
+        }
+    }
+}
+
+/// The locale cookie set by `/locale` overrides the browser preference
+fn request_locale(cookies: &PrivateCookieJar, headers: &HeaderMap) -> Locale {
+    cookies
+        .get("locale")
+        .and_then(|c| Locale::from_code(c.value()))
+        .or_else(|| {
+            headers
+                .get(header::ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Locale::from_accept_language)
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+struct LocaleQueryParams {
+    lang: Option<String>,
+}
+
+async fn locale_handler(
+    query: Query<LocaleQueryParams>,
+    headers: HeaderMap,
+    mut cookies: PrivateCookieJar,
+) -> impl IntoResponse {
+    match query.0.lang.as_deref().and_then(Locale::from_code) {
+        Some(locale) => {
+            cookies = cookies.add(
+                Cookie::build("locale", locale.code())
+                    .http_only(true)
+                    .secure(secure_cookies())
+                    .same_site(SameSite::Lax)
+                    .expires(Expiration::from(OffsetDateTime::now_utc() + Duration::days(365)))
+                    .finish(),
+            )
+        }
+        None => cookies = cookies.remove(Cookie::named("locale")),
+    }
+
+    // Only redirect back to pages of this site
+    let target = headers
+        .get(header::REFERER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|referer| referer.parse::<Uri>().ok())
+        .map(|uri| uri.path().to_owned())
+        .unwrap_or("/".to_owned());
+
+    (cookies, Redirect::to(&target))
+}
+
+#[derive(Deserialize)]
+struct ExportQueryParams {
+    course: Option<u32>,
+}
+
+/// Timetable of the current week for the exports, with its title
+async fn export_timetable(
+    store: &SettingsStore,
+    sources: &SourceConfigs,
+    course: Option<u32>,
+    headers: &HeaderMap,
+    mut cookies: PrivateCookieJar,
+) -> Result<(PrivateCookieJar, Locale, String, Timetable), (StatusCode, String)> {
+    cookies = refresh_access_token(cookies).await;
+
+    // Like the dashboard, sources without Homeworker work without a login
+    let access_token = cookies.get("access-token").map(|c| c.value().to_owned());
+    let locale = request_locale(&cookies, headers);
+    let (cookies, _, mut settings) = user_settings(store, cookies, access_token.as_deref()).await;
+    settings.default_course = course.or(settings.default_course);
+
+    let load = |week| {
+        load_configured(&sources.configs, &sources.calendar, access_token.clone(), &settings, week)
+    };
+    // Holidays have nothing to show
+    let (_, timetable) = load_current_week(today(), load)
+        .await
+        .map_err(source_error)?;
+    let timetable = timetable.without_holidays();
+    let course = configured_course(&sources.configs, access_token, &settings).await;
+
+    let title = locale
+        .timetable_title(&sources_title(&sources.configs, course.as_ref()), timetable.date_range());
+    Ok((cookies, locale, title, timetable))
+}
+
+/// Same content as `/app/print`, rendered on the server without a browser
+async fn pdf_export_handler(
+    State(store): State<SettingsStore>,
+    State(sources): State<SourceConfigs>,
+    query: Query<ExportQueryParams>,
+    headers: HeaderMap,
+    cookies: PrivateCookieJar,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (cookies, locale, title, timetable) =
+        export_timetable(&store, &sources, query.0.course, &headers, cookies).await?;
+    let pdf = timetable_pdf(&timetable, locale, &title).map_err(|err| {
+        error!("Error while rendering the PDF export: {:?}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Error while rendering the PDF".to_owned())
+    })?;
+
+    Ok((
+        cookies,
+        [
+            (header::CONTENT_TYPE, "application/pdf"),
+            (header::CONTENT_DISPOSITION, "inline; filename=\"timetable.pdf\""),
+        ],
+        pdf,
+    ))
+}
+
+#[derive(Deserialize)]
+struct ImageExportQueryParams {
+    course: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    /// `light` or `dark`
+    theme: Option<String>,
+}
+
+impl ImageExportQueryParams {
+    /// Full HD by default, e.g. for wallpapers. Sizes are limited to 4K to keep rendering cheap.
+    fn options(&self) -> Result<ImageOptions, (StatusCode, String)> {
+        let theme = match self.theme.as_deref() {
+            Some(theme) => Theme::parse(theme)
+                .ok_or((StatusCode::BAD_REQUEST, format!("Unknown theme {theme}")))?,
+            None => Theme::Light,
+        };
+        Ok(ImageOptions {
+            width: self.width.unwrap_or(1920).clamp(320, 3840),
+            height: self.height.unwrap_or(1080).clamp(240, 2160),
+            theme,
+        })
+    }
+}
+
+/// The grid of the `Timetable` component as an SVG image, e.g. to post it in a chat
+async fn svg_export_handler(
+    State(leptos_options): State<LeptosOptions>,
+    State(store): State<SettingsStore>,
+    State(sources): State<SourceConfigs>,
+    query: Query<ImageExportQueryParams>,
+    headers: HeaderMap,
+    cookies: PrivateCookieJar,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let options = query.0.options()?;
+    let (cookies, locale, title, timetable) =
+        export_timetable(&store, &sources, query.0.course, &headers, cookies).await?;
+    let svg = timetable_svg(&timetable, locale, &title, options, &leptos_options.site_root);
+
+    Ok((
+        cookies,
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CONTENT_DISPOSITION, "inline; filename=\"timetable.svg\""),
+        ],
+        svg,
+    ))
+}
+
+/// Same as the SVG export, rasterised on the server
+async fn png_export_handler(
+    State(leptos_options): State<LeptosOptions>,
+    State(store): State<SettingsStore>,
+    State(sources): State<SourceConfigs>,
+    query: Query<ImageExportQueryParams>,
+    headers: HeaderMap,
+    cookies: PrivateCookieJar,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let options = query.0.options()?;
+    let (cookies, locale, title, timetable) =
+        export_timetable(&store, &sources, query.0.course, &headers, cookies).await?;
+    let svg = timetable_svg(&timetable, locale, &title, options, &leptos_options.site_root);
+
+    // Rendering is CPU bound
+    let site_root = leptos_options.site_root.clone();
+    let png = tokio::task::spawn_blocking(move || timetable_png(&svg, options, &site_root))
+        .await
+        .map_err(|err| {
+            error!("Rendering the PNG export panicked: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error while rendering the image".to_owned())
+        })?
+        .map_err(|err| {
+            error!("Error while rendering the PNG export: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error while rendering the image".to_owned())
+        })?;
+
+    Ok((
+        cookies,
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CONTENT_DISPOSITION, "inline; filename=\"timetable.png\""),
+        ],
+        png,
+    ))
+}
+
+#[derive(Deserialize)]
+struct ApiTimetableQueryParams {
+    course: Option<u32>,
+    week: Option<String>,
+}
+
+type ApiError = (StatusCode, Json<ErrorResponse>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(ErrorResponse::new(message)))
+}
+
+/// Versioned JSON API, see `desktop_timetable::api` for the schema
+async fn api_timetable_handler(
+    State(cookie_key): State<Key>,
+    State(store): State<SettingsStore>,
+    State(sources): State<SourceConfigs>,
+    query: Query<ApiTimetableQueryParams>,
+    headers: HeaderMap,
+    mut cookies: PrivateCookieJar,
+) -> Result<impl IntoResponse, ApiError> {
+    let bearer_token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    let access_token = match bearer_token {
+        Some(token) => {
+            let refresh_token = store
+                .api_token(&hash_api_token(token))
+                .await
+                .and_then(|api_token| unseal_token(&cookie_key, &api_token.refresh_token))
+                .ok_or(api_error(StatusCode::UNAUTHORIZED, "Invalid API token"))?;
+            homeworker::auth::refresh_token(
+                std::env::var("HW_CLIENT_ID").unwrap(),
+                std::env::var("HW_SECRET").unwrap(),
+                refresh_token,
+            )
+            .await
+            .map_err(|e| {
+                telemetry::token_refresh("api", false);
+                error!("Token refresh for API token failed: {:?}", e);
+                api_error(StatusCode::UNAUTHORIZED, "API token is no longer valid")
+            })
+            .map(|response| {
+                telemetry::token_refresh("api", true);
+                response.access_token
+            })?
+        }
+        None => {
+            cookies = refresh_access_token(cookies).await;
+            cookies
+                .get("access-token")
+                .map(|c| c.value().to_owned())
+                .ok_or(api_error(StatusCode::UNAUTHORIZED, "Not logged in"))?
+        }
+    };
+
+    let week = match &query.0.week {
+        Some(week) => Some(
+            parse_iso_week(week)
+                .ok_or(api_error(StatusCode::BAD_REQUEST, "Invalid week, expected YYYY-Www"))?,
+        ),
+        None => None,
+    };
+
+    let to_api_error = |e| {
+        let (status, message) = source_error(e);
+        api_error(status, &message)
+    };
+
+    let (cookies, _, mut settings) = user_settings(&store, cookies, Some(&access_token)).await;
+    settings.default_course = query.0.course.or(settings.default_course);
+    let access_token = Some(access_token);
+
+    let load = |week| {
+        load_configured(&sources.configs, &sources.calendar, access_token.clone(), &settings, week)
+    };
+    let (week, timetable) = match week {
+        Some(week) => (week, load(week).await.map_err(to_api_error)?),
+        None => load_current_week(today(), load)
+            .await
+            .map_err(to_api_error)?,
+    };
+    let course = configured_course(&sources.configs, access_token, &settings).await;
+    let course_name = sources_title(&sources.configs, course.as_ref());
+    let course_id = course.map(|(id, _)| id);
+
+    Ok((
+        cookies,
+        Json(TimetableResponse {
+            version: API_VERSION,
+            course_id,
+            course_name,
+            week,
+            timetable,
+        }),
+    ))
+}
+
+/// Creates a personal API token and shows it once on `/app/api`
+async fn api_token_creation_handler(
+    State(cookie_key): State<Key>,
+    State(store): State<SettingsStore>,
+    mut cookies: PrivateCookieJar,
+) -> impl IntoResponse {
+    cookies = refresh_access_token(cookies).await;
+    let refresh_token = match cookies.get("refresh-token") {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return (cookies, Redirect::to("/login")),
+    };
+    let access_token = cookies.get("access-token").map(|c| c.value().to_owned());
+    let (mut cookies, user_id, _) = user_settings(&store, cookies, access_token.as_deref()).await;
+    let Some(user_id) = user_id else {
+        return (cookies, Redirect::to("/login"));
+    };
+
+    let (token, id) = generate_api_token();
+    let api_token = ApiToken {
+        id,
+        user_id,
+        created: OffsetDateTime::now_utc().date(),
+        refresh_token: seal_token(&cookie_key, refresh_token),
+    };
+    if let Err(e) = store.add_api_token(hash_api_token(&token), api_token).await {
+        error!("Saving an API token failed: {:?}", e);
+        return (cookies, Redirect::to("/app/api"));
+    }
+
+    cookies = cookies.add(
+        Cookie::build("new-api-token", token)
+            .http_only(true)
+            .secure(secure_cookies())
+            .same_site(SameSite::Strict)
+            .max_age(Duration::minutes(1))
+            .finish(),
+    );
+    (cookies, Redirect::to("/app/api"))
+}
+
+#[derive(Deserialize)]
+struct ApiTokenRevokeForm {
+    id: String,
+}
+
+/// Revokes one of the user's API tokens from the list on `/app/api`
+async fn api_token_revoke_handler(
+    State(store): State<SettingsStore>,
+    mut cookies: PrivateCookieJar,
+    Form(form): Form<ApiTokenRevokeForm>,
+) -> impl IntoResponse {
+    cookies = refresh_access_token(cookies).await;
+    let access_token = cookies.get("access-token").map(|c| c.value().to_owned());
+    let (cookies, user_id, _) = user_settings(&store, cookies, access_token.as_deref()).await;
+    let Some(user_id) = user_id else {
+        return (cookies, Redirect::to("/login"));
+    };
+
+    if let Err(e) = store.revoke_api_token(user_id, &form.id).await {
+        error!("Revoking an API token failed: {:?}", e);
+    }
+    (cookies, Redirect::to("/app/api"))
+}
+
+fn source_error(error: SourceError) -> (StatusCode, String) {
+    match error {
+        SourceError::Homeworker(ref err @ homeworker::Error::RequestError(ref request_err)) => {
+            error!(
+                error_kind = telemetry::error_kind(err),
+                "Error while fetching from Homeworker: {}", request_err
+            )
+        }
+        SourceError::Homeworker(ref err @ homeworker::Error::ApiError(ref api_err)) => error!(
+            error_kind = telemetry::error_kind(err),
+            "Error while fetching from Homeworker: {}", api_err.message
+        ),
+        SourceError::Unauthenticated => {
+            return (StatusCode::UNAUTHORIZED, "Not logged in".to_owned())
+        }
+        SourceError::NotFound => return (StatusCode::NOT_FOUND, "No course found".to_owned()),
+        // Configured sources other than Homeworker
+        SourceError::Unavailable(message) => {
+            error!("Timetable source unavailable: {}", message);
+            return (StatusCode::BAD_GATEWAY, "Error while loading the timetable".to_owned());
+        }
+        SourceError::Calendar(err) => {
+            error!("Invalid calendar source: {:?}", err);
+            return (StatusCode::BAD_GATEWAY, "Error while loading the timetable".to_owned());
+        }
+        SourceError::Manual(err) => {
+            error!("Invalid manual timetable: {}", err);
+            return (StatusCode::BAD_GATEWAY, "Error while loading the timetable".to_owned());
+        }
+    }
+    (StatusCode::BAD_GATEWAY, "Error while fetching from Homeworker".to_owned())
+}
+
+/// Gets a new access token with the refresh token cookie once the access token cookie expired.
+/// Every handler that needs the user's access token calls this first.
+async fn refresh_access_token(cookies: PrivateCookieJar) -> PrivateCookieJar {
+    if cookies.get("access-token").is_some() {
+        return cookies;
+    }
     let refresh_token = match cookies.get("refresh-token") {
         Some(cookie) => cookie.value().to_owned(),
         None => {
@@ -105,22 +1152,72 @@ async fn refresh_token(
         }
     };
 
-    match homeworker::auth::refresh_token(client_id, client_secret, refresh_token).await {
-        Ok(response) => cookies.add(
-            Cookie::build("access-token", response.access_token.clone())
-                .http_only(true)
-                .secure(true)
-                .same_site(SameSite::Lax)
-                .max_age(Duration::seconds(response.expires_in as i64))
-                .finish(),
-        ),
+    match homeworker::auth::refresh_token(
+        std::env::var("HW_CLIENT_ID").unwrap(),
+        std::env::var("HW_SECRET").unwrap(),
+        refresh_token,
+    )
+    .await
+    {
+        Ok(response) => {
+            telemetry::token_refresh("cookie", true);
+            cookies.add(
+                Cookie::build("access-token", response.access_token.clone())
+                    .http_only(true)
+                    .secure(secure_cookies())
+                    .same_site(SameSite::Lax)
+                    .max_age(Duration::seconds(response.expires_in as i64))
+                    .finish(),
+            )
+        }
         Err(e) => {
-            log::error!("Token refresh failed: {:?}", e);
+            telemetry::token_refresh("cookie", false);
+            error!("Token refresh failed: {:?}", e);
             return cookies;
         }
     }
 }
 
+/// The process is up and serving requests
+async fn health_handler() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// 503 until the configuration, the assets and, if configured, Homeworker are fine
+async fn readiness_handler(
+    State(leptos_options): State<LeptosOptions>,
+    State(sources): State<SourceConfigs>,
+) -> impl IntoResponse {
+    let readiness = readiness(&sources.configs, &leptos_options).await;
+    let status = match readiness.ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(readiness))
+}
+
+/// Prometheus metrics, only with `METRICS_TOKEN` sent as a bearer token. Without a configured
+/// token, metrics aren't served at all.
+async fn metrics_handler(
+    State(metrics): State<PrometheusHandle>,
+    headers: HeaderMap,
+) -> Result<String, StatusCode> {
+    let token = std::env::var("METRICS_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let bearer_token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match bearer_token {
+        Some(bearer_token) if bool::from(bearer_token.as_bytes().ct_eq(token.as_bytes())) => {
+            Ok(metrics.render())
+        }
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
 #[derive(Deserialize)]
 struct OauthExchangeQueryParams {
     code: Option<String>,
@@ -146,7 +1243,7 @@ async fn oauth_token_exchange_handler(
             cookies = cookies.add(
                 Cookie::build("access-token", response.access_token)
                     .http_only(true)
-                    .secure(true)
+                    .secure(secure_cookies())
                     .same_site(SameSite::Lax)
                     .max_age(Duration::seconds(response.expires_in as i64))
                     .finish(),
@@ -154,21 +1251,25 @@ async fn oauth_token_exchange_handler(
             cookies = cookies.add(
                 Cookie::build("refresh-token", response.refresh_token)
                     .http_only(true)
-                    .secure(true)
+                    .secure(secure_cookies())
                     .same_site(SameSite::Lax)
                     .expires(Expiration::from(OffsetDateTime::now_utc() + Duration::days(729)))
                     .finish(),
             );
+            // Might belong to a different account that was logged in before
+            cookies = cookies.remove(Cookie::named("user-id"));
             Ok((cookies, Redirect::to("/app")))
         }
         Err(error) => {
             match error {
-                homeworker::Error::RequestError(err) => {
-                    error!("Error while exchanging token: {}", err.to_string())
-                }
-                homeworker::Error::ApiError(err) => {
-                    error!("Error while exchanging token: {}", err.message)
-                }
+                homeworker::Error::RequestError(ref err) => error!(
+                    error_kind = telemetry::error_kind(&error),
+                    "Error while exchanging token: {}", err
+                ),
+                homeworker::Error::ApiError(ref err) => error!(
+                    error_kind = telemetry::error_kind(&error),
+                    "Error while exchanging token: {}", err.message
+                ),
             }
             Err((StatusCode::INTERNAL_SERVER_ERROR, "Error while exchanging the token".to_owned()))
         }
@@ -0,0 +1,77 @@
+use leptos::*;
+
+use crate::{api::ApiTokenInfo, i18n::use_locale};
+
+#[derive(Clone, Copy)]
+pub struct ApiTokenContext {
+    /// Provided by the server right after a token was created on `POST /api/v1/tokens`
+    pub new_token: ReadSignal<Option<String>>,
+    /// Tokens of the user, which can be revoked on `POST /api/v1/tokens/revoke`
+    pub tokens: ReadSignal<Vec<ApiTokenInfo>>,
+}
+
+#[component]
+pub fn api_token_page(cx: Scope) -> impl IntoView {
+    // Default used when no context is provided during hydration
+    let token_context = use_context::<ApiTokenContext>(cx).unwrap_or(ApiTokenContext {
+        new_token: create_signal(cx, None).0,
+        tokens: create_signal(cx, vec![]).0,
+    });
+
+    let token_resource = create_resource(
+        cx,
+        || (),
+        async move |_| ((token_context.new_token)(), (token_context.tokens)()),
+    );
+    let locale = use_locale(cx);
+
+    view! { cx,
+        // TODO: Remove fullscreen div, put classes on body
+        <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen h-screen flex justify-center items-center">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (token_resource.read(), locale.read()) {
+                    (Some((new_token, tokens)), Some(locale)) => {
+                        let strings = locale.strings();
+                        let token_list = (!tokens.is_empty()).then(|| {
+                            let items: Vec<_> = tokens.into_iter().map(|token| view! { cx,
+                                <li class="flex items-center gap-4">
+                                    <code class="grow">{token.id.clone()}</code>
+                                    <span>{locale.format_date(token.created)}</span>
+                                    <form method="post" action="/api/v1/tokens/revoke">
+                                        <input type="hidden" name="id" value=token.id/>
+                                        <button type="submit" class="h-9 px-4 border border-black dark:border-zinc-400 rounded-xl">
+                                            {strings.api_token_revoke}
+                                        </button>
+                                    </form>
+                                </li>
+                            }).collect();
+                            view! { cx,
+                                <p class="mb-2">{strings.api_token_list}</p>
+                                <ul class="mb-4 flex flex-col gap-2">{items}</ul>
+                            }
+                        });
+                        Some(view! { cx,
+                            <div class="w-[32rem] p-4 border rounded-xl border-black dark:border-zinc-400">
+                                <h1 class="text-center mb-4">{strings.api_token_title}</h1>
+                                <p class="mb-4">{strings.api_token_description}</p>
+                                {new_token.map(|token| view! { cx,
+                                    <p>{strings.api_token_created}</p>
+                                    <code class="block mb-4 p-2 break-all select-all rounded-lg bg-zinc-200 dark:bg-zinc-800">{token}</code>
+                                })}
+                                {token_list}
+                                <form method="post" action="/api/v1/tokens">
+                                    <button type="submit" class="w-full h-9 bg-sky-500 hover:bg-sky-400 rounded-xl">
+                                        {strings.api_token_create}
+                                    </button>
+                                </form>
+                            </div>
+                        }.into_view(cx))
+                    }
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
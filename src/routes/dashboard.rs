@@ -1,116 +1,176 @@
 use leptos::*;
 
-use crate::{app::HomeworkerContext, types::timetable::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{changes::*, locale_switcher::*, notifier::*},
+    i18n::use_locale,
+    routes::settings::use_settings,
+    sources::{
+        current_week,
+        ical::{calendar_timetable, parse_calendar, Event, IcalError},
+        today, week_start,
+    },
+    types::{diff::TimetableDiff, timetable::*},
+};
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct DashboardTimetable {
+    pub timetable: Timetable,
+    /// Compared with the timetable of the last visit in the same week
+    pub changes: Option<TimetableDiff>,
+}
+
+/// Timetable of the configured sources for the current week, loaded with the server contexts
+#[cfg(feature = "ssr")]
+pub async fn load_timetable(cx: Scope) -> Option<Timetable> {
+    use crate::{
+        app::HomeworkerContext,
+        sources::{holidays::SchoolCalendar, *},
+    };
+
+    let access_token = use_context::<HomeworkerContext>(cx).and_then(|hw| (hw.access_token)());
+    let (configs, calendar) = use_context::<SourcesContext>(cx)
+        .map(|sources| ((sources.configs)(), (sources.calendar)()))
+        .unwrap_or((SourceConfig::defaults(), SchoolCalendar::default()));
+    let settings = (use_settings(cx).settings)();
+
+    let load = |week| load_configured(&configs, &calendar, access_token.clone(), &settings, week);
+    match load_current_week(today(), load).await {
+        Ok((_, timetable)) => Some(timetable),
+        Err(e) => {
+            log::error!("Loading the timetable failed: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Timetable of the configured sources for the current week and the changes since the last visit,
+/// which is replaced by this one. Only for the initial load of the dashboard.
+#[server(FetchTimetable, "/serverfn")]
+pub async fn fetch_timetable(cx: Scope) -> Result<Option<DashboardTimetable>, ServerFnError> {
+    let Some(timetable) = load_timetable(cx).await else {
+        return Ok(None);
+    };
+
+    let changes = match use_context::<crate::store::StoreContext>(cx) {
+        Some(crate::store::StoreContext {
+            store,
+            user_id: Some(user_id),
+            ..
+        }) => match store.replace_visit(user_id, timetable.clone()).await {
+            Ok(Some(previous)) if previous.date_range() == timetable.date_range() => {
+                Some(crate::types::diff::diff(&previous, &timetable))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                log::error!("Storing the visit failed: {:?}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    Ok(Some(DashboardTimetable { timetable, changes }))
+}
+
+/// Timetable of the configured sources for the current week, without recording a visit. A server
+/// function, so the browser can refetch it in the background without having access to the tokens.
+#[server(RefetchTimetable, "/serverfn")]
+pub async fn refetch_timetable(cx: Scope) -> Result<Option<Timetable>, ServerFnError> {
+    Ok(load_timetable(cx).await)
+}
 
 #[component]
 pub fn dashboard_page(cx: Scope) -> impl IntoView {
-    // Default used when no context is provided during hydration
-    let hw_context = use_context::<HomeworkerContext>(cx).unwrap_or(HomeworkerContext {
-        client_id: create_signal(cx, "".to_owned()).0,
-        access_token: create_signal(cx, None).0,
-        refresh_token: create_signal(cx, None).0,
-    });
+    let settings_context = use_settings(cx);
 
     let timetable_resource = create_resource(
         cx,
         || (),
-        async move |_| -> Option<Timetable> {
-            let access_token = (hw_context.access_token)();
-            if access_token.is_none() {
-                return None;
-            }
+        async move |_| -> Option<DashboardTimetable> { fetch_timetable(cx).await.ok().flatten() },
+    );
+    let notification_settings =
+        create_resource(cx, || (), async move |_| (settings_context.settings)().notifications);
+    // Set by the notifier when it refetched the timetable in the background. The changes stay the
+    // ones since the last visit.
+    let (refetched, set_refetched) = create_signal(cx, None::<Timetable>);
 
-            let client = homeworker::HomeworkerClient::new(
-                access_token.unwrap(),
-                "desktop-timetable".to_owned(),
-            );
-
-            let course_id = client.get_course_memberships().await.unwrap()[0].course_id;
-            let raw_timetable = client.get_timetable(course_id).await.unwrap();
-
-            let mut timetable = Timetable {
-                times: vec![],
-                columns: vec![],
-            };
-
-            // Find times (Currently hardcoded)
-            timetable
-                .times
-                .push(vec!["8:00 - 8:45".to_string(), "8:45 - 9:30".to_string()]);
-            timetable
-                .times
-                .push(vec!["9:45 - 10:30".to_string(), "10:30 - 11:15".to_string()]);
-            timetable
-                .times
-                .push(vec!["11:35 - 12:20".to_string(), "12:20 - 13:05".to_string()]);
-            timetable.times.push(vec![
-                "13:20 - 14:05".to_string(),
-                "14:05 - 14:50".to_string(),
-                "14:05 - 15:35".to_string(),
-            ]);
-
-            // List lessons
-            for day in raw_timetable.iter().take(5) {
-                let mut last_position = 0;
-                let mut lessons: Vec<Option<Lesson>> = vec![];
-
-                for raw_lesson in day.lessons.iter().filter(|l| !l.is_break) {
-                    let lesson = {
-                        let raw_lesson = raw_lesson.lessons.as_ref().and_then(|l| l.first());
-                        Lesson {
-                            subject: Subject {
-                                full_name: match raw_lesson {
-                                    Some(l) => l.name.clone(),
-                                    None => "".to_owned(),
-                                },
-                                short_name: match raw_lesson {
-                                    Some(l) => l.short.clone(),
-                                    None => "".to_owned(),
-                                },
-                                teacher: match raw_lesson {
-                                    Some(l) => l.teacher.clone(),
-                                    None => "".to_owned(),
-                                },
-                                room: match raw_lesson {
-                                    Some(l) => l.room.clone(),
-                                    None => "".to_owned(),
-                                },
-                                color: (255, 255, 255),
-                            },
-                            status: crate::types::timetable::LessonStatus::Normal,
-                        }
-                    };
-
-                    // Fill empty lessons before
-                    (last_position..*raw_lesson.unit.positions.first().unwrap() - 1)
-                        .for_each(|_| lessons.push(None));
-                    last_position = *raw_lesson.unit.positions.last().unwrap();
-
-                    // Add this lesson once for each position it occupies
-                    (0..raw_lesson.unit.positions.len())
-                        .for_each(|_| lessons.push(Some(lesson.clone())));
-                }
+    // Calendars uploaded here are only parsed in the browser and not stored
+    let (uploaded_calendar, set_uploaded_calendar) =
+        create_signal(cx, None::<(String, Vec<Event>)>);
+    let (upload_error, set_upload_error) = create_signal(cx, None::<IcalError>);
 
-                timetable.columns.push(TimetableColumn {
-                    name: day.date.weekday().to_string(),
-                    lessons,
-                });
+    let on_upload = move |ev| {
+        let input = event_target::<web_sys::HtmlInputElement>(&ev);
+        let Some(file) = input.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+
+        spawn_local(async move {
+            let content = wasm_bindgen_futures::JsFuture::from(file.text())
+                .await
+                .ok()
+                .and_then(|text| text.as_string())
+                .unwrap_or_default();
+
+            match parse_calendar(&content) {
+                Ok(events) => {
+                    set_upload_error(None);
+                    set_uploaded_calendar(Some((file.name(), events)));
+                }
+                Err(e) => set_upload_error(Some(e)),
             }
+        });
+    };
 
-            Some(timetable)
-        },
-    );
+    let locale = use_locale(cx);
 
     // Name overlap with types::timetable::Timetable and components::timetable::Timetable
     use crate::components::timetable::*;
 
     view! { cx,
         // TODO: Remove fullscreen div, put classes on body
-        <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen h-screen flex justify-center items-center">
+        <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen h-screen flex flex-col justify-center items-center gap-4">
+        <LessonNotifier timetable=timetable_resource settings=notification_settings locale=locale on_update=set_refetched/>
         <Transition fallback=move || { None::<View> }>
             {move || {
-                match timetable_resource.read() {
-                    Some(Some(timetable)) => Some(view! { cx, <Timetable state=timetable/> }.into_view(cx)),
+                match (timetable_resource.read(), locale.read()) {
+                    (Some(Some(dashboard)), Some(locale)) => {
+                        let DashboardTimetable { timetable, changes } = dashboard;
+                        let timetable = refetched().unwrap_or(timetable);
+                        let timetable = match uploaded_calendar() {
+                            Some((name, events)) => {
+                                let week = timetable
+                                    .date_range()
+                                    .map(|(first, _)| week_start(first))
+                                    .unwrap_or(current_week(today()));
+                                let calendar = calendar_timetable(&events, timetable.times.clone(), week);
+                                Timetable::merge(vec![
+                                    (locale.strings().timetable_title.to_owned(), timetable),
+                                    (name, calendar),
+                                ])
+                                .with_weekdays(week, &(settings_context.settings)().weekdays)
+                            }
+                            None => timetable,
+                        };
+                        let strings = locale.strings();
+
+                        Some(view! { cx,
+                            {changes.map(|changes| view! { cx, <ChangesPanel changes=changes locale=locale/> })}
+                            <Timetable state=timetable locale=locale/>
+                            <label class="text-sm cursor-pointer underline">
+                                {strings.calendar_upload}
+                                <input type="file" accept=".ics,text/calendar" class="hidden" on:change=on_upload/>
+                            </label>
+                            {move || upload_error().map(|e| view! { cx,
+                                <p class="text-sm text-red-500">
+                                    {format!("{} ({} {})", strings.calendar_upload_error, strings.line, e.line())}
+                                </p>
+                            })}
+                            <LocaleSwitcher current=locale/>
+                        }.into_view(cx))
+                    }
                     _ => None::<View>,
                 }
             }}
@@ -0,0 +1,221 @@
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use time::{Date, PrimitiveDateTime};
+
+use crate::{
+    i18n::{use_locale, Locale},
+    sources::homeworker::HomeworkerSource,
+    types::timetable::*,
+};
+
+/// Provided by the server for `/display`. Unlike `HomeworkerContext`, the token belongs to the
+/// display account configured on the server, not to a user.
+#[derive(Clone, Copy)]
+pub struct DisplayContext {
+    pub access_token: ReadSignal<Option<String>>,
+    /// Empty to show all courses of the display account
+    pub courses: ReadSignal<Vec<u32>>,
+    /// Local time of the school, used to pick the day to show
+    pub now: ReadSignal<Option<PrimitiveDateTime>>,
+    pub refresh_seconds: ReadSignal<u32>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct DisplayState {
+    pub date: Date,
+    /// One column per course, all for `date`
+    pub timetable: Timetable,
+}
+
+/// Picks today, or the next day in the timetable once the last period of today is over
+pub fn display_date(timetable: &Timetable, now: PrimitiveDateTime) -> Option<Date> {
+    let school_end = timetable
+        .times
+        .iter()
+        .flatten()
+        .map(|period| period.end)
+        .max();
+    let today_over = school_end.map_or(false, |end| now.time() > end);
+
+    let mut dates = timetable.columns.iter().filter_map(|column| column.date);
+    dates
+        .clone()
+        .find(|date| *date > now.date() || (*date == now.date() && !today_over))
+        .or_else(|| dates.next_back())
+}
+
+#[component]
+pub fn display_page(cx: Scope) -> impl IntoView {
+    // Default used when no context is provided during hydration
+    let display_context = use_context::<DisplayContext>(cx).unwrap_or(DisplayContext {
+        access_token: create_signal(cx, None).0,
+        courses: create_signal(cx, vec![]).0,
+        now: create_signal(cx, None).0,
+        refresh_seconds: create_signal(cx, 300).0,
+    });
+
+    let display_resource = create_resource(
+        cx,
+        || (),
+        async move |_| -> Option<DisplayState> {
+            let access_token = (display_context.access_token)()?;
+            let client = homeworker::HomeworkerClient::new(
+                access_token.clone(),
+                "desktop-timetable".to_owned(),
+            );
+            let now = (display_context.now)()?;
+
+            let mut courses = (display_context.courses)();
+            if courses.is_empty() {
+                courses = client
+                    .get_course_memberships()
+                    .await
+                    .ok()?
+                    .iter()
+                    .map(|membership| membership.course_id)
+                    .collect();
+            }
+
+            let mut state: Option<DisplayState> = None;
+            for course_id in courses {
+                // Not limited to a week, so the next day can be shown on fridays
+                let source = HomeworkerSource::new(access_token.clone(), Some(course_id));
+                // A course that fails is left out, so the others are still shown
+                let timetable = match source.full_timetable().await {
+                    Ok(timetable) => timetable,
+                    Err(e) => {
+                        log::error!("Fetching the timetable of course {course_id} failed: {:?}", e);
+                        continue;
+                    }
+                };
+                let course_name = source
+                    .course_name()
+                    .await
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| course_id.to_string());
+
+                if state.is_none() {
+                    let Some(date) = display_date(&timetable, now) else {
+                        continue;
+                    };
+                    state = Some(DisplayState {
+                        date,
+                        timetable: Timetable {
+                            times: timetable.times.clone(),
+                            columns: vec![],
+                            week_type: timetable.week_type,
+                        },
+                    });
+                }
+                let current = state.as_mut().unwrap();
+
+                let period_count = timetable.period_count();
+                let column = timetable
+                    .columns
+                    .into_iter()
+                    .find(|column| column.date == Some(current.date));
+                current.timetable.columns.push(match column {
+                    Some(column) => TimetableColumn {
+                        name: course_name,
+                        date: None,
+                        ..column
+                    },
+                    None => TimetableColumn {
+                        name: course_name,
+                        date: None,
+                        lessons: vec![None; period_count],
+                        appointments: vec![],
+                        holiday: None,
+                    },
+                });
+            }
+
+            state
+        },
+    );
+
+    let locale = use_locale(cx);
+    let refresh = move || (display_context.refresh_seconds)().to_string();
+
+    // Name overlap with types::timetable::Timetable and components::timetable::Timetable
+    use crate::components::timetable::*;
+
+    view! { cx,
+        // Reloading the page refetches the timetable and moves on to the next day after school
+        <meta http-equiv="refresh" content=refresh/>
+        <Style>
+            // Layout sizes are in rem, so scaling the root font scales the whole grid to the screen
+            "html { font-size: 1.1vw; cursor: none; }"
+        </Style>
+        <div class="font-rubik bg-zinc-900 text-white w-screen h-screen flex flex-col justify-center items-center gap-8 overflow-hidden select-none">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (display_resource.read(), locale.read()) {
+                    (Some(Some(state)), Some(locale)) => Some(view! { cx,
+                        <h1 class="text-4xl">{display_heading(locale, state.date)}</h1>
+                        <Timetable state=state.timetable locale=locale/>
+                    }.into_view(cx)),
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
+
+fn display_heading(locale: Locale, date: Date) -> String {
+    format!("{} {}", locale.weekday(date.weekday()), locale.format_date(date))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use super::*;
+    use crate::{sources::default_times, test_util::column};
+
+    const FRIDAY: Date = date!(2023 - 01 - 20);
+    const MONDAY: Date = date!(2023 - 01 - 23);
+
+    /// Friday and the monday after, school ends at 15:35
+    fn timetable() -> Timetable {
+        let times = default_times();
+        let lessons = vec![None; times.concat().len()];
+        Timetable {
+            times,
+            columns: vec![column(FRIDAY, lessons.clone()), column(MONDAY, lessons)],
+            week_type: None,
+        }
+    }
+
+    #[test]
+    fn today_is_shown_until_school_ends() {
+        assert_eq!(display_date(&timetable(), datetime!(2023-01-20 7:00)), Some(FRIDAY));
+        assert_eq!(display_date(&timetable(), datetime!(2023-01-20 15:35)), Some(FRIDAY));
+    }
+
+    #[test]
+    fn fridays_move_on_to_monday_after_school() {
+        assert_eq!(display_date(&timetable(), datetime!(2023-01-20 15:36)), Some(MONDAY));
+        // Over the weekend, until monday is over
+        assert_eq!(display_date(&timetable(), datetime!(2023-01-22 12:00)), Some(MONDAY));
+        assert_eq!(display_date(&timetable(), datetime!(2023-01-23 12:00)), Some(MONDAY));
+    }
+
+    #[test]
+    fn the_last_day_stays_once_all_are_over() {
+        assert_eq!(display_date(&timetable(), datetime!(2023-01-23 16:00)), Some(MONDAY));
+        assert_eq!(display_date(&timetable(), datetime!(2023-02-01 9:00)), Some(MONDAY));
+    }
+
+    #[test]
+    fn columns_without_dates_have_nothing_to_show() {
+        let mut timetable = timetable();
+        for column in &mut timetable.columns {
+            column.date = None;
+        }
+
+        assert_eq!(display_date(&timetable, datetime!(2023-01-20 9:00)), None);
+    }
+}
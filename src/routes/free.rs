@@ -0,0 +1,309 @@
+use std::collections::BTreeSet;
+
+use leptos::*;
+use leptos_router::*;
+use time::{Date, Duration};
+
+use crate::{
+    api::{format_iso_week, parse_iso_week},
+    app::HomeworkerContext,
+    i18n::{use_locale, Locale},
+    sources::{current_week, today, SourcesContext},
+    types::{availability::Availability, timetable::*},
+};
+
+/// Course id, course name and timetable
+type CourseTimetable = (u32, String, Timetable);
+
+/// Every course is loaded from the sources, so only this many can be compared at once
+pub const MAX_COURSES: usize = 20;
+
+/// Timetables of several courses in `week`, loaded like the dashboard with the configured sources
+/// and the settings of the user, but with each course as the default course. `courses` are comma
+/// separated ids, `week` is formatted like `2023-W08`. Courses that fail to load are left out.
+/// At most [`MAX_COURSES`] can be requested, and only a few are loaded at the same time.
+#[server(FetchCourseTimetables, "/serverfn")]
+pub async fn fetch_course_timetables(
+    cx: Scope,
+    courses: String,
+    week: String,
+) -> Result<Vec<(u32, Timetable)>, ServerFnError> {
+    use futures::StreamExt;
+
+    use crate::{
+        routes::settings::use_settings,
+        sources::{holidays::SchoolCalendar, load_configured, SourceConfig},
+        types::settings::UserSettings,
+    };
+
+    const CONCURRENT_COURSES: usize = 4;
+
+    let week =
+        parse_iso_week(&week).ok_or_else(|| ServerFnError::Args(format!("invalid week {week}")))?;
+    let access_token = use_context::<HomeworkerContext>(cx).and_then(|hw| (hw.access_token)());
+    let (configs, calendar) = use_context::<SourcesContext>(cx)
+        .map(|sources| ((sources.configs)(), (sources.calendar)()))
+        .unwrap_or((SourceConfig::defaults(), SchoolCalendar::default()));
+    let settings = (use_settings(cx).settings)();
+
+    let courses: BTreeSet<u32> = courses
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect();
+    if courses.len() > MAX_COURSES {
+        return Err(ServerFnError::Args(format!("at most {MAX_COURSES} courses")));
+    }
+    let timetables: Vec<_> = futures::stream::iter(courses.into_iter().map(|course| {
+        let settings = UserSettings {
+            default_course: Some(course),
+            ..settings.clone()
+        };
+        let (configs, calendar, access_token) = (&configs, &calendar, access_token.clone());
+        async move {
+            let timetable = load_configured(configs, calendar, access_token, &settings, week).await;
+            (course, timetable)
+        }
+    }))
+    .buffered(CONCURRENT_COURSES)
+    .collect()
+    .await;
+
+    Ok(timetables
+        .into_iter()
+        .filter_map(|(course, timetable)| match timetable {
+            Ok(timetable) => Some((course, timetable)),
+            Err(e) => {
+                log::error!("Loading the timetable of course {course} failed: {:?}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+fn day_headers(cx: Scope, availability: &Availability, locale: Locale) -> Vec<impl IntoView> {
+    availability
+        .days
+        .iter()
+        .map(|day| {
+            view! { cx,
+                <th class="px-2 font-normal">
+                    {format!("{} {}", locale.weekday(day.weekday()), locale.format_date(*day))}
+                </th>
+            }
+        })
+        .collect()
+}
+
+/// Periods where more courses are free are greener, periods where all of them are free are outlined
+fn free_heatmap(cx: Scope, availability: &Availability, locale: Locale) -> impl IntoView {
+    let rows: Vec<_> = availability
+        .times
+        .concat()
+        .iter()
+        .enumerate()
+        .map(|(position, period)| {
+            let cells: Vec<_> = (0..availability.days.len())
+                .map(|day| {
+                    let free = availability.free[day][position];
+                    let share = free as f32 / availability.timetables.max(1) as f32;
+                    let style = format!("background-color: rgba(34, 197, 94, {share:.2})");
+                    view! { cx,
+                        <td class="h-10 text-center rounded-lg" class:ring-2=availability.all_free(day, position) style=style>
+                            {format!("{free}/{}", availability.timetables)}
+                        </td>
+                    }
+                })
+                .collect();
+            view! { cx,
+                <tr>
+                    <td class="pr-2 text-sm">
+                        {format!("{} - {}", locale.format_time(period.start), locale.format_time(period.end))}
+                    </td>
+                    {cells}
+                </tr>
+            }
+        })
+        .collect();
+
+    view! { cx,
+        <table class="border-separate border-spacing-1">
+            <tr><th></th>{day_headers(cx, availability, locale)}</tr>
+            {rows}
+        </table>
+    }
+}
+
+/// One row per room with a square per period, green while the room is free
+fn room_table(cx: Scope, availability: &Availability, locale: Locale) -> impl IntoView {
+    let period = locale.strings().period;
+    let positions = availability.times.concat().len();
+
+    let rows: Vec<_> = availability
+        .rooms
+        .iter()
+        .map(|room| {
+            let cells: Vec<_> = (0..availability.days.len())
+                .map(|day| {
+                    let squares: Vec<_> = (0..positions)
+                        .map(|position| {
+                            let free = !availability.occupied[day][position].contains(room);
+                            view! { cx,
+                                <div class="w-3 h-3 rounded-sm bg-zinc-400" class:bg-green-500=free title=format!("{period} {}", position + 1)></div>
+                            }
+                        })
+                        .collect();
+                    view! { cx, <td><div class="flex gap-0.5">{squares}</div></td> }
+                })
+                .collect();
+            view! { cx,
+                <tr>
+                    <td class="pr-2 text-sm">{room.clone()}</td>
+                    {cells}
+                </tr>
+            }
+        })
+        .collect();
+
+    view! { cx,
+        <table class="border-separate border-spacing-1">
+            <tr><th></th>{day_headers(cx, availability, locale)}</tr>
+            {rows}
+        </table>
+    }
+}
+
+/// Free periods and rooms across the courses of the user, e.g. to find a time to meet or an empty
+/// room. Courses can be left out with the checkboxes, `?week=2023-W08` selects the week.
+#[component]
+pub fn free_periods_page(cx: Scope) -> impl IntoView {
+    // Default used when no context is provided during hydration
+    let hw_context = use_context::<HomeworkerContext>(cx).unwrap_or(HomeworkerContext {
+        client_id: create_signal(cx, "".to_owned()).0,
+        access_token: create_signal(cx, None).0,
+        refresh_token: create_signal(cx, None).0,
+    });
+
+    let sources_context = use_context::<SourcesContext>(cx).unwrap_or(SourcesContext {
+        configs: create_signal(cx, vec![]).0,
+        calendar: create_signal(cx, Default::default()).0,
+    });
+    let query = use_query_map(cx);
+
+    // Only fetched once, changing the week keeps the courses
+    let memberships_resource = create_resource(
+        cx,
+        || (),
+        async move |_| -> Option<Vec<(u32, String)>> {
+            let client = homeworker::HomeworkerClient::new(
+                (hw_context.access_token)()?,
+                "desktop-timetable".to_owned(),
+            );
+            let memberships = client.get_course_memberships().await.ok()?;
+            Some(
+                memberships
+                    .into_iter()
+                    .map(|membership| (membership.course_id, membership.course.name))
+                    .collect(),
+            )
+        },
+    );
+
+    let timetables_resource = create_resource(
+        cx,
+        move || {
+            let week = query().get("week").and_then(|week| parse_iso_week(week));
+            (week.unwrap_or(current_week(today())), memberships_resource.read().flatten())
+        },
+        async move |(week, memberships)| -> Option<(Date, Option<WeekType>, Vec<CourseTimetable>)> {
+            let memberships: Vec<_> = memberships?.into_iter().take(MAX_COURSES).collect();
+            let courses = memberships
+                .iter()
+                .map(|(id, _)| id.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            let timetables = fetch_course_timetables(cx, courses, format_iso_week(week))
+                .await
+                .map_err(|e| log::error!("Fetching the course timetables failed: {:?}", e))
+                .ok()?;
+
+            let timetables = memberships
+                .into_iter()
+                .filter_map(|(id, name)| {
+                    let (_, timetable) = timetables.iter().find(|(course, _)| *course == id)?;
+                    Some((id, name, timetable.clone()))
+                })
+                .collect();
+
+            // Counted by the school calendar, so weeks of holidays are skipped
+            let week_type = (sources_context.calendar)().week_type(week);
+
+            Some((week, week_type, timetables))
+        },
+    );
+
+    // Courses are left out in the browser, without fetching again
+    let (deselected, set_deselected) = create_signal(cx, BTreeSet::<u32>::new());
+
+    let locale = use_locale(cx);
+
+    view! { cx,
+        <div class="font-rubik dark:bg-zinc-900 dark:text-white min-h-screen p-8 flex flex-col items-center gap-6">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (timetables_resource.read(), locale.read()) {
+                    (Some(Some((week, week_type, courses))), Some(locale)) => {
+                        let strings = locale.strings();
+                        let selected: Vec<Timetable> = courses
+                            .iter()
+                            .filter(|(id, _, _)| !deselected().contains(id))
+                            .map(|(_, _, timetable)| timetable.clone())
+                            .collect();
+                        let availability = Availability::new(&selected);
+
+                        let checkboxes: Vec<_> = courses
+                            .into_iter()
+                            .map(|(id, name, _)| view! { cx,
+                                <label class="flex items-center gap-1">
+                                    <input
+                                        type="checkbox"
+                                        prop:checked=move || !deselected().contains(&id)
+                                        on:change=move |_| set_deselected.update(|deselected| {
+                                            if !deselected.remove(&id) {
+                                                deselected.insert(id);
+                                            }
+                                        })
+                                    />
+                                    {name}
+                                </label>
+                            })
+                            .collect();
+
+                        let previous = format!("?week={}", format_iso_week(week - Duration::weeks(1)));
+                        let next = format!("?week={}", format_iso_week(week + Duration::weeks(1)));
+
+                        Some(view! { cx,
+                            <h1 class="text-2xl">{strings.free_title}</h1>
+                            <div class="flex gap-4 text-sm">
+                                <a href=previous class="underline">{strings.week_previous}</a>
+                                <span>{format_iso_week(week)}</span>
+                                {week_type.map(|week_type| view! { cx, <span>{locale.week_type(week_type)}</span> })}
+                                <a href=next class="underline">{strings.week_next}</a>
+                            </div>
+                            <div class="flex flex-wrap gap-4">
+                                <span>{strings.free_courses}":"</span>
+                                {checkboxes}
+                            </div>
+                            <h2 class="text-xl">{strings.free_heatmap}</h2>
+                            {free_heatmap(cx, &availability, locale)}
+                            <h2 class="text-xl">{strings.free_rooms}</h2>
+                            <p class="text-sm">{strings.free_rooms_hint}</p>
+                            {room_table(cx, &availability, locale)}
+                        }.into_view(cx))
+                    }
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
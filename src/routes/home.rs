@@ -1,10 +1,21 @@
 use leptos::*;
 use leptos_router::*;
 
+use crate::{components::locale_switcher::*, i18n::use_locale};
+
 #[component]
 pub fn home_page(cx: Scope) -> impl IntoView {
+    let locale = use_locale(cx);
+
     view! { cx,
-        <p>"Total tolle homepage"</p>
-        <A href="/app">"Zum Dashboard"</A>
+        <Suspense fallback=move || { None::<View> }>
+            {move || {
+                locale.read().map(|locale| view! { cx,
+                    <p>{locale.strings().home_title}</p>
+                    <A href="/app">{locale.strings().home_dashboard_link}</A>
+                    <LocaleSwitcher current=locale/>
+                }.into_view(cx))
+            }}
+        </Suspense>
     }
 }
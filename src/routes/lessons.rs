@@ -0,0 +1,86 @@
+use futures::future::join_all;
+use leptos::*;
+
+use crate::{
+    app::HomeworkerContext,
+    i18n::use_locale,
+    routes::settings::use_settings,
+    sources::{
+        homeworker::HomeworkerSource, load_current_week, today, SourceError, TimetableSource,
+    },
+    types::timetable::*,
+};
+
+/// All lessons of the user across their course memberships, mainly for teachers
+#[component]
+pub fn my_lessons_page(cx: Scope) -> impl IntoView {
+    // Default used when no context is provided during hydration
+    let hw_context = use_context::<HomeworkerContext>(cx).unwrap_or(HomeworkerContext {
+        client_id: create_signal(cx, "".to_owned()).0,
+        access_token: create_signal(cx, None).0,
+        refresh_token: create_signal(cx, None).0,
+    });
+
+    let settings_context = use_settings(cx);
+
+    let timetable_resource = create_resource(
+        cx,
+        || (),
+        async move |_| -> Option<Timetable> {
+            let access_token = (hw_context.access_token)()?;
+            let client = homeworker::HomeworkerClient::new(
+                access_token.clone(),
+                "desktop-timetable".to_owned(),
+            );
+            let settings = (settings_context.settings)();
+            let memberships = client.get_course_memberships().await.ok()?;
+
+            let (memberships, access_token, overrides) =
+                (&memberships, &access_token, &settings.overrides);
+            let load = |week| async move {
+                let timetables = join_all(memberships.iter().map(|membership| {
+                    let source =
+                        HomeworkerSource::new(access_token.clone(), Some(membership.course_id))
+                            .with_overrides(overrides.clone());
+                    let name = membership.course.name.clone();
+                    async move { (name, source.timetable(week).await) }
+                }))
+                .await;
+
+                let timetables = timetables
+                    .into_iter()
+                    .filter_map(|(name, timetable)| match timetable {
+                        Ok(timetable) => Some((name, timetable)),
+                        Err(e) => {
+                            log::error!("Fetching the timetable of {name} failed: {:?}", e);
+                            None
+                        }
+                    })
+                    .collect();
+                Ok::<_, SourceError>(Timetable::merge(timetables))
+            };
+            let (week, timetable) = load_current_week(today(), load).await.ok()?;
+
+            Some(timetable.with_weekdays(week, &settings.weekdays))
+        },
+    );
+
+    let locale = use_locale(cx);
+
+    // Name overlap with types::timetable::Timetable and components::timetable::Timetable
+    use crate::components::timetable::*;
+
+    view! { cx,
+        // TODO: Remove fullscreen div, put classes on body
+        <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen h-screen flex justify-center items-center overflow-auto">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (timetable_resource.read(), locale.read()) {
+                    (Some(Some(timetable)), Some(locale)) => Some(view! { cx, <Timetable state=timetable locale=locale/> }.into_view(cx)),
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
@@ -1,6 +1,6 @@
 use leptos::*;
 
-use crate::app::HomeworkerContext;
+use crate::{app::HomeworkerContext, components::locale_switcher::*, i18n::use_locale};
 
 #[component]
 pub fn login_page(cx: Scope) -> impl IntoView {
@@ -46,13 +46,16 @@ pub fn login_page(cx: Scope) -> impl IntoView {
         },
     );
 
+    let locale = use_locale(cx);
+
     view! { cx,
         // TODO: Remove fullscreen div, put classes on body
         <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen h-screen flex justify-center items-center">
         <Transition fallback=move || { None::<View> }>
             {move || {
-                match login_status.read() {
-                    Some(value) => {
+                match (login_status.read(), locale.read()) {
+                    (Some(value), Some(locale)) => {
+                        let strings = locale.strings();
                         if value {
                             // Redirect to app
                             Some(view! { cx,
@@ -60,24 +63,25 @@ pub fn login_page(cx: Scope) -> impl IntoView {
                                     "window.location.href = \"/app\""
                                 </script>
                                 <meta http-equiv="refresh" content="0; url=/app" />
-                                <a href="/app">"Redirect"</a>
+                                <a href="/app">{strings.login_redirect}</a>
                             }.into_view(cx))
                         } else {
                             // Show login page
                             Some(view! { cx,
                                 <div class="w-96 p-4 border rounded-xl border-black dark:border-zinc-400">
-                                    <h1 class="text-center mb-4">"Anmelden mit Homeworker"</h1>
-                                    <p class="mb-4">"Um den Stundenplan abzurufen, musst du dich mit Homeworker anmelden"</p>
+                                    <h1 class="text-center mb-4">{strings.login_title}</h1>
+                                    <p class="mb-4">{strings.login_description}</p>
                                     <a href=login_url.read()>
                                         <div class="h-9 bg-sky-500 hover:bg-sky-400 rounded-xl flex justify-center items-center">
-                                            "Anmelden"
+                                            {strings.login_button}
                                         </div>
                                     </a>
+                                    <LocaleSwitcher current=locale/>
                                 </div>
                             }.into_view(cx))
                         }
                     },
-                    None => {
+                    _ => {
                         None::<View>
                     }
                 }
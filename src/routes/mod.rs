@@ -1,3 +1,11 @@
+pub mod api_token;
 pub mod dashboard;
+pub mod display;
+pub mod free;
 pub mod home;
+pub mod lessons;
 pub mod login;
+pub mod print;
+pub mod settings;
+pub mod subjects;
+pub mod widget;
@@ -0,0 +1,109 @@
+use leptos::*;
+use leptos_router::*;
+
+use crate::{
+    app::HomeworkerContext,
+    i18n::use_locale,
+    routes::settings::use_settings,
+    sources::{
+        homeworker::HomeworkerSource, load_current_week, today, SourcesContext, TimetableSource,
+    },
+    types::timetable::*,
+};
+
+/// Black and white A4 landscape version of the dashboard. `/export/timetable.pdf` renders the same
+/// page on the server.
+#[component]
+pub fn print_page(cx: Scope) -> impl IntoView {
+    // Default used when no context is provided during hydration
+    let hw_context = use_context::<HomeworkerContext>(cx).unwrap_or(HomeworkerContext {
+        client_id: create_signal(cx, "".to_owned()).0,
+        access_token: create_signal(cx, None).0,
+        refresh_token: create_signal(cx, None).0,
+    });
+
+    let settings_context = use_settings(cx);
+    let sources_context = use_context::<SourcesContext>(cx).unwrap_or(SourcesContext {
+        configs: create_signal(cx, vec![]).0,
+        calendar: create_signal(cx, Default::default()).0,
+    });
+    let query = use_query_map(cx);
+
+    let timetable_resource = create_resource(
+        cx,
+        move || query().get("course").and_then(|id| id.parse::<u32>().ok()),
+        async move |course_id| -> Option<(String, Timetable)> {
+            let settings = (settings_context.settings)();
+            let source = HomeworkerSource::new(
+                (hw_context.access_token)()?,
+                course_id.or(settings.default_course),
+            )
+            .with_overrides(settings.overrides.clone());
+            let (week, timetable) = load_current_week(today(), |week| source.timetable(week))
+                .await
+                .ok()?;
+            let timetable = timetable.with_weekdays(week, &settings.weekdays);
+            // Holidays have nothing to print
+            let timetable = settings
+                .overrides
+                .for_week_type((sources_context.calendar)().apply(timetable, week))
+                .without_holidays();
+            let course_name = source.course_name().await.ok().flatten()?;
+
+            Some((course_name, timetable))
+        },
+    );
+
+    let locale = use_locale(cx);
+
+    // The exports show the same course as the page
+    let export_link =
+        move |format: &str| match query().get("course").and_then(|id| id.parse::<u32>().ok()) {
+            Some(course) => format!("/export/timetable.{format}?course={course}"),
+            None => format!("/export/timetable.{format}"),
+        };
+
+    // Name overlap with types::timetable::Timetable and components::timetable::Timetable
+    use crate::components::timetable::*;
+
+    view! { cx,
+        <Style>
+            "@page { size: A4 landscape; margin: 10mm; }
+            @media print {
+                html { font-size: 9px; }
+                .print-hidden { display: none; }
+                * { color: black !important; background: white !important; border-color: black !important; }
+            }"
+        </Style>
+        <div class="font-rubik p-4 flex flex-col items-center gap-4">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (timetable_resource.read(), locale.read()) {
+                    (Some(Some((course_name, timetable))), Some(locale)) => {
+                        let title = locale.timetable_title(&course_name, timetable.date_range());
+                        Some(view! { cx,
+                            <h1>{title}</h1>
+                            <Timetable state=timetable locale=locale/>
+                            <div class="print-hidden flex gap-4">
+                                <button class="h-9 px-4 bg-sky-500 hover:bg-sky-400 rounded-xl" on:click=move |_| { _ = window().print(); }>
+                                    {locale.strings().print_button}
+                                </button>
+                                <a href=export_link("pdf") rel="external" class="h-9 px-4 border border-black rounded-xl flex items-center">
+                                    "PDF"
+                                </a>
+                                <a href=export_link("png") rel="external" class="h-9 px-4 border border-black rounded-xl flex items-center">
+                                    "PNG"
+                                </a>
+                                <a href=export_link("svg") rel="external" class="h-9 px-4 border border-black rounded-xl flex items-center">
+                                    "SVG"
+                                </a>
+                            </div>
+                        }.into_view(cx))
+                    }
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
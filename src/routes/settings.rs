@@ -0,0 +1,393 @@
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+use time::Weekday;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{app::HomeworkerContext, i18n::use_locale, types::settings::UserSettings};
+
+/// Provided by the server with the stored settings of the current user
+#[derive(Clone, Copy)]
+pub struct SettingsContext {
+    pub settings: ReadSignal<UserSettings>,
+}
+
+/// Uses the default when no context is provided during hydration
+pub fn use_settings(cx: Scope) -> SettingsContext {
+    use_context::<SettingsContext>(cx).unwrap_or(SettingsContext {
+        settings: create_signal(cx, UserSettings::default()).0,
+    })
+}
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+    Weekday::Sunday,
+];
+
+/// Comma separated numbers from monday as 1, in the order of the week
+fn format_weekdays(weekdays: &[Weekday]) -> String {
+    WEEKDAYS
+        .iter()
+        .filter(|day| weekdays.contains(day))
+        .map(|day| day.number_from_monday().to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Outcome of `save_settings` for the message below the form. Nothing is saved on the invalid ones.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum SaveResult {
+    Saved,
+    /// Saved, the new email address waits for its confirmation
+    ConfirmationSent,
+    InvalidWebhook,
+    ConfirmationFailed,
+}
+
+#[cfg(feature = "ssr")]
+fn parse_weekdays(weekdays: &str) -> Vec<Weekday> {
+    weekdays
+        .split(',')
+        .filter_map(|number| number.trim().parse::<usize>().ok())
+        .filter_map(|number| WEEKDAYS.get(number.checked_sub(1)?).copied())
+        .collect()
+}
+
+#[server(GetSettings, "/serverfn")]
+pub async fn get_settings(cx: Scope) -> Result<UserSettings, ServerFnError> {
+    let context = use_context::<crate::store::StoreContext>(cx)
+        .ok_or_else(|| ServerFnError::ServerError("Settings store missing".to_owned()))?;
+    let user_id = context
+        .user_id
+        .ok_or_else(|| ServerFnError::ServerError("Not logged in".to_owned()))?;
+
+    Ok(context.store.get(user_id).await)
+}
+
+/// Asks the user to confirm a new email address, which is only used for alerts once confirmed
+#[cfg(feature = "ssr")]
+async fn request_email_confirmation(
+    store: &crate::store::SettingsStore,
+    user_id: u32,
+    address: String,
+    locale: crate::i18n::Locale,
+) -> Result<(), ServerFnError> {
+    use crate::alerts::email::{confirmation_token, EmailSender};
+
+    let sender = EmailSender::from_env()
+        .ok_or_else(|| ServerFnError::ServerError("Email alerts aren't configured".to_owned()))?;
+    let (token, hash) = confirmation_token();
+    sender
+        .send_confirmation(&address, &token, locale)
+        .await
+        .map_err(|e| ServerFnError::ServerError(format!("{e:?}")))?;
+
+    let confirmation = crate::store::EmailConfirmation {
+        user_id,
+        address,
+        created: crate::sources::today(),
+    };
+    store
+        .add_email_confirmation(hash, confirmation)
+        .await
+        .map_err(|e| ServerFnError::ServerError(format!("{e:?}")))
+}
+
+/// An empty `default_course` resets it to the first course membership. `notifications` and
+/// `change_alerts` are checkboxes, so they are only sent when checked. `weekdays` is the hidden
+/// field kept up to date by the weekday checkboxes, without any days the default is kept.
+///
+/// Webhooks have to be public HTTPS URLs. A new email address is kept apart until it is confirmed.
+/// Both are checked before anything is saved, so the settings stay as they were on failure.
+#[server(SaveSettings, "/serverfn")]
+pub async fn save_settings(
+    cx: Scope,
+    default_course: String,
+    notifications: Option<String>,
+    minutes_before: String,
+    change_alerts: Option<String>,
+    webhook_url: String,
+    email: String,
+    weekdays: String,
+) -> Result<SaveResult, ServerFnError> {
+    let context = use_context::<crate::store::StoreContext>(cx)
+        .ok_or_else(|| ServerFnError::ServerError("Settings store missing".to_owned()))?;
+    let user_id = context
+        .user_id
+        .ok_or_else(|| ServerFnError::ServerError("Not logged in".to_owned()))?;
+
+    let default_course = default_course.parse().ok();
+    let minutes_before = minutes_before.parse::<u8>().unwrap_or(5).clamp(1, 60);
+    let non_empty = |value: String| Some(value.trim().to_owned()).filter(|v| !v.is_empty());
+    let weekdays = Some(parse_weekdays(&weekdays))
+        .filter(|weekdays| !weekdays.is_empty())
+        .unwrap_or_else(|| UserSettings::default().weekdays);
+    let locale = use_context::<crate::i18n::LocaleContext>(cx)
+        .map(|context| (context.locale)())
+        .unwrap_or_default();
+
+    let webhook_url = non_empty(webhook_url);
+    if let Some(url) = &webhook_url {
+        if let Err(e) = crate::alerts::webhook::public_webhook(url).await {
+            log::info!("Rejected webhook: {:?}", e);
+            return Ok(SaveResult::InvalidWebhook);
+        }
+    }
+    // The confirmed address stays in use until the new one is confirmed
+    let email = non_empty(email);
+    let confirmed_email = context.store.get(user_id).await.notifications.email;
+    let new_email = email
+        .clone()
+        .filter(|email| Some(email) != confirmed_email.as_ref());
+    if let Some(address) = new_email.clone() {
+        if let Err(e) = request_email_confirmation(&context.store, user_id, address, locale).await {
+            log::error!("Sending the email confirmation failed: {:?}", e);
+            return Ok(SaveResult::ConfirmationFailed);
+        }
+    }
+
+    context
+        .store
+        .update(user_id, |settings| {
+            settings.default_course = default_course;
+            settings.notifications.browser = notifications.is_some();
+            settings.notifications.minutes_before = minutes_before;
+            settings.notifications.changes = change_alerts.is_some();
+            settings.notifications.webhook_url = webhook_url;
+            if email.is_none() {
+                settings.notifications.email = None;
+            }
+            settings.weekdays = weekdays;
+            settings.locale = Some(locale);
+        })
+        .await
+        .map_err(|e| ServerFnError::ServerError(format!("{e:?}")))?;
+
+    // The background check needs the refresh token, but only while alerts are enabled
+    let alert_token = change_alerts.and(context.alert_token);
+    context
+        .store
+        .set_alert_token(user_id, alert_token)
+        .await
+        .map_err(|e| ServerFnError::ServerError(format!("{e:?}")))?;
+
+    match new_email {
+        Some(_) => Ok(SaveResult::ConfirmationSent),
+        None => Ok(SaveResult::Saved),
+    }
+}
+
+/// Public VAPID key browsers subscribe to push with, `None` if push isn't configured
+#[server(PushPublicKey, "/serverfn")]
+pub async fn push_public_key(cx: Scope) -> Result<Option<String>, ServerFnError> {
+    Ok(std::env::var("VAPID_PUBLIC_KEY").ok())
+}
+
+/// `subscription` is the JSON of a `PushSubscription`
+#[server(SavePushSubscription, "/serverfn")]
+pub async fn save_push_subscription(cx: Scope, subscription: String) -> Result<(), ServerFnError> {
+    let context = use_context::<crate::store::StoreContext>(cx)
+        .ok_or_else(|| ServerFnError::ServerError("Settings store missing".to_owned()))?;
+    let user_id = context
+        .user_id
+        .ok_or_else(|| ServerFnError::ServerError("Not logged in".to_owned()))?;
+    let subscription: crate::types::settings::PushSubscription =
+        serde_json::from_str(&subscription)
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))?;
+    // The server posts to the endpoint, so it has to be public like a webhook
+    crate::alerts::webhook::public_webhook(&subscription.endpoint)
+        .await
+        .map_err(|e| ServerFnError::Args(format!("{e:?}")))?;
+
+    context
+        .store
+        .update(user_id, |settings| {
+            let subscriptions = &mut settings.notifications.push_subscriptions;
+            if !subscriptions.contains(&subscription) {
+                subscriptions.push(subscription);
+            }
+        })
+        .await
+        .map_err(|e| ServerFnError::ServerError(format!("{e:?}")))?;
+    Ok(())
+}
+
+/// Registers `public/sw.js` and subscribes to push, returns the subscription as JSON
+async fn subscribe_push(public_key: &str) -> Result<String, JsValue> {
+    let container = window().navigator().service_worker();
+    JsFuture::from(container.register("/sw.js")).await?;
+    let registration: web_sys::ServiceWorkerRegistration =
+        JsFuture::from(container.ready()?).await?.unchecked_into();
+
+    let mut options = web_sys::PushSubscriptionOptionsInit::new();
+    options
+        .user_visible_only(true)
+        .application_server_key(Some(&JsValue::from_str(public_key)));
+    let subscription = JsFuture::from(
+        registration
+            .push_manager()?
+            .subscribe_with_options(&options)?,
+    )
+    .await?;
+
+    js_sys::JSON::stringify(&subscription)?
+        .as_string()
+        .ok_or(JsValue::NULL)
+}
+
+#[component]
+pub fn settings_page(cx: Scope) -> impl IntoView {
+    // Default used when no context is provided during hydration
+    let hw_context = use_context::<HomeworkerContext>(cx).unwrap_or(HomeworkerContext {
+        client_id: create_signal(cx, "".to_owned()).0,
+        access_token: create_signal(cx, None).0,
+        refresh_token: create_signal(cx, None).0,
+    });
+    let settings_context = use_settings(cx);
+
+    let settings_resource = create_resource(
+        cx,
+        || (),
+        async move |_| -> Option<(Vec<(u32, String)>, UserSettings, Option<String>)> {
+            let client = homeworker::HomeworkerClient::new(
+                (hw_context.access_token)()?,
+                "desktop-timetable".to_owned(),
+            );
+            let courses = client
+                .get_course_memberships()
+                .await
+                .ok()?
+                .into_iter()
+                .map(|membership| (membership.course_id, membership.course.name))
+                .collect();
+
+            let push_key = push_public_key(cx).await.ok().flatten();
+
+            Some((courses, (settings_context.settings)(), push_key))
+        },
+    );
+
+    let save = create_server_action::<SaveSettings>(cx);
+    let (push_enabled, set_push_enabled) = create_signal(cx, false);
+    let on_push = move |_| {
+        let Some(Some((_, _, Some(public_key)))) = settings_resource.read() else {
+            return;
+        };
+        spawn_local(async move {
+            match subscribe_push(&public_key).await {
+                Ok(subscription) => match save_push_subscription(cx, subscription).await {
+                    Ok(()) => set_push_enabled(true),
+                    Err(e) => log::error!("Saving the push subscription failed: {:?}", e),
+                },
+                Err(e) => log::error!("Subscribing to push failed: {:?}", e),
+            }
+        });
+    };
+    let locale = use_locale(cx);
+
+    view! { cx,
+        // TODO: Remove fullscreen div, put classes on body
+        <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen h-screen flex justify-center items-center">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (settings_resource.read(), locale.read()) {
+                    (Some(Some((courses, settings, push_key))), Some(locale)) => {
+                        let strings = locale.strings();
+                        let options: Vec<_> = courses.into_iter().map(|(id, name)| view! { cx,
+                            <option value=id.to_string() selected=settings.default_course == Some(id)>{name}</option>
+                        }).collect();
+                        let (weekdays, set_weekdays) = create_signal(cx, settings.weekdays.clone());
+                        let weekday_boxes: Vec<_> = WEEKDAYS.into_iter().map(|day| view! { cx,
+                            <label class="flex flex-col items-center text-sm">
+                                {locale.weekday(day).chars().take(2).collect::<String>()}
+                                <input type="checkbox" checked=settings.weekdays.contains(&day) on:change=move |ev| {
+                                    let checked = event_target_checked(&ev);
+                                    set_weekdays.update(|weekdays| {
+                                        weekdays.retain(|other| *other != day);
+                                        if checked {
+                                            weekdays.push(day);
+                                        }
+                                    });
+                                }/>
+                            </label>
+                        }).collect();
+
+                        Some(view! { cx,
+                            <div class="w-[32rem] p-4 border rounded-xl border-black dark:border-zinc-400">
+                                <h1 class="text-center mb-4">{strings.settings_title}</h1>
+                                <ActionForm action=save>
+                                    <label class="flex justify-between items-center mb-4">
+                                        {strings.settings_default_course}
+                                        <select name="default_course" class="w-64 px-2 rounded-lg border border-black dark:border-zinc-400 bg-transparent">
+                                            <option value="">{strings.settings_first_course}</option>
+                                            {options}
+                                        </select>
+                                    </label>
+                                    <div class="flex justify-between items-center mb-4">
+                                        {strings.settings_weekdays}
+                                        <input type="hidden" name="weekdays" value=move || format_weekdays(&weekdays())/>
+                                        <div class="flex gap-2">{weekday_boxes}</div>
+                                    </div>
+                                    <label class="flex justify-between items-center mb-4">
+                                        {strings.settings_notifications}
+                                        // Asking when the box is checked, browsers only allow it after user interaction
+                                        <input type="checkbox" name="notifications" checked=settings.notifications.browser on:change=move |ev| {
+                                            if event_target_checked(&ev) {
+                                                _ = web_sys::Notification::request_permission();
+                                            }
+                                        }/>
+                                    </label>
+                                    <label class="flex justify-between items-center mb-4">
+                                        {strings.settings_minutes_before}
+                                        <input type="number" name="minutes_before" min="1" max="60" value=settings.notifications.minutes_before.to_string() class="w-24 px-2 rounded-lg border border-black dark:border-zinc-400 bg-transparent"/>
+                                    </label>
+                                    <label class="flex justify-between items-center mb-4">
+                                        {strings.settings_change_alerts}
+                                        <input type="checkbox" name="change_alerts" checked=settings.notifications.changes/>
+                                    </label>
+                                    <label class="flex justify-between items-center mb-4">
+                                        {strings.settings_webhook}
+                                        <input type="url" name="webhook_url" value=settings.notifications.webhook_url.unwrap_or_default() class="w-64 px-2 rounded-lg border border-black dark:border-zinc-400 bg-transparent"/>
+                                    </label>
+                                    <label class="flex justify-between items-center mb-4">
+                                        {strings.settings_email}
+                                        <input type="email" name="email" value=settings.notifications.email.unwrap_or_default() class="w-64 px-2 rounded-lg border border-black dark:border-zinc-400 bg-transparent"/>
+                                    </label>
+                                    {push_key.map(|_| view! { cx,
+                                        <button type="button" class="w-full h-9 mb-4 border border-black dark:border-zinc-400 rounded-xl" on:click=on_push>
+                                            {move || match push_enabled() {
+                                                true => strings.settings_push_enabled,
+                                                false => strings.settings_push,
+                                            }}
+                                        </button>
+                                    })}
+                                    <button type="submit" class="w-full h-9 bg-sky-500 hover:bg-sky-400 rounded-xl">
+                                        {strings.save}
+                                    </button>
+                                </ActionForm>
+                                {move || save.value()().map(|result| view! { cx,
+                                    <p class="mt-2 text-center">{match result {
+                                        Ok(SaveResult::Saved) => strings.settings_saved,
+                                        Ok(SaveResult::ConfirmationSent) => strings.settings_email_confirm_sent,
+                                        Ok(SaveResult::InvalidWebhook) => strings.settings_webhook_invalid,
+                                        Ok(SaveResult::ConfirmationFailed) => strings.settings_email_failed,
+                                        Err(_) => strings.settings_save_failed,
+                                    }}</p>
+                                })}
+                                <a href="/app/subjects" class="block mt-4 text-center underline">{strings.subjects_title}</a>
+                            </div>
+                        }.into_view(cx))
+                    }
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
@@ -0,0 +1,149 @@
+use leptos::*;
+
+use crate::{
+    app::HomeworkerContext,
+    i18n::use_locale,
+    routes::settings::use_settings,
+    sources::{homeworker::HomeworkerSource, load_current_week, today, TimetableSource},
+    types::{
+        overrides::{format_color, SubjectOverrides},
+        timetable::{Subject, WeekType},
+    },
+};
+
+/// Color of subjects that only have an override, as their original color isn't known
+const UNKNOWN_COLOR: (u8, u8, u8) = (160, 160, 160);
+
+/// Lists the subjects of this week's timetable and the subjects with overrides, e.g. from courses
+/// without lessons this week. Saving posts the form to the server, which stores the overrides.
+#[component]
+pub fn subjects_page(cx: Scope) -> impl IntoView {
+    // Default used when no context is provided during hydration
+    let hw_context = use_context::<HomeworkerContext>(cx).unwrap_or(HomeworkerContext {
+        client_id: create_signal(cx, "".to_owned()).0,
+        access_token: create_signal(cx, None).0,
+        refresh_token: create_signal(cx, None).0,
+    });
+    let settings_context = use_settings(cx);
+
+    let subjects_resource = create_resource(
+        cx,
+        || (),
+        async move |_| -> Option<(Vec<(Subject, Option<(u8, u8, u8)>)>, SubjectOverrides)> {
+            let settings = (settings_context.settings)();
+            // Without overrides, so the subjects keep their original short names
+            let source =
+                HomeworkerSource::new((hw_context.access_token)()?, settings.default_course);
+            // Holidays have no lessons, the overrides are still listed
+            let lessons = load_current_week(today(), |week| source.timetable(week))
+                .await
+                .map(|(_, timetable)| timetable.columns)
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|c| c.lessons)
+                .flatten();
+
+            // With the original color, if it is known
+            let mut subjects: Vec<(Subject, Option<(u8, u8, u8)>)> = vec![];
+            for lesson in lessons {
+                if !subjects
+                    .iter()
+                    .any(|(s, _)| s.short_name == lesson.subject.short_name)
+                {
+                    let color = lesson.subject.color;
+                    subjects.push((lesson.subject, Some(color)));
+                }
+            }
+            for (short_name, o) in &settings.overrides.0 {
+                if !subjects.iter().any(|(s, _)| s.short_name == *short_name) {
+                    let subject = Subject {
+                        full_name: short_name.clone(),
+                        short_name: short_name.clone(),
+                        teacher: String::new(),
+                        room: String::new(),
+                        color: o.color.unwrap_or(UNKNOWN_COLOR),
+                    };
+                    // Without an original color, a stored color is always kept
+                    let original = o.color.is_none().then_some(UNKNOWN_COLOR);
+                    subjects.push((subject, original));
+                }
+            }
+            subjects.sort_by(|(a, _), (b, _)| a.short_name.cmp(&b.short_name));
+
+            Some((subjects, settings.overrides))
+        },
+    );
+
+    let locale = use_locale(cx);
+
+    view! { cx,
+        // TODO: Remove fullscreen div, put classes on body
+        <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen min-h-screen flex justify-center items-center">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (subjects_resource.read(), locale.read()) {
+                    (Some(Some((subjects, overrides))), Some(locale)) => {
+                        let strings = locale.strings();
+                        let rows: Vec<_> = subjects.into_iter().enumerate().map(|(i, (subject, original_color))| {
+                            let o = overrides.0.get(&subject.short_name).cloned().unwrap_or_default();
+                            let color = format_color(o.color.unwrap_or(subject.color));
+                            view! { cx,
+                                <tr>
+                                    <td class="pr-4">
+                                        {subject.short_name.clone()}
+                                        <input type="hidden" name=format!("key_{i}") value=subject.short_name.clone()/>
+                                    </td>
+                                    <td class="pr-4">
+                                        <input type="text" name=format!("full_name_{i}") placeholder=subject.full_name value=o.full_name.unwrap_or_default() class="w-48 px-2 rounded-lg border border-black dark:border-zinc-400 bg-transparent"/>
+                                    </td>
+                                    <td class="pr-4">
+                                        <input type="text" name=format!("short_name_{i}") placeholder=subject.short_name value=o.short_name.unwrap_or_default() class="w-24 px-2 rounded-lg border border-black dark:border-zinc-400 bg-transparent"/>
+                                    </td>
+                                    <td class="pr-4">
+                                        <input type="color" name=format!("color_{i}") value=color/>
+                                        // Color inputs always have a value, so only changes get stored
+                                        {original_color.map(|original| view! { cx,
+                                            <input type="hidden" name=format!("original_color_{i}") value=format_color(original)/>
+                                        })}
+                                    </td>
+                                    <td class="pr-4">
+                                        <select name=format!("week_type_{i}") class="px-2 rounded-lg border border-black dark:border-zinc-400 bg-transparent">
+                                            <option value="">{strings.subject_every_week}</option>
+                                            <option value="A" selected=o.week_type == Some(WeekType::A)>"A"</option>
+                                            <option value="B" selected=o.week_type == Some(WeekType::B)>"B"</option>
+                                        </select>
+                                    </td>
+                                    <td class="text-center">
+                                        <input type="checkbox" name=format!("hidden_{i}") checked=o.hidden/>
+                                    </td>
+                                </tr>
+                            }
+                        }).collect();
+
+                        Some(view! { cx,
+                            <form method="post" action="/app/subjects" class="p-4 border rounded-xl border-black dark:border-zinc-400">
+                                <h1 class="text-center mb-4">{strings.subjects_title}</h1>
+                                <table class="mb-4">
+                                    <tr class="text-left">
+                                        <th></th>
+                                        <th>{strings.subject_full_name}</th>
+                                        <th>{strings.subject_short_name}</th>
+                                        <th>{strings.subject_color}</th>
+                                        <th>{strings.subject_weeks}</th>
+                                        <th>{strings.subject_hidden}</th>
+                                    </tr>
+                                    {rows}
+                                </table>
+                                <button type="submit" class="w-full h-9 bg-sky-500 hover:bg-sky-400 rounded-xl">
+                                    {strings.save}
+                                </button>
+                            </form>
+                        }.into_view(cx))
+                    }
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
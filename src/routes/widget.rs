@@ -0,0 +1,324 @@
+use leptos::*;
+use time::{Date, PrimitiveDateTime, Time};
+use wasm_bindgen::{prelude::Closure, JsCast};
+
+use crate::{
+    i18n::{use_locale, Locale},
+    sources::german_now,
+    types::{overrides::format_color, timetable::*},
+};
+
+/// How often the countdown is updated
+const TICK_MILLIS: i32 = 15_000;
+/// How often the timetable is fetched again
+const REFETCH_MILLIS: i32 = 5 * 60_000;
+
+/// Timetable of the current week without recording a visit, so the widget doesn't empty the
+/// changes panel of the dashboard
+#[server(FetchWidgetTimetable, "/serverfn")]
+pub async fn fetch_widget_timetable(cx: Scope) -> Result<Option<Timetable>, ServerFnError> {
+    Ok(crate::routes::dashboard::load_timetable(cx).await)
+}
+
+/// A lesson of one day, double lessons are joined
+#[derive(PartialEq, Clone, Debug)]
+pub struct DayLesson {
+    pub start: Time,
+    pub end: Time,
+    pub lesson: Lesson,
+}
+
+impl DayLesson {
+    /// The replacement of substituted lessons
+    pub fn subject(&self) -> &Subject {
+        match &self.lesson.status {
+            LessonStatus::Replaced(subject) => subject,
+            _ => &self.lesson.subject,
+        }
+    }
+}
+
+/// Lessons of all columns for `date`, ordered by start
+pub fn day_lessons(timetable: &Timetable, date: Date) -> Vec<DayLesson> {
+    let periods = timetable.times.concat();
+    let mut lessons: Vec<DayLesson> = vec![];
+
+    for column in timetable
+        .columns
+        .iter()
+        .filter(|column| column.date == Some(date))
+    {
+        for (position, lesson) in column.lessons.iter().enumerate() {
+            let (Some(lesson), Some(period)) = (lesson, periods.get(position)) else {
+                continue;
+            };
+            if position > 0 && column.lessons[position - 1].as_ref() == Some(lesson) {
+                if let Some(last) = lessons.last_mut() {
+                    last.end = period.end;
+                }
+                continue;
+            }
+            lessons.push(DayLesson {
+                start: period.start,
+                end: period.end,
+                lesson: lesson.clone(),
+            });
+        }
+    }
+    lessons.sort_by_key(|lesson| lesson.start);
+    lessons
+}
+
+/// End of the block of periods that isn't over yet. Blocks are separated by breaks.
+pub fn next_break(timetable: &Timetable, now: Time) -> Option<Time> {
+    timetable
+        .times
+        .iter()
+        .filter_map(|block| block.last())
+        .map(|period| period.end)
+        .find(|end| *end > now)
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct WidgetState {
+    pub current: Option<DayLesson>,
+    pub next: Option<DayLesson>,
+    /// Minutes until the next break, only while there are lessons left today
+    pub break_in: Option<i64>,
+    /// Lessons after the next one
+    pub later: Vec<DayLesson>,
+}
+
+pub fn widget_state(timetable: &Timetable, now: PrimitiveDateTime) -> WidgetState {
+    let time = now.time();
+    let lessons: Vec<DayLesson> = day_lessons(timetable, now.date())
+        .into_iter()
+        .filter(|lesson| lesson.end > time && lesson.lesson.status != LessonStatus::Cancelled)
+        .collect();
+
+    let current = lessons.iter().find(|lesson| lesson.start <= time).cloned();
+    let mut upcoming = lessons.into_iter().filter(|lesson| lesson.start > time);
+    let next = upcoming.next();
+    let break_in = (current.is_some() || next.is_some())
+        .then(|| next_break(timetable, time))
+        .flatten()
+        .map(|end| (end - time).whole_minutes());
+
+    WidgetState {
+        current,
+        next,
+        break_in,
+        later: upcoming.collect(),
+    }
+}
+
+fn lesson_line(lesson: &DayLesson, locale: Locale) -> String {
+    let subject = lesson.subject();
+    let mut line = format!(
+        "{} - {} {}",
+        locale.format_time(lesson.start),
+        locale.format_time(lesson.end),
+        subject.full_name
+    );
+    if !subject.room.is_empty() {
+        line += &format!(" · {} {}", locale.strings().room, subject.room);
+    }
+    line
+}
+
+#[component]
+fn widget_lesson(
+    cx: Scope,
+    label: &'static str,
+    lesson: DayLesson,
+    locale: Locale,
+) -> impl IntoView {
+    let replaced = matches!(lesson.lesson.status, LessonStatus::Replaced(_));
+    let style = format!("border-color: {}", format_color(lesson.subject().color));
+
+    view! { cx,
+        <div class="pl-2 border-l-4" style=style>
+            <p class="text-xs opacity-70">{label}</p>
+            <p class="text-lg">{lesson.subject().full_name.clone()}</p>
+            <p class="text-sm" class:text-orange-500=replaced>{lesson_line(&lesson, locale)}</p>
+        </div>
+    }
+}
+
+/// Compact view of today for sidebars, new tab pages and iframes
+#[component]
+pub fn widget_page(cx: Scope) -> impl IntoView {
+    let timetable_resource = create_resource(
+        cx,
+        || (),
+        async move |_| -> Option<Timetable> { fetch_widget_timetable(cx).await.ok().flatten() },
+    );
+    // Only set in the browser, so the countdown doesn't start at the time of the server render
+    let (now, set_now) = create_signal(cx, None::<PrimitiveDateTime>);
+
+    create_effect(cx, move |_| {
+        set_now(Some(german_now()));
+
+        let tick = Closure::<dyn Fn()>::new(move || set_now(Some(german_now())));
+        let refetch = Closure::<dyn Fn()>::new(move || timetable_resource.refetch());
+
+        let window = window();
+        let handles = [
+            window.set_interval_with_callback_and_timeout_and_arguments_0(
+                tick.as_ref().unchecked_ref(),
+                TICK_MILLIS,
+            ),
+            window.set_interval_with_callback_and_timeout_and_arguments_0(
+                refetch.as_ref().unchecked_ref(),
+                REFETCH_MILLIS,
+            ),
+        ];
+
+        on_cleanup(cx, move || {
+            for handle in handles.into_iter().flatten() {
+                window.clear_interval_with_handle(handle);
+            }
+            // Keeps the callbacks alive as long as the intervals
+            drop((tick, refetch));
+        });
+    });
+
+    let locale = use_locale(cx);
+
+    view! { cx,
+        <div class="font-rubik dark:bg-zinc-900 dark:text-white w-screen h-screen p-3 flex flex-col gap-3 overflow-hidden">
+        <Transition fallback=move || { None::<View> }>
+            {move || {
+                match (timetable_resource.read(), locale.read(), now()) {
+                    (Some(Some(timetable)), Some(locale), Some(now)) => {
+                        let strings = locale.strings();
+                        let state = widget_state(&timetable, now);
+                        if state.current.is_none() && state.next.is_none() {
+                            return Some(view! { cx, <p>{strings.widget_done}</p> }.into_view(cx));
+                        }
+
+                        let later: Vec<_> = state
+                            .later
+                            .iter()
+                            .map(|lesson| view! { cx, <li>{lesson_line(lesson, locale)}</li> })
+                            .collect();
+
+                        Some(view! { cx,
+                            {state.current.map(|lesson| view! { cx,
+                                <WidgetLesson label=strings.widget_now lesson=lesson locale=locale/>
+                            })}
+                            {state.next.map(|lesson| view! { cx,
+                                <WidgetLesson label=strings.widget_next lesson=lesson locale=locale/>
+                            })}
+                            {state.break_in.map(|minutes| view! { cx,
+                                <p class="text-sm">{format!("{} {} min", strings.widget_break_in, minutes)}</p>
+                            })}
+                            {(!later.is_empty()).then(|| view! { cx,
+                                <div>
+                                    <p class="text-xs opacity-70">{strings.widget_later}</p>
+                                    <ul class="text-sm">{later}</ul>
+                                </div>
+                            })}
+                        }.into_view(cx))
+                    }
+                    _ => None::<View>,
+                }
+            }}
+        </Transition>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime, time};
+
+    use super::*;
+    use crate::{
+        sources::default_times,
+        test_util::{column, lesson, with_status},
+    };
+
+    const MONDAY: Date = date!(2023 - 01 - 16);
+
+    /// A double lesson of M, a cancelled D and E until 11:15
+    fn monday() -> Timetable {
+        let times = default_times();
+        let mut lessons = vec![None; times.concat().len()];
+        lessons[0] = Some(lesson("M"));
+        lessons[1] = Some(lesson("M"));
+        lessons[2] = Some(with_status("D", LessonStatus::Cancelled));
+        lessons[3] = Some(lesson("E"));
+        Timetable {
+            times,
+            columns: vec![column(MONDAY, lessons)],
+            week_type: None,
+        }
+    }
+
+    fn short_name(lesson: &Option<DayLesson>) -> Option<&str> {
+        lesson
+            .as_ref()
+            .map(|lesson| lesson.lesson.subject.short_name.as_str())
+    }
+
+    #[test]
+    fn double_lessons_are_joined() {
+        let lessons = day_lessons(&monday(), MONDAY);
+
+        let spans: Vec<_> = lessons
+            .iter()
+            .map(|lesson| (lesson.lesson.subject.short_name.as_str(), lesson.start, lesson.end))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("M", time!(8:00), time!(9:30)),
+                ("D", time!(9:45), time!(10:30)),
+                ("E", time!(10:30), time!(11:15)),
+            ]
+        );
+        assert!(day_lessons(&monday(), date!(2023 - 01 - 17)).is_empty());
+    }
+
+    #[test]
+    fn cancelled_lessons_are_skipped() {
+        let state = widget_state(&monday(), datetime!(2023-01-16 8:50));
+
+        assert_eq!(short_name(&state.current), Some("M"));
+        assert_eq!(short_name(&state.next), Some("E"));
+        assert!(state.later.is_empty());
+        assert_eq!(state.break_in, Some(40));
+    }
+
+    #[test]
+    fn before_the_first_lesson_it_is_next() {
+        let state = widget_state(&monday(), datetime!(2023-01-16 7:30));
+
+        assert_eq!(state.current, None);
+        assert_eq!(short_name(&state.next), Some("M"));
+        assert_eq!(state.later.len(), 1);
+        assert_eq!(state.break_in, Some(120));
+    }
+
+    #[test]
+    fn nothing_is_left_after_the_last_lesson() {
+        let state = widget_state(&monday(), datetime!(2023-01-16 11:15));
+
+        assert_eq!(state.current, None);
+        assert_eq!(state.next, None);
+        assert!(state.later.is_empty());
+        // No countdown to a break without lessons
+        assert_eq!(state.break_in, None);
+    }
+
+    #[test]
+    fn breaks_follow_the_end_of_each_block() {
+        let timetable = monday();
+
+        assert_eq!(next_break(&timetable, time!(8:00)), Some(time!(9:30)));
+        // A block that just ended is over
+        assert_eq!(next_break(&timetable, time!(9:30)), Some(time!(11:15)));
+        assert_eq!(next_break(&timetable, time!(15:35)), None);
+    }
+}
@@ -0,0 +1,350 @@
+//! Security headers, rate limits and body limits, added as middleware around the router.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header, HeaderValue, Request, StatusCode};
+use hyper::body::HttpBody;
+
+/// Routes other sites may embed in an iframe, e.g. on a start page or an info screen.
+///
+/// The cookies are `SameSite=Lax`, so embedding sites on another domain show the login state of a
+/// fresh browser. New tab pages and sidebars of the browser itself aren't affected.
+const FRAMED_PATHS: &[&str] = &["/widget", "/display"];
+
+#[derive(Clone, Debug)]
+pub struct SecurityConfig {
+    /// Off in the development mode with insecure cookies, as browsers would then insist on HTTPS
+    pub hsts: bool,
+    /// `frame-ancestors` of the framed routes, from `WIDGET_FRAME_ANCESTORS`
+    pub frame_ancestors: String,
+    /// Origins the browser fetches from besides this site
+    pub connect_src: String,
+    /// Largest request body in bytes, from `MAX_BODY_BYTES`
+    pub body_limit: usize,
+}
+
+impl SecurityConfig {
+    /// `WIDGET_FRAME_ANCESTORS` limits the sites embedding the framed routes, e.g.
+    /// `https://start.example.org`, and defaults to all. `CSP_CONNECT_SRC` adds origins, e.g. of
+    /// calendars the browser loads.
+    pub fn from_env(hsts: bool) -> SecurityConfig {
+        let connect_src = match std::env::var("CSP_CONNECT_SRC") {
+            Ok(extra) => format!("'self' https://homeworker.li {extra}"),
+            Err(_) => "'self' https://homeworker.li".to_owned(),
+        };
+        SecurityConfig {
+            hsts,
+            frame_ancestors: std::env::var("WIDGET_FRAME_ANCESTORS")
+                .unwrap_or_else(|_| "*".to_owned()),
+            connect_src,
+            body_limit: std::env::var("MAX_BODY_BYTES")
+                .ok()
+                .and_then(|bytes| bytes.parse().ok())
+                .unwrap_or(2 * 1024 * 1024),
+        }
+    }
+
+    /// Leptos hydrates with an inline module script and has no nonces yet, so inline scripts stay
+    /// allowed. Loading the wasm needs `wasm-unsafe-eval` and `<Style>` needs inline styles.
+    fn content_security_policy(&self, framed: bool) -> String {
+        let frame_ancestors = match framed {
+            true => self.frame_ancestors.as_str(),
+            false => "'none'",
+        };
+        format!(
+            "default-src 'self'; script-src 'self' 'unsafe-inline' 'wasm-unsafe-eval'; \
+             style-src 'self' 'unsafe-inline'; img-src 'self' data:; font-src 'self' data:; \
+             connect-src {}; object-src 'none'; base-uri 'self'; frame-ancestors {frame_ancestors}",
+            self.connect_src
+        )
+    }
+}
+
+/// Added to every response, including the static files
+pub async fn security_headers<B>(
+    State(config): State<Arc<SecurityConfig>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let framed = FRAMED_PATHS.contains(&request.uri().path());
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    if let Ok(value) = HeaderValue::from_str(&config.content_security_policy(framed)) {
+        headers.insert(header::CONTENT_SECURITY_POLICY, value);
+    }
+    // X-Frame-Options can't allow single sites, so framed routes only have the CSP directive
+    if !framed {
+        headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    }
+    if config.hsts {
+        headers.insert(
+            header::STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_static("max-age=31536000"),
+        );
+    }
+    headers.insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("strict-origin-when-cross-origin"),
+    );
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+
+    response
+}
+
+/// Reads the whole body up to the limit. Server functions read it without one, so the default
+/// limit of the axum extractors isn't enough.
+pub async fn limit_body(
+    State(config): State<Arc<SecurityConfig>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let too_large = (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").into_response();
+
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.map_or(false, |length| length > config.body_limit) {
+        return too_large;
+    }
+
+    // Chunked bodies don't declare their length
+    let (parts, mut body) = request.into_parts();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let Ok(chunk) = chunk else {
+            return (StatusCode::BAD_REQUEST, "Couldn't read the request body").into_response();
+        };
+        if bytes.len() + chunk.len() > config.body_limit {
+            return too_large;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Fixed window per client IP. Behind a reverse proxy, e.g. on a Unix socket, `TRUST_PROXY=true`
+/// takes the IP the proxy appended to `X-Forwarded-For`. Requests without a known IP aren't limited.
+#[derive(Clone)]
+pub struct RateLimiter {
+    requests: u32,
+    window: Duration,
+    trust_proxy: bool,
+    clients: Arc<Mutex<HashMap<IpAddr, (Instant, u32)>>>,
+}
+
+impl RateLimiter {
+    /// `variable` can override the number of requests per minute
+    pub fn per_minute(variable: &str, default: u32) -> RateLimiter {
+        RateLimiter {
+            requests: std::env::var(variable)
+                .ok()
+                .and_then(|requests| requests.parse().ok())
+                .unwrap_or(default),
+            window: Duration::from_secs(60),
+            trust_proxy: std::env::var("TRUST_PROXY").as_deref() == Ok("true"),
+            clients: Arc::default(),
+        }
+    }
+
+    fn client_ip<B>(&self, request: &Request<B>) -> Option<IpAddr> {
+        if self.trust_proxy {
+            let forwarded = request
+                .headers()
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .and_then(|ip| ip.trim().parse().ok());
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip())
+    }
+
+    /// Seconds until the client can try again, if it is over the limit
+    fn check(&self, ip: IpAddr) -> Option<u64> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+
+        // Keeps the map from growing with every client that ever connected
+        if clients.len() > 10_000 {
+            clients.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+        }
+
+        let (start, count) = clients.entry(ip).or_insert((now, 0));
+        if now.duration_since(*start) >= self.window {
+            (*start, *count) = (now, 0);
+        }
+        *count += 1;
+
+        match *count > self.requests {
+            true => Some((self.window - now.duration_since(*start)).as_secs().max(1)),
+            false => None,
+        }
+    }
+}
+
+pub async fn rate_limit<B>(
+    State(limiter): State<RateLimiter>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let retry_after = limiter.client_ip(&request).and_then(|ip| limiter.check(ip));
+    match retry_after {
+        Some(seconds) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, seconds.to_string())],
+            "Too many requests",
+        )
+            .into_response(),
+        None => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use tower::util::ServiceExt;
+
+    use super::*;
+
+    fn config() -> Arc<SecurityConfig> {
+        Arc::new(SecurityConfig {
+            hsts: true,
+            frame_ancestors: "https://start.example.org".to_owned(),
+            connect_src: "'self'".to_owned(),
+            body_limit: 10,
+        })
+    }
+
+    fn limiter(requests: u32, window: Duration, trust_proxy: bool) -> RateLimiter {
+        RateLimiter {
+            requests,
+            window,
+            trust_proxy,
+            clients: Arc::default(),
+        }
+    }
+
+    fn request(forwarded_for: Option<&str>) -> Request<Body> {
+        let mut request = Request::get("/auth");
+        if let Some(forwarded_for) = forwarded_for {
+            request = request.header("x-forwarded-for", forwarded_for);
+        }
+        let mut request = request.body(Body::empty()).unwrap();
+        let peer: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+    }
+
+    #[test]
+    fn clients_are_limited_per_window() {
+        let limiter = limiter(2, Duration::from_millis(100), false);
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+
+        assert_eq!(limiter.check(ip), None);
+        assert_eq!(limiter.check(ip), None);
+        assert_eq!(limiter.check(ip), Some(1));
+        // Every client has its own count
+        assert_eq!(limiter.check(other), None);
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(limiter.check(ip), None);
+    }
+
+    #[test]
+    fn forwarded_ips_are_only_used_behind_a_trusted_proxy() {
+        let direct = limiter(1, Duration::from_secs(60), false);
+        let proxied = limiter(1, Duration::from_secs(60), true);
+        let forwarded = Some("203.0.113.9, 198.51.100.7");
+
+        assert_eq!(direct.client_ip(&request(forwarded)), "10.0.0.1".parse().ok());
+        // The proxy appends the address it saw, earlier entries come from the client
+        assert_eq!(proxied.client_ip(&request(forwarded)), "198.51.100.7".parse().ok());
+        assert_eq!(proxied.client_ip(&request(None)), "10.0.0.1".parse().ok());
+        assert_eq!(proxied.client_ip(&request(Some("unknown"))), "10.0.0.1".parse().ok());
+    }
+
+    #[tokio::test]
+    async fn only_framed_paths_can_be_embedded() {
+        let router = Router::new()
+            .route("/widget", get(|| async { "widget" }))
+            .route("/app", get(|| async { "app" }))
+            .layer(middleware::from_fn_with_state(config(), security_headers));
+
+        let widget = router
+            .clone()
+            .oneshot(Request::get("/widget").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let app = router
+            .oneshot(Request::get("/app").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let csp = |response: &Response| {
+            response.headers()[header::CONTENT_SECURITY_POLICY]
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+        assert!(widget.headers().get(header::X_FRAME_OPTIONS).is_none());
+        assert!(csp(&widget).ends_with("frame-ancestors https://start.example.org"));
+        assert_eq!(app.headers()[header::X_FRAME_OPTIONS], "DENY");
+        assert!(csp(&app).ends_with("frame-ancestors 'none'"));
+        assert!(app
+            .headers()
+            .contains_key(header::STRICT_TRANSPORT_SECURITY));
+    }
+
+    #[tokio::test]
+    async fn bodies_over_the_limit_are_rejected() {
+        let router = Router::new()
+            .route("/", post(|body: String| async move { body }))
+            .layer(middleware::from_fn_with_state(config(), limit_body));
+        let send = |body: &'static str, declared: bool| {
+            let mut request = Request::post("/");
+            if declared {
+                request = request.header(header::CONTENT_LENGTH, body.len());
+            }
+            router
+                .clone()
+                .oneshot(request.body(Body::from(body)).unwrap())
+        };
+
+        assert_eq!(send("0123456789", true).await.unwrap().status(), StatusCode::OK);
+        let declared = send("0123456789a", true).await.unwrap();
+        assert_eq!(declared.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        // Without a length, the body is counted while it is read
+        let undeclared = send("0123456789a", false).await.unwrap();
+        assert_eq!(undeclared.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = send("short", false).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"short");
+    }
+}
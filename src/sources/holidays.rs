@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration};
+
+use crate::{
+    sources::{
+        ical::{parse_calendar, IcalError},
+        week_start,
+    },
+    types::timetable::{Timetable, WeekType},
+};
+
+/// School holidays or another closure, both days included
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct Holiday {
+    pub name: String,
+    pub start: Date,
+    pub end: Date,
+}
+
+/// Holidays and the A/B week rhythm of the school, configured in the sources file
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SchoolCalendar {
+    pub holidays: Vec<Holiday>,
+    /// A day in an A-week. Weeks of holidays don't count, the rhythm continues after them.
+    pub ab_reference: Option<Date>,
+}
+
+impl SchoolCalendar {
+    /// Monday to friday of the week starting at `monday` are all holidays
+    pub fn is_holiday_week(&self, monday: Date) -> bool {
+        (0..5).all(|day| {
+            let date = monday + Duration::days(day);
+            self.holidays
+                .iter()
+                .any(|holiday| holiday.start <= date && date <= holiday.end)
+        })
+    }
+
+    /// `None` without a reference and for weeks of holidays
+    pub fn week_type(&self, week: Date) -> Option<WeekType> {
+        let reference = week_start(self.ab_reference?);
+        let week = week_start(week);
+        if self.is_holiday_week(week) {
+            return None;
+        }
+
+        let (from, to) = (week.min(reference), week.max(reference));
+        let school_weeks = (0..(to - from).whole_weeks())
+            .map(|i| from + Duration::weeks(i))
+            .filter(|monday| !self.is_holiday_week(*monday))
+            .count();
+        match school_weeks % 2 {
+            0 => Some(WeekType::A),
+            _ => Some(WeekType::B),
+        }
+    }
+
+    /// Marks the holidays of the week starting at `week` and sets its week type, if configured.
+    /// Otherwise the week type of the source, e.g. a manual timetable, is kept.
+    pub fn apply(&self, mut timetable: Timetable, week: Date) -> Timetable {
+        if self.ab_reference.is_some() {
+            timetable.week_type = self.week_type(week);
+        }
+        mark_holidays(timetable, &self.holidays)
+    }
+}
+
+/// `[holidays]` of the TOML file in `TIMETABLE_SOURCES`. Homeworker has no holiday data, so they
+/// come from a local file.
+///
+/// ```toml
+/// [holidays]
+/// path = "holidays.json"
+/// state = "BY"
+/// ```
+///
+/// JSON files list the holidays of each federal state:
+///
+/// ```json
+/// { "BY": [{ "name": "Winterferien", "start": "2023-02-20", "end": "2023-02-24" }] }
+/// ```
+///
+/// Files ending in `.ics` are calendars of one state, every event is a holiday and `state` is
+/// ignored. Recurrence rules aren't expanded.
+#[derive(PartialEq, Clone, Debug, Deserialize)]
+pub struct HolidayConfig {
+    pub path: String,
+    pub state: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum HolidayError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Calendar(IcalError),
+    /// The JSON file has no holidays for the configured state, or no state is configured
+    UnknownState(Option<String>),
+}
+
+impl HolidayConfig {
+    pub fn load(&self) -> Result<Vec<Holiday>, HolidayError> {
+        let content = std::fs::read_to_string(&self.path).map_err(HolidayError::Io)?;
+        match self.path.ends_with(".ics") {
+            true => calendar_holidays(&content),
+            false => json_holidays(&content, self.state.as_deref()),
+        }
+    }
+}
+
+pub fn json_holidays(content: &str, state: Option<&str>) -> Result<Vec<Holiday>, HolidayError> {
+    let mut states: HashMap<String, Vec<Holiday>> =
+        serde_json::from_str(content).map_err(HolidayError::Json)?;
+    state
+        .and_then(|state| states.remove(state))
+        .ok_or_else(|| HolidayError::UnknownState(state.map(str::to_owned)))
+}
+
+pub fn calendar_holidays(content: &str) -> Result<Vec<Holiday>, HolidayError> {
+    let events = parse_calendar(content).map_err(HolidayError::Calendar)?;
+    Ok(events
+        .into_iter()
+        .map(|event| Holiday {
+            name: event.summary,
+            start: event.start.date(),
+            // The end of all-day events is the day after
+            end: match event.all_day {
+                true => (event.end - Duration::days(1))
+                    .date()
+                    .max(event.start.date()),
+                false => event.end.date(),
+            },
+        })
+        .collect())
+}
+
+/// Marks the columns of days during `holidays` and removes their lessons, so no reminders or
+/// alerts are sent for them. Appointments are kept.
+pub fn mark_holidays(mut timetable: Timetable, holidays: &[Holiday]) -> Timetable {
+    for column in &mut timetable.columns {
+        let Some(date) = column.date else { continue };
+        if let Some(holiday) = holidays
+            .iter()
+            .find(|holiday| holiday.start <= date && date <= holiday.end)
+        {
+            column.holiday = Some(holiday.name.clone());
+            column.lessons.iter_mut().for_each(|lesson| *lesson = None);
+        }
+    }
+    timetable
+}
@@ -0,0 +1,383 @@
+//! Conversion of deserialised Homeworker responses to the internal format. Nothing else maps
+//! Homeworker types, so the layout rules live in one place.
+
+use homeworker::types::timetable::{TimetableDay, TimetableLesson};
+
+use crate::{
+    sources::default_times,
+    types::{overrides::SubjectOverrides, timetable::*},
+};
+
+/// Only the first of several lessons in a unit is shown. Homeworker doesn't provide colours.
+fn subject(raw_lesson: Option<&TimetableLesson>) -> Subject {
+    match raw_lesson {
+        Some(lesson) => Subject {
+            full_name: lesson.name.clone(),
+            short_name: lesson.short.clone(),
+            teacher: lesson.teacher.clone(),
+            room: lesson.room.clone(),
+            color: (255, 255, 255),
+        },
+        None => Subject {
+            full_name: "".to_owned(),
+            short_name: "".to_owned(),
+            teacher: "".to_owned(),
+            room: "".to_owned(),
+            color: (255, 255, 255),
+        },
+    }
+}
+
+/// One column per day Homeworker returns, with a lesson slot for every period of the grid.
+/// Homeworker doesn't provide the period times, so the default times are used.
+///
+/// Units are placed by their positions, which count periods from 1. Units overlapping an earlier
+/// one of the same day go into a parallel column, like in `Timetable::merge`.
+pub fn timetable(days: &[TimetableDay], overrides: &SubjectOverrides) -> Timetable {
+    let mut timetable = Timetable {
+        times: default_times(),
+        columns: vec![],
+        week_type: None,
+    };
+    let period_count = timetable.period_count();
+
+    for day in days {
+        let mut lanes: Vec<Vec<Option<Lesson>>> = vec![vec![None; period_count]];
+
+        for raw_lesson in day.lessons.iter().filter(|l| !l.is_break) {
+            // Positions outside of the grid can't be shown
+            let slots: Vec<usize> = raw_lesson
+                .unit
+                .positions
+                .iter()
+                .filter_map(|position| position.checked_sub(1))
+                .filter(|slot| *slot < period_count)
+                .collect();
+            // Substitutions list the lessons taking place instead, e.g. with another teacher
+            let status = match &raw_lesson.substitution {
+                Some(substitution) if substitution.is_cancelled => LessonStatus::Cancelled,
+                Some(substitution) => match substitution.lessons.as_ref().and_then(|l| l.first()) {
+                    Some(replacement) => LessonStatus::Replaced(subject(Some(replacement))),
+                    None => LessonStatus::Normal,
+                },
+                None => LessonStatus::Normal,
+            };
+            // Hidden by the overrides
+            let Some(lesson) = overrides.apply(Lesson {
+                subject: subject(raw_lesson.lessons.as_ref().and_then(|l| l.first())),
+                status,
+                course: None,
+            }) else {
+                continue;
+            };
+
+            let lane = match lanes
+                .iter()
+                .position(|lane| slots.iter().all(|slot| lane[*slot].is_none()))
+            {
+                Some(lane) => lane,
+                None => {
+                    lanes.push(vec![None; period_count]);
+                    lanes.len() - 1
+                }
+            };
+            for slot in slots {
+                lanes[lane][slot] = Some(lesson.clone());
+            }
+        }
+
+        let name = day.date.weekday().to_string();
+        for (i, lessons) in lanes.into_iter().enumerate() {
+            timetable.columns.push(TimetableColumn {
+                name: match i {
+                    0 => name.clone(),
+                    _ => format!("{} ({})", name, i + 1),
+                },
+                date: Some(day.date),
+                lessons,
+                appointments: vec![],
+                holiday: None,
+            });
+        }
+    }
+
+    timetable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fixtures in `tests/fixtures` stand in for responses of the Homeworker timetable
+    /// endpoint, see the README there
+    fn convert(fixture: &str) -> Timetable {
+        let days: Vec<TimetableDay> = serde_json::from_str(fixture).unwrap();
+        timetable(&days, &SubjectOverrides::default())
+    }
+
+    fn short_names(column: &TimetableColumn) -> Vec<Option<&str>> {
+        column
+            .lessons
+            .iter()
+            .map(|lesson| {
+                lesson
+                    .as_ref()
+                    .map(|lesson| lesson.subject.short_name.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn units_are_placed_at_their_positions_with_gaps_in_between() {
+        let timetable = convert(include_str!("../../../tests/fixtures/gaps.json"));
+
+        assert_eq!(timetable.columns.len(), 1);
+        assert_eq!(timetable.columns[0].date, Some(time::macros::date!(2023 - 01 - 16)));
+        assert_eq!(
+            short_names(&timetable.columns[0]),
+            [
+                Some("M"),
+                None,
+                None,
+                Some("D"),
+                None,
+                None,
+                Some("E"),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn units_with_several_positions_fill_all_of_them() {
+        let timetable = convert(include_str!("../../../tests/fixtures/multi_position.json"));
+
+        assert_eq!(
+            short_names(&timetable.columns[0]),
+            [
+                Some("M"),
+                Some("M"),
+                None,
+                None,
+                Some("SP"),
+                Some("SP"),
+                Some("SP"),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_take_no_periods() {
+        let timetable = convert(include_str!("../../../tests/fixtures/breaks.json"));
+
+        assert_eq!(
+            short_names(&timetable.columns[0]),
+            [
+                Some("M"),
+                Some("M"),
+                Some("D"),
+                Some("E"),
+                Some("RE"),
+                Some("RE"),
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn days_without_lessons_keep_their_column() {
+        let timetable = convert(include_str!("../../../tests/fixtures/empty_days.json"));
+
+        assert_eq!(timetable.columns.len(), 3);
+        for column in &timetable.columns[1..] {
+            assert_eq!(column.lessons, vec![None; timetable.period_count()]);
+        }
+        assert!(convert("[]").columns.is_empty());
+    }
+
+    #[test]
+    fn parallel_units_go_into_a_second_column_of_the_same_day() {
+        let timetable = convert(include_str!("../../../tests/fixtures/parallel.json"));
+
+        assert_eq!(timetable.columns.len(), 2);
+        assert_eq!(timetable.columns[0].date, timetable.columns[1].date);
+        assert_eq!(
+            short_names(&timetable.columns[0]),
+            [
+                Some("M"),
+                None,
+                Some("RE"),
+                Some("RE"),
+                Some("D"),
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        assert_eq!(
+            short_names(&timetable.columns[1]),
+            [
+                None,
+                None,
+                Some("ETH"),
+                Some("ETH"),
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    /// Monday to wednesday with overlapping units, a unit with non-contiguous positions and a free
+    /// first period after a break
+    fn grid() -> Timetable {
+        let timetable = convert(include_str!("../../../tests/fixtures/grid.json"));
+        for column in &timetable.columns {
+            assert_eq!(column.lessons.len(), timetable.period_count());
+        }
+        timetable
+    }
+
+    #[test]
+    fn overlapping_units_keep_their_positions_in_parallel_columns() {
+        let timetable = grid();
+        let monday: Vec<_> = timetable
+            .columns
+            .iter()
+            .filter(|column| column.date == Some(time::macros::date!(2023 - 01 - 16)))
+            .collect();
+
+        assert_eq!(monday.len(), 2);
+        assert_eq!(
+            short_names(monday[0]),
+            [
+                None,
+                Some("PH"),
+                Some("PH"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        assert_eq!(
+            short_names(monday[1]),
+            [
+                None,
+                None,
+                Some("CH"),
+                Some("CH"),
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        // Grouped by date, so the "(2)" in the name isn't needed to find the day of a column
+        let days: Vec<_> = timetable
+            .days()
+            .into_iter()
+            .map(|(column, lanes)| (column.date, lanes))
+            .collect();
+        assert_eq!(
+            days,
+            [
+                (Some(time::macros::date!(2023 - 01 - 16)), 2),
+                (Some(time::macros::date!(2023 - 01 - 17)), 1),
+                (Some(time::macros::date!(2023 - 01 - 18)), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_contiguous_positions_leave_the_periods_in_between() {
+        let timetable = grid();
+
+        assert_eq!(
+            short_names(&timetable.columns[2]),
+            [
+                Some("M"),
+                Some("D"),
+                Some("M"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn free_first_period_after_a_break_stays_free() {
+        let timetable = grid();
+
+        assert_eq!(
+            short_names(&timetable.columns[3]),
+            [
+                None,
+                Some("M"),
+                Some("D"),
+                Some("D"),
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn substitutions_and_cancellations_set_the_status() {
+        let timetable = convert(include_str!("../../../tests/fixtures/substitutions.json"));
+        let lessons = &timetable.columns[0].lessons;
+
+        assert_eq!(lessons[0].as_ref().unwrap().status, LessonStatus::Normal);
+        assert_eq!(lessons[2].as_ref().unwrap().status, LessonStatus::Cancelled);
+        // The regular lesson stays the subject, so the change can be shown next to it
+        let substituted = lessons[3].as_ref().unwrap();
+        assert_eq!(substituted.subject.teacher, "Meier");
+        match &substituted.status {
+            LessonStatus::Replaced(replacement) => {
+                assert_eq!(replacement.teacher, "Schmidt");
+                assert_eq!(replacement.room, "B12");
+            }
+            status => panic!("expected a replacement, got {status:?}"),
+        }
+    }
+
+    #[test]
+    fn overrides_apply_to_replacements() {
+        let days: Vec<TimetableDay> =
+            serde_json::from_str(include_str!("../../../tests/fixtures/substitutions.json"))
+                .unwrap();
+        let mut overrides = SubjectOverrides::default();
+        overrides.0.insert(
+            "E".to_owned(),
+            crate::types::overrides::SubjectOverride {
+                color: Some((0, 128, 255)),
+                ..Default::default()
+            },
+        );
+
+        let timetable = timetable(&days, &overrides);
+
+        match &timetable.columns[0].lessons[3].as_ref().unwrap().status {
+            LessonStatus::Replaced(replacement) => assert_eq!(replacement.color, (0, 128, 255)),
+            status => panic!("expected a replacement, got {status:?}"),
+        }
+    }
+}
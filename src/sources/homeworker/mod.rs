@@ -0,0 +1,101 @@
+use std::future::Future;
+
+use async_trait::async_trait;
+use homeworker::HomeworkerClient;
+use time::Date;
+
+use crate::{
+    sources::{SourceError, TimetableSource},
+    types::{overrides::SubjectOverrides, timetable::*},
+};
+
+pub mod convert;
+
+/// Timetable of a Homeworker course, fetched with the access token of a user
+pub struct HomeworkerSource {
+    client: HomeworkerClient,
+    /// The first course membership is used if not set
+    course_id: Option<u32>,
+    overrides: SubjectOverrides,
+}
+
+impl HomeworkerSource {
+    pub fn new(access_token: String, course_id: Option<u32>) -> HomeworkerSource {
+        HomeworkerSource {
+            client: HomeworkerClient::new(access_token, "desktop-timetable".to_owned()),
+            course_id,
+            overrides: SubjectOverrides::default(),
+        }
+    }
+
+    /// Personal overrides applied while converting the subjects
+    pub fn with_overrides(mut self, overrides: SubjectOverrides) -> HomeworkerSource {
+        self.overrides = overrides;
+        self
+    }
+
+    pub async fn course_id(&self) -> Result<u32, SourceError> {
+        match self.course_id {
+            Some(id) => Ok(id),
+            None => match timed("get_course_memberships", self.client.get_course_memberships())
+                .await?
+                .first()
+            {
+                Some(membership) => Ok(membership.course_id),
+                None => Err(SourceError::NotFound),
+            },
+        }
+    }
+
+    /// Looks up the name of the course among the memberships of the user
+    pub async fn course_name(&self) -> Result<Option<String>, SourceError> {
+        let course_id = self.course_id().await?;
+        Ok(timed("get_course_memberships", self.client.get_course_memberships())
+            .await?
+            .into_iter()
+            .find(|membership| membership.course_id == course_id)
+            .map(|membership| membership.course.name))
+    }
+
+    /// Id of the user the access token belongs to, used as the key for stored settings
+    pub async fn user_id(&self) -> Result<u32, SourceError> {
+        Ok(timed("get_me", self.client.get_me()).await?.id)
+    }
+
+    /// All days Homeworker returns, not limited to one week
+    pub async fn full_timetable(&self) -> Result<Timetable, SourceError> {
+        Ok(fetch_timetable(&self.client, self.course_id().await?, &self.overrides).await?)
+    }
+}
+
+#[cfg_attr(feature = "ssr", async_trait)]
+#[cfg_attr(not(feature = "ssr"), async_trait(?Send))]
+impl TimetableSource for HomeworkerSource {
+    async fn timetable(&self, week: Date) -> Result<Timetable, SourceError> {
+        Ok(self.full_timetable().await?.week(week))
+    }
+}
+
+/// Fetches the timetable of a course and converts it to the internal format
+async fn fetch_timetable(
+    client: &HomeworkerClient,
+    course_id: u32,
+    overrides: &SubjectOverrides,
+) -> Result<Timetable, homeworker::Error> {
+    let days = timed("get_timetable", client.get_timetable(course_id)).await?;
+    Ok(convert::timetable(&days, overrides))
+}
+
+/// Latency and errors of the calls are recorded on the server. In the browser, they are only awaited.
+async fn timed<T>(
+    operation: &'static str,
+    call: impl Future<Output = Result<T, homeworker::Error>>,
+) -> Result<T, homeworker::Error> {
+    #[cfg(feature = "ssr")]
+    return crate::telemetry::upstream(operation, call).await;
+    #[cfg(not(feature = "ssr"))]
+    {
+        _ = operation;
+        call.await
+    }
+}
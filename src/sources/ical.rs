@@ -0,0 +1,656 @@
+use async_trait::async_trait;
+use time::{Date, Duration, Month, PrimitiveDateTime, Time, Weekday};
+
+use crate::{
+    sources::{read_file, utc_to_german_time, SourceError, TimetableSource},
+    types::timetable::*,
+};
+
+/// Events starting and ending this close to a period boundary are shown as lessons
+const SNAP_TOLERANCE: Duration = Duration::minutes(10);
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Event {
+    pub summary: String,
+    pub location: String,
+    pub start: PrimitiveDateTime,
+    pub end: PrimitiveDateTime,
+    /// Date-only events without a time
+    pub all_day: bool,
+    pub recurrence: Option<Recurrence>,
+    /// Dates from `EXDATE` on which a recurring event doesn't take place
+    pub exceptions: Vec<Date>,
+}
+
+/// Supported subset of `RRULE`. Rules like `BYDAY` are ignored.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    /// Last possible start. Date-only values include the whole day.
+    pub until: Option<PrimitiveDateTime>,
+    pub count: Option<u32>,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+}
+
+/// Line numbers refer to the unfolded line in the original file
+#[derive(Clone, Debug)]
+pub enum IcalError {
+    MissingProperty { line: usize, property: &'static str },
+    InvalidValue { line: usize, value: String },
+    UnterminatedEvent { line: usize },
+}
+
+impl IcalError {
+    pub fn line(&self) -> usize {
+        match self {
+            IcalError::MissingProperty { line, .. }
+            | IcalError::InvalidValue { line, .. }
+            | IcalError::UnterminatedEvent { line } => *line,
+        }
+    }
+}
+
+#[derive(Default)]
+struct EventBuilder {
+    line: usize,
+    summary: String,
+    location: String,
+    start: Option<(PrimitiveDateTime, bool)>,
+    end: Option<(PrimitiveDateTime, bool)>,
+    recurrence: Option<Recurrence>,
+    exceptions: Vec<Date>,
+}
+
+impl EventBuilder {
+    fn property(&mut self, line: usize, name: &str, value: &str) -> Result<(), IcalError> {
+        match name {
+            "SUMMARY" => self.summary = unescape(value),
+            "LOCATION" => self.location = unescape(value),
+            "DTSTART" => self.start = Some(parse_date_time(line, value)?),
+            "DTEND" => self.end = Some(parse_date_time(line, value)?),
+            "RRULE" => self.recurrence = parse_recurrence(line, value)?,
+            "EXDATE" => {
+                for value in value.split(',') {
+                    self.exceptions.push(parse_date_time(line, value)?.0.date());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<Event, IcalError> {
+        let (start, all_day) = self.start.ok_or(IcalError::MissingProperty {
+            line: self.line,
+            property: "DTSTART",
+        })?;
+        let end = match self.end {
+            Some((end, _)) => end,
+            None if all_day => start + Duration::days(1),
+            None => start,
+        };
+
+        Ok(Event {
+            summary: self.summary,
+            location: self.location,
+            start,
+            end,
+            all_day,
+            recurrence: self.recurrence,
+            exceptions: self.exceptions,
+        })
+    }
+}
+
+/// Parses all `VEVENT`s of a calendar. Other components are skipped.
+pub fn parse_calendar(content: &str) -> Result<Vec<Event>, IcalError> {
+    // Long lines are folded: continuation lines start with a space or tab
+    let mut lines: Vec<(usize, String)> = vec![];
+    for (i, line) in content.lines().enumerate() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().1.push_str(rest),
+            _ => lines.push((i + 1, line.to_owned())),
+        }
+    }
+
+    let mut events = vec![];
+    let mut current: Option<EventBuilder> = None;
+
+    for (number, line) in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Parameters like TZID are ignored, all times are treated as local school time
+        let name = name
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+
+        match (name.as_str(), value) {
+            ("BEGIN", "VEVENT") => {
+                if let Some(event) = current {
+                    return Err(IcalError::UnterminatedEvent { line: event.line });
+                }
+                current = Some(EventBuilder {
+                    line: number,
+                    ..Default::default()
+                });
+            }
+            ("END", "VEVENT") => {
+                if let Some(event) = current.take() {
+                    events.push(event.build()?);
+                }
+            }
+            (name, value) => {
+                if let Some(event) = &mut current {
+                    event.property(number, name, value)?;
+                }
+            }
+        }
+    }
+
+    match current {
+        Some(event) => Err(IcalError::UnterminatedEvent { line: event.line }),
+        None => Ok(events),
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => {}
+        }
+    }
+    result
+}
+
+/// Parses `YYYYMMDD` and `YYYYMMDDTHHMMSS[Z]`. The flag is set for date-only values.
+fn parse_date_time(line: usize, value: &str) -> Result<(PrimitiveDateTime, bool), IcalError> {
+    let invalid = || IcalError::InvalidValue {
+        line,
+        value: value.to_owned(),
+    };
+    let number = |range: std::ops::Range<usize>| -> Result<u32, IcalError> {
+        value
+            .get(range)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(invalid)
+    };
+
+    let month = Month::try_from(number(4..6)? as u8).map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(number(0..4)? as i32, month, number(6..8)? as u8)
+        .map_err(|_| invalid())?;
+
+    if value.len() == 8 {
+        return Ok((date.midnight(), true));
+    }
+    if value.get(8..9) != Some("T") {
+        return Err(invalid());
+    }
+
+    let time = Time::from_hms(number(9..11)? as u8, number(11..13)? as u8, number(13..15)? as u8)
+        .map_err(|_| invalid())?;
+    let date_time = PrimitiveDateTime::new(date, time);
+
+    match value.ends_with('Z') {
+        true => Ok((utc_to_german_time(date_time), false)),
+        false => Ok((date_time, false)),
+    }
+}
+
+fn parse_recurrence(line: usize, value: &str) -> Result<Option<Recurrence>, IcalError> {
+    let invalid = || IcalError::InvalidValue {
+        line,
+        value: value.to_owned(),
+    };
+
+    let mut frequency = None;
+    let mut recurrence = Recurrence {
+        frequency: Frequency::Weekly,
+        interval: 1,
+        until: None,
+        count: None,
+    };
+
+    for part in value.split(';') {
+        let Some((key, value)) = part.split_once('=') else {
+            return Err(invalid());
+        };
+        match key {
+            "FREQ" => {
+                frequency = match value {
+                    "DAILY" => Some(Frequency::Daily),
+                    "WEEKLY" => Some(Frequency::Weekly),
+                    _ => None,
+                }
+            }
+            "INTERVAL" => recurrence.interval = value.parse().map_err(|_| invalid())?,
+            "COUNT" => recurrence.count = Some(value.parse().map_err(|_| invalid())?),
+            "UNTIL" => {
+                recurrence.until = Some(match parse_date_time(line, value)? {
+                    (until, true) => until + Duration::days(1) - Duration::nanoseconds(1),
+                    (until, false) => until,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    // Unsupported frequencies are shown as single events
+    Ok(frequency.map(|frequency| Recurrence {
+        frequency,
+        ..recurrence
+    }))
+}
+
+impl Event {
+    /// Start of every occurrence between `from` and `to`, both inclusive
+    pub fn occurrences(&self, from: Date, to: Date) -> Vec<PrimitiveDateTime> {
+        let Some(rule) = &self.recurrence else {
+            return match self.start.date() >= from && self.start.date() <= to {
+                true => vec![self.start],
+                false => vec![],
+            };
+        };
+
+        let step = match rule.frequency {
+            Frequency::Daily => Duration::days(rule.interval.max(1) as i64),
+            Frequency::Weekly => Duration::weeks(rule.interval.max(1) as i64),
+        };
+
+        let mut occurrences = vec![];
+        let mut start = self.start;
+        let mut count = 0;
+        while start.date() <= to
+            && rule.count.map_or(true, |max| count < max)
+            && rule.until.map_or(true, |until| start <= until)
+        {
+            if start.date() >= from && !self.exceptions.contains(&start.date()) {
+                occurrences.push(start);
+            }
+            start += step;
+            count += 1;
+        }
+        occurrences
+    }
+}
+
+/// Maps the events of a week onto the period grid. Events that start and end at period boundaries
+/// within one group and span at most two periods become lessons, everything else appointments.
+/// Weekends only get a column if something happens on them.
+pub fn calendar_timetable(events: &[Event], times: Vec<Vec<Period>>, week: Date) -> Timetable {
+    let periods: Vec<(usize, Period)> = times
+        .iter()
+        .enumerate()
+        .flat_map(|(group, periods)| periods.iter().map(move |period| (group, *period)))
+        .collect();
+    let near = |a: Time, b: Time| (a - b).abs() <= SNAP_TOLERANCE;
+
+    let mut columns: Vec<TimetableColumn> = (0..7)
+        .map(|day| {
+            let date = week + Duration::days(day);
+            TimetableColumn {
+                name: date.weekday().to_string(),
+                date: Some(date),
+                lessons: vec![None; periods.len()],
+                appointments: vec![],
+                holiday: None,
+            }
+        })
+        .collect();
+
+    for event in events {
+        let duration = event.end - event.start;
+
+        for start in event.occurrences(week, week + Duration::days(6)) {
+            let end = start + duration;
+            let column = &mut columns[(start.date() - week).whole_days() as usize];
+
+            let first = periods
+                .iter()
+                .position(|(_, p)| near(p.start, start.time()));
+            let last = periods.iter().rposition(|(_, p)| near(p.end, end.time()));
+
+            let slots = match (event.all_day, first, last) {
+                (false, Some(first), Some(last))
+                    if last >= first
+                        && last - first < 2
+                        && periods[first].0 == periods[last].0
+                        && start.date() == end.date()
+                        && column.lessons[first..=last].iter().all(Option::is_none) =>
+                {
+                    Some(first..=last)
+                }
+                _ => None,
+            };
+
+            match slots {
+                Some(slots) => {
+                    let lesson = Lesson {
+                        subject: Subject {
+                            full_name: event.summary.clone(),
+                            short_name: short_name(&event.summary),
+                            teacher: "".to_owned(),
+                            room: event.location.clone(),
+                            color: (255, 255, 255),
+                        },
+                        status: LessonStatus::Normal,
+                        course: None,
+                    };
+                    slots.for_each(|i| column.lessons[i] = Some(lesson.clone()));
+                }
+                None => column.appointments.push(Appointment {
+                    title: event.summary.clone(),
+                    start: (!event.all_day).then_some(start.time()),
+                    end: (!event.all_day).then_some(end.time()),
+                    location: event.location.clone(),
+                }),
+            }
+        }
+    }
+
+    columns.retain(|column| {
+        let weekend = matches!(
+            column.date.map(|date| date.weekday()),
+            Some(Weekday::Saturday | Weekday::Sunday)
+        );
+        !weekend || !column.appointments.is_empty() || column.lessons.iter().any(Option::is_some)
+    });
+
+    Timetable {
+        times,
+        columns,
+        week_type: None,
+    }
+}
+
+/// Calendars have no short names, so the summary is shortened to fit into a lesson block
+fn short_name(summary: &str) -> String {
+    match summary.chars().count() {
+        0..=12 => summary.to_owned(),
+        _ => summary.chars().take(11).chain(['…']).collect(),
+    }
+}
+
+pub enum CalendarLocation {
+    File(String),
+    Url(String),
+}
+
+impl CalendarLocation {
+    /// http(s) URLs are fetched, everything else is treated as a file path
+    pub fn parse(location: &str) -> CalendarLocation {
+        match location.starts_with("http://") || location.starts_with("https://") {
+            true => CalendarLocation::Url(location.to_owned()),
+            false => CalendarLocation::File(location.to_owned()),
+        }
+    }
+}
+
+/// Calendar from a file or URL, mapped onto the period grid of the school
+pub struct IcalSource {
+    location: CalendarLocation,
+    times: Vec<Vec<Period>>,
+}
+
+impl IcalSource {
+    pub fn new(location: CalendarLocation, times: Vec<Vec<Period>>) -> IcalSource {
+        IcalSource { location, times }
+    }
+
+    async fn content(&self) -> Result<String, SourceError> {
+        match &self.location {
+            CalendarLocation::File(path) => read_file(path).await,
+            CalendarLocation::Url(url) => {
+                let response = reqwest::get(url)
+                    .await
+                    .map_err(|e| SourceError::Unavailable(format!("{url}: {e}")))?;
+                response
+                    .text()
+                    .await
+                    .map_err(|e| SourceError::Unavailable(format!("{url}: {e}")))
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "ssr", async_trait)]
+#[cfg_attr(not(feature = "ssr"), async_trait(?Send))]
+impl TimetableSource for IcalSource {
+    async fn timetable(&self, week: Date) -> Result<Timetable, SourceError> {
+        let events = parse_calendar(&self.content().await?).map_err(SourceError::Calendar)?;
+        Ok(calendar_timetable(&events, self.times.clone(), week))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime, time};
+
+    use super::*;
+    use crate::sources::default_times;
+
+    fn calendar(events: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{events}END:VCALENDAR\r\n")
+    }
+
+    fn starts(event: &Event, from: Date, to: Date) -> Vec<Date> {
+        event
+            .occurrences(from, to)
+            .iter()
+            .map(|start| start.date())
+            .collect()
+    }
+
+    #[test]
+    fn parses_folded_and_escaped_properties() {
+        let content = calendar(
+            "BEGIN:VEVENT\r\n\
+             SUMMARY:Chor\\, Probe\r\n\
+             LOCATION:Aula\r\n  Bühne\r\n\
+             DTSTART;TZID=Europe/Berlin:20230116T080000\r\n\
+             DTEND;TZID=Europe/Berlin:20230116T084500\r\n\
+             END:VEVENT\r\n",
+        );
+
+        let events = parse_calendar(&content).unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Chor, Probe");
+        assert_eq!(events[0].location, "Aula Bühne");
+        assert_eq!(events[0].start, datetime!(2023-01-16 8:00));
+        assert!(!events[0].all_day);
+    }
+
+    #[test]
+    fn utc_times_are_converted_to_german_time() {
+        let content = calendar(
+            "BEGIN:VEVENT\r\nDTSTART:20230116T070000Z\r\nDTEND:20230116T074500Z\r\nEND:VEVENT\r\n\
+             BEGIN:VEVENT\r\nDTSTART:20230619T060000Z\r\nEND:VEVENT\r\n",
+        );
+
+        let events = parse_calendar(&content).unwrap();
+
+        assert_eq!(events[0].start, datetime!(2023-01-16 8:00));
+        assert_eq!(events[0].end, datetime!(2023-01-16 8:45));
+        // Summer time
+        assert_eq!(events[1].start, datetime!(2023-06-19 8:00));
+    }
+
+    #[test]
+    fn weekly_events_repeat_until_their_count_without_exceptions() {
+        let content = calendar(
+            "BEGIN:VEVENT\r\n\
+             DTSTART:20230116T080000\r\n\
+             RRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=4\r\n\
+             EXDATE:20230130T080000\r\n\
+             END:VEVENT\r\n",
+        );
+
+        let events = parse_calendar(&content).unwrap();
+
+        assert_eq!(
+            events[0].recurrence,
+            Some(Recurrence {
+                frequency: Frequency::Weekly,
+                interval: 2,
+                until: None,
+                count: Some(4),
+            })
+        );
+        // The exception still counts towards COUNT
+        assert_eq!(
+            starts(&events[0], date!(2023 - 01 - 01), date!(2023 - 12 - 31)),
+            [
+                date!(2023 - 01 - 16),
+                date!(2023 - 02 - 13),
+                date!(2023 - 02 - 27)
+            ]
+        );
+    }
+
+    #[test]
+    fn daily_events_repeat_until_the_end_date() {
+        let content = calendar(
+            "BEGIN:VEVENT\r\n\
+             DTSTART:20230116T140000\r\n\
+             RRULE:FREQ=DAILY;UNTIL=20230119T235959Z\r\n\
+             END:VEVENT\r\n",
+        );
+
+        let events = parse_calendar(&content).unwrap();
+
+        // Only the occurrences in the requested range
+        assert_eq!(
+            starts(&events[0], date!(2023 - 01 - 17), date!(2023 - 01 - 31)),
+            [
+                date!(2023 - 01 - 17),
+                date!(2023 - 01 - 18),
+                date!(2023 - 01 - 19)
+            ]
+        );
+    }
+
+    #[test]
+    fn date_only_end_dates_include_their_day() {
+        let content = calendar(
+            "BEGIN:VEVENT\r\n\
+             DTSTART:20230116T140000\r\n\
+             RRULE:FREQ=DAILY;UNTIL=20230118\r\n\
+             END:VEVENT\r\n",
+        );
+
+        let events = parse_calendar(&content).unwrap();
+
+        assert_eq!(
+            starts(&events[0], date!(2023 - 01 - 01), date!(2023 - 01 - 31)),
+            [
+                date!(2023 - 01 - 16),
+                date!(2023 - 01 - 17),
+                date!(2023 - 01 - 18)
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_frequencies_are_single_events() {
+        let content = calendar(
+            "BEGIN:VEVENT\r\nDTSTART:20230116T080000\r\nRRULE:FREQ=MONTHLY\r\nEND:VEVENT\r\n",
+        );
+
+        let events = parse_calendar(&content).unwrap();
+
+        assert_eq!(events[0].recurrence, None);
+        assert_eq!(
+            starts(&events[0], date!(2023 - 01 - 01), date!(2023 - 12 - 31)),
+            [date!(2023 - 01 - 16)]
+        );
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        let invalid = calendar("BEGIN:VEVENT\r\nSUMMARY:Test\r\nDTSTART:2023011\r\nEND:VEVENT\r\n");
+        let missing = calendar("BEGIN:VEVENT\r\nSUMMARY:Test\r\nEND:VEVENT\r\n");
+        let unterminated = calendar("BEGIN:VEVENT\r\nDTSTART:20230116\r\n");
+
+        assert_eq!(parse_calendar(&invalid).unwrap_err().line(), 5);
+        assert_eq!(parse_calendar(&missing).unwrap_err().line(), 3);
+        assert!(matches!(
+            parse_calendar(&unterminated),
+            Err(IcalError::UnterminatedEvent { line: 3 })
+        ));
+    }
+
+    fn event(summary: &str, start: PrimitiveDateTime, end: PrimitiveDateTime) -> Event {
+        Event {
+            summary: summary.to_owned(),
+            location: "Aula".to_owned(),
+            start,
+            end,
+            all_day: false,
+            recurrence: None,
+            exceptions: vec![],
+        }
+    }
+
+    #[test]
+    fn events_near_period_boundaries_snap_onto_the_grid() {
+        let monday = date!(2023 - 01 - 16);
+        let events = [
+            // Periods 1 and 2, a few minutes off
+            event("Chor", datetime!(2023-01-16 8:05), datetime!(2023-01-16 9:25)),
+            // Starts in the first group and ends in the second
+            event("Ausflug", datetime!(2023-01-17 8:45), datetime!(2023-01-17 10:30)),
+            // Too far from any period
+            event("Sprechstunde", datetime!(2023-01-18 8:20), datetime!(2023-01-18 9:00)),
+        ];
+
+        let timetable = calendar_timetable(&events, default_times(), monday);
+
+        let monday_lessons = &timetable.columns[0].lessons;
+        assert_eq!(monday_lessons[0].as_ref().unwrap().subject.full_name, "Chor");
+        assert_eq!(monday_lessons[0], monday_lessons[1]);
+        assert_eq!(monday_lessons[2], None);
+
+        let tuesday = &timetable.columns[1];
+        assert!(tuesday.lessons.iter().all(Option::is_none));
+        assert_eq!(tuesday.appointments[0].title, "Ausflug");
+        assert_eq!(tuesday.appointments[0].start, Some(time!(8:45)));
+
+        assert_eq!(timetable.columns[2].appointments[0].title, "Sprechstunde");
+    }
+
+    #[test]
+    fn weekends_only_get_a_column_with_events() {
+        let monday = date!(2023 - 01 - 16);
+        let mut sports_day =
+            event("Sportfest", datetime!(2023-01-21 0:00), datetime!(2023-01-22 0:00));
+        sports_day.all_day = true;
+
+        let timetable = calendar_timetable(&[sports_day], default_times(), monday);
+
+        let weekdays: Vec<_> = timetable
+            .columns
+            .iter()
+            .map(|column| column.date.unwrap().weekday())
+            .collect();
+        assert_eq!(weekdays.len(), 6);
+        assert_eq!(weekdays[5], Weekday::Saturday);
+        assert_eq!(timetable.columns[5].appointments[0].start, None);
+    }
+}
@@ -0,0 +1,506 @@
+use std::{collections::BTreeMap, fmt::Display, ops::Range};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use time::{Date, Duration, Time, Weekday};
+use toml::Spanned;
+
+use crate::{
+    sources::{default_times, parse_date, read_file, week_start, SourceError, TimetableSource},
+    types::{overrides::parse_color, timetable::*},
+};
+
+/// Timetable defined by hand in a TOML file, for users without Homeworker or as a fallback.
+///
+/// ```toml
+/// # Optional, defaults to the school's period grid. Groups are separated by breaks.
+/// periods = [["08:00-08:45", "08:45-09:30"], ["09:45-10:30", "10:30-11:15"]]
+/// # Optional, the week containing this date is an A-week
+/// ab_reference = "2023-02-20"
+///
+/// [subjects.MA]
+/// name = "Mathematik"
+/// teacher = "Müller"
+/// room = "A101"
+/// color = "#f59e0b"
+///
+/// [[lessons]]
+/// day = "monday"
+/// periods = [1, 2]  # Counted from 1, across groups
+/// subject = "MA"
+/// weeks = "A"       # Optional, "A" or "B"
+/// room = "B12"      # Optional, overrides the room of the subject
+/// ```
+#[derive(PartialEq, Clone, Debug)]
+pub struct ManualTimetable {
+    times: Vec<Vec<Period>>,
+    ab_reference: Option<Date>,
+    lessons: Vec<ManualLesson>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+struct ManualLesson {
+    weekday: Weekday,
+    positions: Range<usize>,
+    week_type: Option<WeekType>,
+    lesson: Lesson,
+}
+
+/// Points at the line of the file that caused the error
+#[derive(PartialEq, Clone, Debug)]
+pub struct ManualError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ManualError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Deserialize)]
+struct ManualFile {
+    periods: Option<Vec<Vec<Spanned<String>>>>,
+    ab_reference: Option<Spanned<String>>,
+    /// Sorted, so the first invalid subject is always the one reported
+    #[serde(default)]
+    subjects: BTreeMap<String, SubjectEntry>,
+    #[serde(default)]
+    lessons: Vec<LessonEntry>,
+}
+
+#[derive(Deserialize)]
+struct SubjectEntry {
+    name: String,
+    short: Option<String>,
+    #[serde(default)]
+    teacher: String,
+    #[serde(default)]
+    room: String,
+    color: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct LessonEntry {
+    day: Spanned<String>,
+    periods: Spanned<Vec<usize>>,
+    subject: Spanned<String>,
+    weeks: Option<Spanned<String>>,
+    room: Option<String>,
+    teacher: Option<String>,
+}
+
+impl ManualTimetable {
+    pub fn parse(content: &str) -> Result<ManualTimetable, ManualError> {
+        let error = |span: Range<usize>, message: String| ManualError {
+            line: content[..span.start.min(content.len())]
+                .matches('\n')
+                .count()
+                + 1,
+            message,
+        };
+
+        let file: ManualFile = toml::from_str(content)
+            .map_err(|e| error(e.span().unwrap_or(0..0), e.message().to_owned()))?;
+
+        let times = match file.periods {
+            Some(groups) => groups
+                .iter()
+                .map(|group| {
+                    group
+                        .iter()
+                        .map(|period| {
+                            parse_period(period.get_ref()).ok_or_else(|| {
+                                error(
+                                    period.span(),
+                                    format!(
+                                        "invalid period {:?}, expected HH:MM-HH:MM",
+                                        period.get_ref()
+                                    ),
+                                )
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => default_times(),
+        };
+        let period_count: usize = times.iter().map(Vec::len).sum();
+
+        let ab_reference = match &file.ab_reference {
+            Some(date) => Some(parse_date(date.get_ref()).ok_or_else(|| {
+                error(
+                    date.span(),
+                    format!("invalid date {:?}, expected YYYY-MM-DD", date.get_ref()),
+                )
+            })?),
+            None => None,
+        };
+
+        let mut subjects = BTreeMap::new();
+        for (key, entry) in &file.subjects {
+            let color = match &entry.color {
+                Some(color) => parse_color(color.get_ref()).ok_or_else(|| {
+                    error(
+                        color.span(),
+                        format!("invalid color {:?}, expected #rrggbb", color.get_ref()),
+                    )
+                })?,
+                None => (255, 255, 255),
+            };
+            subjects.insert(
+                key.clone(),
+                Subject {
+                    full_name: entry.name.clone(),
+                    short_name: entry.short.clone().unwrap_or(key.clone()),
+                    teacher: entry.teacher.clone(),
+                    room: entry.room.clone(),
+                    color,
+                },
+            );
+        }
+
+        let mut lessons: Vec<ManualLesson> = vec![];
+        for entry in &file.lessons {
+            let weekday = parse_weekday(entry.day.get_ref()).ok_or_else(|| {
+                error(entry.day.span(), format!("unknown day {:?}", entry.day.get_ref()))
+            })?;
+
+            let mut subject = subjects
+                .get(entry.subject.get_ref())
+                .cloned()
+                .ok_or_else(|| {
+                    error(
+                        entry.subject.span(),
+                        format!("unknown subject {:?}", entry.subject.get_ref()),
+                    )
+                })?;
+            if let Some(room) = &entry.room {
+                subject.room = room.clone();
+            }
+            if let Some(teacher) = &entry.teacher {
+                subject.teacher = teacher.clone();
+            }
+
+            // Periods have to be consecutive, so they can be shown as one block
+            let periods = entry.periods.get_ref();
+            let positions = match (periods.first(), periods.last()) {
+                (Some(&first), Some(&last))
+                    if first >= 1
+                        && last <= period_count
+                        && periods.windows(2).all(|pair| pair[1] == pair[0] + 1) =>
+                {
+                    first - 1..last
+                }
+                _ => {
+                    return Err(error(
+                        entry.periods.span(),
+                        format!("periods must be consecutive numbers from 1 to {period_count}"),
+                    ))
+                }
+            };
+
+            let week_type = match &entry.weeks {
+                Some(weeks) => Some(match weeks.get_ref().to_ascii_uppercase().as_str() {
+                    "A" => WeekType::A,
+                    "B" => WeekType::B,
+                    _ => {
+                        return Err(error(weeks.span(), "weeks must be \"A\" or \"B\"".to_owned()))
+                    }
+                }),
+                None => None,
+            };
+            if week_type.is_some() && ab_reference.is_none() {
+                return Err(error(
+                    entry.day.span(),
+                    "lessons with weeks need ab_reference to be set".to_owned(),
+                ));
+            }
+
+            let overlapping = lessons.iter().any(|other| {
+                other.weekday == weekday
+                    && other.positions.start < positions.end
+                    && positions.start < other.positions.end
+                    && (other.week_type.is_none()
+                        || week_type.is_none()
+                        || other.week_type == week_type)
+            });
+            if overlapping {
+                return Err(error(
+                    entry.day.span(),
+                    "lesson overlaps with a previous lesson".to_owned(),
+                ));
+            }
+
+            lessons.push(ManualLesson {
+                weekday,
+                positions,
+                week_type,
+                lesson: Lesson {
+                    subject,
+                    status: LessonStatus::Normal,
+                    course: None,
+                },
+            });
+        }
+
+        Ok(ManualTimetable {
+            times,
+            ab_reference,
+            lessons,
+        })
+    }
+
+    /// A-week for every even number of weeks since `ab_reference`
+    pub fn week_type(&self, week: Date) -> Option<WeekType> {
+        let weeks = (week_start(week) - week_start(self.ab_reference?)).whole_weeks();
+        match weeks.rem_euclid(2) {
+            0 => Some(WeekType::A),
+            _ => Some(WeekType::B),
+        }
+    }
+
+    /// Monday to friday, plus saturday if there are lessons on saturdays
+    pub fn timetable(&self, week: Date) -> Timetable {
+        let week_type = self.week_type(week);
+        let period_count = self.times.iter().map(Vec::len).sum();
+        let day_count = match self.lessons.iter().any(|l| l.weekday == Weekday::Saturday) {
+            true => 6,
+            false => 5,
+        };
+
+        let columns = (0..day_count)
+            .map(|day| {
+                let date = week + Duration::days(day);
+                let mut lessons = vec![None; period_count];
+
+                for lesson in self.lessons.iter().filter(|lesson| {
+                    lesson.weekday == date.weekday()
+                        && (lesson.week_type.is_none() || lesson.week_type == week_type)
+                }) {
+                    lesson
+                        .positions
+                        .clone()
+                        .for_each(|i| lessons[i] = Some(lesson.lesson.clone()));
+                }
+
+                TimetableColumn {
+                    name: date.weekday().to_string(),
+                    date: Some(date),
+                    lessons,
+                    appointments: vec![],
+                    holiday: None,
+                }
+            })
+            .collect();
+
+        Timetable {
+            times: self.times.clone(),
+            columns,
+            week_type,
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Option<Time> {
+    let (hour, minute) = time.trim().split_once(':')?;
+    Time::from_hms(hour.parse().ok()?, minute.parse().ok()?, 0).ok()
+}
+
+fn parse_period(period: &str) -> Option<Period> {
+    let (start, end) = period.split_once('-')?;
+    let period = Period {
+        start: parse_time(start)?,
+        end: parse_time(end)?,
+    };
+    (period.start < period.end).then_some(period)
+}
+
+/// Accepts english and german day names
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.to_lowercase().as_str() {
+        "monday" | "montag" => Some(Weekday::Monday),
+        "tuesday" | "dienstag" => Some(Weekday::Tuesday),
+        "wednesday" | "mittwoch" => Some(Weekday::Wednesday),
+        "thursday" | "donnerstag" => Some(Weekday::Thursday),
+        "friday" | "freitag" => Some(Weekday::Friday),
+        "saturday" | "samstag" => Some(Weekday::Saturday),
+        _ => None,
+    }
+}
+
+/// Reads the file on every request, so changes show up without a restart
+pub struct ManualSource {
+    path: String,
+}
+
+impl ManualSource {
+    pub fn new(path: String) -> ManualSource {
+        ManualSource { path }
+    }
+
+    pub async fn load(&self) -> Result<ManualTimetable, SourceError> {
+        let content = read_file(&self.path).await?;
+        ManualTimetable::parse(&content).map_err(SourceError::Manual)
+    }
+}
+
+#[cfg_attr(feature = "ssr", async_trait)]
+#[cfg_attr(not(feature = "ssr"), async_trait(?Send))]
+impl TimetableSource for ManualSource {
+    async fn timetable(&self, week: Date) -> Result<Timetable, SourceError> {
+        Ok(self.load().await?.timetable(week))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, time};
+
+    use super::*;
+
+    const AB_TIMETABLE: &str = r##"periods = [["08:00-08:45", "08:45-09:30"], ["09:45-10:30"]]
+ab_reference = "2023-01-16"
+
+[subjects.MA]
+name = "Mathematik"
+teacher = "Müller"
+room = "A101"
+color = "#f59e0b"
+
+[subjects.PH]
+name = "Physik"
+short = "Ph"
+
+[[lessons]]
+day = "monday"
+periods = [1, 2]
+subject = "MA"
+
+[[lessons]]
+day = "Montag"
+periods = [3]
+subject = "PH"
+weeks = "A"
+
+[[lessons]]
+day = "monday"
+periods = [3]
+subject = "MA"
+weeks = "b"
+room = "B12"
+"##;
+
+    fn short_names(timetable: &Timetable) -> Vec<Option<String>> {
+        timetable.columns[0]
+            .lessons
+            .iter()
+            .map(|lesson| {
+                lesson
+                    .as_ref()
+                    .map(|lesson| lesson.subject.short_name.clone())
+            })
+            .collect()
+    }
+
+    fn error_line(content: &str) -> usize {
+        ManualTimetable::parse(content).unwrap_err().line
+    }
+
+    #[test]
+    fn parses_periods_and_subjects() {
+        let timetable = ManualTimetable::parse(AB_TIMETABLE)
+            .unwrap()
+            .timetable(date!(2023 - 01 - 16));
+
+        assert_eq!(timetable.times.len(), 2);
+        assert_eq!(timetable.times[1][0].start, time!(9:45));
+        // Monday to friday without lessons on saturdays
+        assert_eq!(timetable.columns.len(), 5);
+
+        let lesson = timetable.columns[0].lessons[0].as_ref().unwrap();
+        assert_eq!(lesson.subject.full_name, "Mathematik");
+        assert_eq!(lesson.subject.short_name, "MA");
+        assert_eq!(lesson.subject.color, (0xf5, 0x9e, 0x0b));
+        assert_eq!(timetable.columns[0].lessons[0], timetable.columns[0].lessons[1]);
+    }
+
+    #[test]
+    fn alternates_between_a_and_b_weeks() {
+        let manual = ManualTimetable::parse(AB_TIMETABLE).unwrap();
+
+        let a_week = manual.timetable(date!(2023 - 01 - 16));
+        let b_week = manual.timetable(date!(2023 - 01 - 23));
+        let next_a_week = manual.timetable(date!(2023 - 01 - 30));
+
+        assert_eq!(a_week.week_type, Some(WeekType::A));
+        assert_eq!(b_week.week_type, Some(WeekType::B));
+        assert_eq!(short_names(&a_week)[2].as_deref(), Some("Ph"));
+        assert_eq!(short_names(&b_week)[2].as_deref(), Some("MA"));
+        assert_eq!(b_week.columns[0].lessons[2].as_ref().unwrap().subject.room, "B12");
+        assert_eq!(short_names(&next_a_week), short_names(&a_week));
+        // Weeks before the reference alternate as well
+        let earlier = manual.timetable(date!(2023 - 01 - 09));
+        assert_eq!(earlier.week_type, Some(WeekType::B));
+    }
+
+    #[test]
+    fn saturdays_are_only_shown_with_lessons() {
+        let content = r#"
+[subjects.SP]
+name = "Sport"
+
+[[lessons]]
+day = "samstag"
+periods = [1]
+subject = "SP"
+"#;
+
+        let timetable = ManualTimetable::parse(content)
+            .unwrap()
+            .timetable(date!(2023 - 01 - 16));
+
+        assert_eq!(timetable.columns.len(), 6);
+        assert_eq!(timetable.week_type, None);
+        // Without periods, the grid of the school is used
+        assert_eq!(timetable.times, default_times());
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        let unknown_subject = AB_TIMETABLE.replace("subject = \"PH\"", "subject = \"CH\"");
+        let invalid_period = AB_TIMETABLE.replace("09:45-10:30", "9.45");
+        let invalid_weeks = AB_TIMETABLE.replace("weeks = \"b\"", "weeks = \"C\"");
+        let gap = AB_TIMETABLE.replace("periods = [1, 2]", "periods = [1, 3]");
+        let overlap = AB_TIMETABLE.replace("weeks = \"b\"", "weeks = \"A\"");
+        let no_reference = AB_TIMETABLE.replace("ab_reference = \"2023-01-16\"", "");
+
+        assert_eq!(error_line(&unknown_subject), 22);
+        assert_eq!(error_line(&invalid_period), 1);
+        assert_eq!(error_line(&invalid_weeks), 29);
+        assert_eq!(error_line(&gap), 16);
+        assert_eq!(error_line(&overlap), 26);
+        // Removing the line keeps an empty one
+        assert_eq!(error_line(&no_reference), 20);
+        assert_eq!(error_line("[[lessons]\n"), 1);
+    }
+
+    #[test]
+    fn invalid_subjects_are_reported_in_order() {
+        let content = r##"
+[subjects.ZZ]
+name = "Last"
+color = "red"
+
+[subjects.AA]
+name = "First"
+color = "blue"
+"##;
+
+        let error = ManualTimetable::parse(content).unwrap_err();
+
+        assert_eq!(error.line, 8);
+        assert_eq!(error.to_string(), "line 8: invalid color \"blue\", expected #rrggbb");
+    }
+}
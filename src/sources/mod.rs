@@ -0,0 +1,495 @@
+use std::future::Future;
+
+use async_trait::async_trait;
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, Weekday};
+
+use crate::{
+    sources::{
+        holidays::SchoolCalendar,
+        homeworker::HomeworkerSource,
+        ical::{CalendarLocation, IcalError, IcalSource},
+        manual::{ManualError, ManualSource},
+    },
+    types::{
+        settings::UserSettings,
+        timetable::{Period, Timetable},
+    },
+};
+
+pub mod holidays;
+pub mod homeworker;
+pub mod ical;
+pub mod manual;
+
+/// Anything that can produce a timetable for a week
+#[cfg_attr(feature = "ssr", async_trait)]
+#[cfg_attr(not(feature = "ssr"), async_trait(?Send))]
+pub trait TimetableSource: Send + Sync {
+    /// `week` is the monday of the requested week. Columns outside of that week are left out.
+    async fn timetable(&self, week: Date) -> Result<Timetable, SourceError>;
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    Homeworker(::homeworker::Error),
+    /// The source needs a logged in Homeworker user
+    Unauthenticated,
+    NotFound,
+    /// A file or URL could not be read
+    Unavailable(String),
+    Calendar(IcalError),
+    Manual(ManualError),
+}
+
+impl From<::homeworker::Error> for SourceError {
+    fn from(error: ::homeworker::Error) -> Self {
+        SourceError::Homeworker(error)
+    }
+}
+
+/// One `[[source]]` entry of the TOML file in `TIMETABLE_SOURCES`
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SourceConfig {
+    /// Course of the logged in user. Uses the first membership if no course is set.
+    Homeworker {
+        name: Option<String>,
+        course: Option<u32>,
+        /// Manual timetable file used while Homeworker is unreachable
+        fallback: Option<String>,
+    },
+    /// iCalendar file, read from a path or an http(s) URL
+    Ical {
+        name: Option<String>,
+        location: String,
+    },
+    /// Hand-written TOML timetable, see `ManualTimetable`
+    Manual { name: Option<String>, path: String },
+}
+
+impl SourceConfig {
+    /// Used when no configuration file is set
+    pub fn defaults() -> Vec<SourceConfig> {
+        vec![SourceConfig::Homeworker {
+            name: None,
+            course: None,
+            fallback: None,
+        }]
+    }
+
+    /// Label for the lessons of this source when several sources are merged
+    pub fn name(&self) -> String {
+        match self {
+            SourceConfig::Homeworker { name, .. } => {
+                name.clone().unwrap_or("Homeworker".to_owned())
+            }
+            SourceConfig::Ical { name, location } => name.clone().unwrap_or(location.clone()),
+            SourceConfig::Manual { name, path } => name.clone().unwrap_or(path.clone()),
+        }
+    }
+
+    /// The overrides and default course of `settings` are applied to Homeworker sources
+    pub fn build(
+        &self,
+        access_token: Option<String>,
+        settings: &UserSettings,
+    ) -> Result<Box<dyn TimetableSource>, SourceError> {
+        match self {
+            SourceConfig::Homeworker {
+                course, fallback, ..
+            } => {
+                let homeworker = HomeworkerSource::new(
+                    access_token.ok_or(SourceError::Unauthenticated)?,
+                    course.or(settings.default_course),
+                )
+                .with_overrides(settings.overrides.clone());
+                match fallback {
+                    Some(path) => Ok(Box::new(FallbackSource {
+                        primary: Box::new(homeworker),
+                        fallback: Box::new(ManualSource::new(path.clone())),
+                    })),
+                    None => Ok(Box::new(homeworker)),
+                }
+            }
+            SourceConfig::Ical { location, .. } => {
+                Ok(Box::new(IcalSource::new(CalendarLocation::parse(location), default_times())))
+            }
+            SourceConfig::Manual { path, .. } => Ok(Box::new(ManualSource::new(path.clone()))),
+        }
+    }
+
+    /// Checks the manual timetable files of this source, so mistakes show up on startup
+    pub async fn validate(&self) -> Result<(), String> {
+        let path = match self {
+            SourceConfig::Homeworker {
+                fallback: Some(path),
+                ..
+            }
+            | SourceConfig::Manual { path, .. } => path,
+            _ => return Ok(()),
+        };
+
+        match ManualSource::new(path.clone()).load().await {
+            Ok(_) => Ok(()),
+            Err(SourceError::Manual(e)) => Err(format!("{path}, {e}")),
+            Err(e) => Err(format!("{path}: {:?}", e)),
+        }
+    }
+}
+
+/// Uses `fallback` when `primary` can't reach Homeworker
+struct FallbackSource {
+    primary: Box<dyn TimetableSource>,
+    fallback: Box<dyn TimetableSource>,
+}
+
+#[cfg_attr(feature = "ssr", async_trait)]
+#[cfg_attr(not(feature = "ssr"), async_trait(?Send))]
+impl TimetableSource for FallbackSource {
+    async fn timetable(&self, week: Date) -> Result<Timetable, SourceError> {
+        match self.primary.timetable(week).await {
+            Err(SourceError::Homeworker(e)) => {
+                log::warn!("Homeworker unavailable, using fallback timetable: {:?}", e);
+                self.fallback.timetable(week).await
+            }
+            result => result,
+        }
+    }
+}
+
+/// Loads the timetable of all configured sources. A single source is returned as is, several are
+/// merged with their names as course labels. Only the weekdays chosen in `settings` are kept, and
+/// the holidays and week type of `calendar` are applied.
+pub async fn load_configured(
+    configs: &[SourceConfig],
+    calendar: &SchoolCalendar,
+    access_token: Option<String>,
+    settings: &UserSettings,
+    week: Date,
+) -> Result<Timetable, SourceError> {
+    let sources = configs
+        .iter()
+        .map(|config| Ok((config.name(), config.build(access_token.clone(), settings)?)))
+        .collect::<Result<_, SourceError>>()?;
+    load_sources(sources, calendar, settings, week).await
+}
+
+/// Id and name of the Homeworker course among the configured sources, for titles. `None` without a
+/// Homeworker source or a login, and while Homeworker can't be reached.
+pub async fn configured_course(
+    configs: &[SourceConfig],
+    access_token: Option<String>,
+    settings: &UserSettings,
+) -> Option<(u32, String)> {
+    let course = configs.iter().find_map(|config| match config {
+        SourceConfig::Homeworker { course, .. } => Some(course.or(settings.default_course)),
+        _ => None,
+    })?;
+    let source = HomeworkerSource::new(access_token?, course);
+    match (source.course_id().await, source.course_name().await) {
+        (Ok(id), Ok(name)) => Some((id, name.unwrap_or_default())),
+        (Err(e), _) | (_, Err(e)) => {
+            log::warn!("Looking up the Homeworker course failed: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Title of the configured sources: the Homeworker course if there is one, otherwise the names of
+/// the sources
+pub fn sources_title(configs: &[SourceConfig], course: Option<&(u32, String)>) -> String {
+    match course {
+        Some((_, name)) => name.clone(),
+        None => configs
+            .iter()
+            .filter(|config| !matches!(config, SourceConfig::Homeworker { .. }))
+            .map(SourceConfig::name)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Loads and combines already built sources with their names, see `load_configured`
+pub async fn load_sources(
+    sources: Vec<(String, Box<dyn TimetableSource>)>,
+    calendar: &SchoolCalendar,
+    settings: &UserSettings,
+    week: Date,
+) -> Result<Timetable, SourceError> {
+    if let [(_, source)] = sources.as_slice() {
+        let timetable = source.timetable(week).await?;
+        let timetable = calendar.apply(timetable.with_weekdays(week, &settings.weekdays), week);
+        return Ok(settings.overrides.for_week_type(timetable));
+    }
+
+    let mut timetables = vec![];
+    for (name, source) in sources {
+        timetables.push((name, source.timetable(week).await?));
+    }
+    let timetable = Timetable::merge(timetables).with_weekdays(week, &settings.weekdays);
+    Ok(settings
+        .overrides
+        .for_week_type(calendar.apply(timetable, week)))
+}
+
+fn period(start_hour: u8, start_minute: u8, end_hour: u8, end_minute: u8) -> Period {
+    Period {
+        start: Time::from_hms(start_hour, start_minute, 0).unwrap(),
+        end: Time::from_hms(end_hour, end_minute, 0).unwrap(),
+    }
+}
+
+/// Reads a local file of a source without blocking the other requests of the server
+pub async fn read_file(path: &str) -> Result<String, SourceError> {
+    #[cfg(feature = "ssr")]
+    let content = tokio::fs::read_to_string(path).await;
+    // Browsers have no files to read, this only keeps the sources buildable for them
+    #[cfg(not(feature = "ssr"))]
+    let content = std::fs::read_to_string(path);
+
+    content.map_err(|e| SourceError::Unavailable(format!("{path}: {e}")))
+}
+
+/// Period grid of the school, grouped by breaks (Currently hardcoded)
+pub fn default_times() -> Vec<Vec<Period>> {
+    vec![
+        vec![period(8, 0, 8, 45), period(8, 45, 9, 30)],
+        vec![period(9, 45, 10, 30), period(10, 30, 11, 15)],
+        vec![period(11, 35, 12, 20), period(12, 20, 13, 5)],
+        vec![
+            period(13, 20, 14, 5),
+            period(14, 5, 14, 50),
+            period(14, 5, 15, 35),
+        ],
+    ]
+}
+
+/// Parses dates like `2023-02-20`
+pub fn parse_date(date: &str) -> Option<Date> {
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = Month::try_from(parts.next()?.parse::<u8>().ok()?).ok()?;
+    Date::from_calendar_date(year, month, parts.next()?.parse().ok()?).ok()
+}
+
+/// Monday of the week containing `date`
+pub fn week_start(date: Date) -> Date {
+    date - Duration::days(date.weekday().number_days_from_monday() as i64)
+}
+
+/// The week to show by default. On weekends, that's already the next one.
+pub fn current_week(today: Date) -> Date {
+    match today.weekday() {
+        Weekday::Saturday | Weekday::Sunday => week_start(today) + Duration::weeks(1),
+        _ => week_start(today),
+    }
+}
+
+/// Loads the week to show by default with `load`, which gets its monday. On weekends the next week
+/// is only shown once it has lessons, as schools often publish it on monday. Until then, the week
+/// that just ended stays.
+pub async fn load_current_week<F>(
+    today: Date,
+    load: impl Fn(Date) -> F,
+) -> Result<(Date, Timetable), SourceError>
+where
+    F: Future<Output = Result<Timetable, SourceError>>,
+{
+    let week = current_week(today);
+    let timetable = load(week).await?;
+    if week == week_start(today) || !timetable.is_empty() {
+        return Ok((week, timetable));
+    }
+
+    let this_week = week_start(today);
+    Ok((this_week, load(this_week).await?))
+}
+
+/// Provided by the server with the configured sources
+#[derive(Clone, Copy)]
+pub struct SourcesContext {
+    pub configs: ReadSignal<Vec<SourceConfig>>,
+    pub calendar: ReadSignal<SchoolCalendar>,
+}
+
+/// Central European time with daylight saving time from the last sunday of march to the last
+/// sunday of october, both at 01:00 UTC
+pub fn utc_to_german_time(utc: PrimitiveDateTime) -> PrimitiveDateTime {
+    let last_sunday = |month: Month| {
+        let mut date = Date::from_calendar_date(utc.year(), month, 31).unwrap();
+        while date.weekday() != Weekday::Sunday {
+            date = date.previous_day().unwrap();
+        }
+        PrimitiveDateTime::new(date, Time::from_hms(1, 0, 0).unwrap())
+    };
+
+    match utc >= last_sunday(Month::March) && utc < last_sunday(Month::October) {
+        true => utc + Duration::hours(2),
+        false => utc + Duration::hours(1),
+    }
+}
+
+/// Local time of the school, independent of the time zone of the server
+pub fn german_now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    utc_to_german_time(PrimitiveDateTime::new(now.date(), now.time()))
+}
+
+/// Today at the school, so the week doesn't change at midnight UTC
+pub fn today() -> Date {
+    german_now().date()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use futures::executor::block_on;
+    use time::macros::{date, datetime};
+
+    use super::*;
+    use crate::test_util::{column, lesson};
+
+    /// Serves fixed timetables by week, weeks without one have no columns
+    struct FakeSource(BTreeMap<Date, Timetable>);
+
+    #[cfg_attr(feature = "ssr", async_trait)]
+    #[cfg_attr(not(feature = "ssr"), async_trait(?Send))]
+    impl TimetableSource for FakeSource {
+        async fn timetable(&self, week: Date) -> Result<Timetable, SourceError> {
+            Ok(self.0.get(&week).cloned().unwrap_or(Timetable {
+                times: default_times(),
+                columns: vec![],
+                week_type: None,
+            }))
+        }
+    }
+
+    /// One lesson in the first period of every day from monday to saturday
+    fn week_with_lessons(monday: Date, short_name: &str) -> Timetable {
+        let period_count = default_times().iter().map(Vec::len).sum();
+        let columns = (0..6)
+            .map(|day| {
+                let mut lessons = vec![None; period_count];
+                lessons[0] = Some(lesson(short_name));
+                column(monday + Duration::days(day), lessons)
+            })
+            .collect();
+        Timetable {
+            times: default_times(),
+            columns,
+            week_type: None,
+        }
+    }
+
+    fn fake(weeks: &[Date]) -> FakeSource {
+        FakeSource(
+            weeks
+                .iter()
+                .map(|week| (*week, week_with_lessons(*week, "M")))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn german_time_follows_daylight_saving_time() {
+        assert_eq!(utc_to_german_time(datetime!(2023-03-26 0:59)), datetime!(2023-03-26 1:59));
+        assert_eq!(utc_to_german_time(datetime!(2023-03-26 1:00)), datetime!(2023-03-26 3:00));
+        assert_eq!(utc_to_german_time(datetime!(2023-10-29 0:59)), datetime!(2023-10-29 2:59));
+        assert_eq!(utc_to_german_time(datetime!(2023-10-29 1:00)), datetime!(2023-10-29 2:00));
+        // The date changes an hour before midnight UTC
+        assert_eq!(utc_to_german_time(datetime!(2023-01-16 23:30)), datetime!(2023-01-17 0:30));
+    }
+
+    #[test]
+    fn current_week_moves_on_at_the_weekend() {
+        assert_eq!(current_week(date!(2023 - 01 - 20)), date!(2023 - 01 - 16));
+        assert_eq!(current_week(date!(2023 - 01 - 21)), date!(2023 - 01 - 23));
+        assert_eq!(current_week(date!(2023 - 01 - 22)), date!(2023 - 01 - 23));
+    }
+
+    #[test]
+    fn weekend_shows_the_next_week_once_it_has_lessons() {
+        let source = fake(&[date!(2023 - 01 - 16), date!(2023 - 01 - 23)]);
+
+        let (week, timetable) =
+            block_on(load_current_week(date!(2023 - 01 - 21), |week| source.timetable(week)))
+                .unwrap();
+
+        assert_eq!(week, date!(2023 - 01 - 23));
+        assert!(!timetable.is_empty());
+    }
+
+    #[test]
+    fn weekend_keeps_the_current_week_while_the_next_is_empty() {
+        let source = fake(&[date!(2023 - 01 - 16)]);
+
+        let (week, timetable) =
+            block_on(load_current_week(date!(2023 - 01 - 22), |week| source.timetable(week)))
+                .unwrap();
+
+        assert_eq!(week, date!(2023 - 01 - 16));
+        assert_eq!(timetable.columns[0].date, Some(date!(2023 - 01 - 16)));
+    }
+
+    #[test]
+    fn weekdays_show_the_current_week_even_if_empty() {
+        let source = fake(&[date!(2023 - 01 - 23)]);
+
+        let (week, timetable) =
+            block_on(load_current_week(date!(2023 - 01 - 18), |week| source.timetable(week)))
+                .unwrap();
+
+        assert_eq!(week, date!(2023 - 01 - 16));
+        assert!(timetable.is_empty());
+    }
+
+    #[test]
+    fn single_source_is_filtered_to_the_chosen_weekdays() {
+        let monday = date!(2023 - 01 - 16);
+        let sources: Vec<(String, Box<dyn TimetableSource>)> =
+            vec![("School".to_owned(), Box::new(fake(&[monday])))];
+
+        let timetable = block_on(load_sources(
+            sources,
+            &SchoolCalendar::default(),
+            &UserSettings::default(),
+            monday,
+        ))
+        .unwrap();
+
+        // Saturday isn't part of the default weekdays
+        assert_eq!(timetable.columns.len(), 5);
+        let lesson = timetable.columns[0].lessons[0].as_ref().unwrap();
+        assert_eq!(lesson.course, None);
+    }
+
+    #[test]
+    fn several_sources_are_merged_with_their_names() {
+        let monday = date!(2023 - 01 - 16);
+        let club = FakeSource(BTreeMap::from([(monday, week_with_lessons(monday, "C"))]));
+        let sources: Vec<(String, Box<dyn TimetableSource>)> = vec![
+            ("School".to_owned(), Box::new(fake(&[monday]))),
+            ("Club".to_owned(), Box::new(club)),
+        ];
+
+        let timetable = block_on(load_sources(
+            sources,
+            &SchoolCalendar::default(),
+            &UserSettings::default(),
+            monday,
+        ))
+        .unwrap();
+
+        // Both have a lesson in the first period, so every day has a parallel column
+        assert_eq!(timetable.columns.len(), 10);
+        assert_eq!(timetable.days().len(), 5);
+        let courses: Vec<_> = timetable.columns[..2]
+            .iter()
+            .map(|column| column.lessons[0].as_ref().unwrap().course.as_deref())
+            .collect();
+        assert_eq!(courses, [Some("School"), Some("Club")]);
+    }
+}
@@ -0,0 +1,390 @@
+//! Server-side storage of user settings and timetable snapshots, keyed by Homeworker user id.
+//!
+//! Everything is kept in memory and written to a single JSON file on every change, which is
+//! plenty for the number of users of one school. The file has a `version`, and older files are
+//! brought up to date by [`MIGRATIONS`] when the store is opened.
+
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+use time::Date;
+
+use crate::{
+    api::ApiTokenInfo,
+    types::{settings::UserSettings, timetable::Timetable},
+};
+
+/// Applied in order to files with an older `version`, the index is the version they upgrade from.
+/// Adding a field with a serde default, to `UserSettings` or a new map here, doesn't need a
+/// migration.
+const MIGRATIONS: &[fn(&mut Value)] = &[
+    // 0 -> 1: Initial layout
+    |data| data["users"] = json!({}),
+];
+
+/// Confirmation links are valid for this many days
+const EMAIL_CONFIRMATION_DAYS: i64 = 2;
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The file was written by a newer version of the app
+    UnknownVersion(u64),
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(error: std::io::Error) -> Self {
+        StoreError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(error: serde_json::Error) -> Self {
+        StoreError::Json(error)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoreData {
+    version: u64,
+    users: BTreeMap<u32, UserSettings>,
+    /// Last timetable fetched for change alerts
+    #[serde(default)]
+    snapshots: BTreeMap<u32, Timetable>,
+    /// Refresh tokens sealed with the server key, so changes can be fetched while the user is away.
+    /// Kept apart from the settings, which are sent to the browser.
+    #[serde(default)]
+    alert_tokens: BTreeMap<u32, String>,
+    /// Timetable the user saw on their last visit to the dashboard
+    #[serde(default)]
+    visits: BTreeMap<u32, Timetable>,
+    /// Personal API tokens by the hash of the token
+    #[serde(default)]
+    api_tokens: BTreeMap<String, ApiToken>,
+    /// By the hash of the token in the confirmation link
+    #[serde(default)]
+    email_confirmations: BTreeMap<String, EmailConfirmation>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Short id to tell the tokens of a user apart, e.g. to revoke one
+    pub id: String,
+    pub user_id: u32,
+    pub created: Date,
+    /// Refresh token of the user sealed with the server key
+    pub refresh_token: String,
+}
+
+/// Email address a user entered for alerts. It is only used once the link sent to it was opened, so
+/// nobody can have alerts sent to addresses that aren't theirs.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmailConfirmation {
+    pub user_id: u32,
+    pub address: String,
+    pub created: Date,
+}
+
+#[derive(Clone)]
+pub struct SettingsStore {
+    path: PathBuf,
+    data: Arc<RwLock<StoreData>>,
+}
+
+impl SettingsStore {
+    /// Creates the file if it doesn't exist yet
+    pub async fn open(path: impl Into<PathBuf>) -> Result<SettingsStore, StoreError> {
+        let path = path.into();
+        let mut value = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => json!({}),
+            Err(e) => return Err(e.into()),
+        };
+
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+        let latest = MIGRATIONS.len() as u64;
+        if version > latest {
+            return Err(StoreError::UnknownVersion(version));
+        }
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(&mut value);
+        }
+        value["version"] = latest.into();
+
+        let store = SettingsStore {
+            path,
+            data: Arc::new(RwLock::new(serde_json::from_value(value)?)),
+        };
+        if version < latest {
+            store.persist(&*store.data.read().await).await?;
+        }
+        Ok(store)
+    }
+
+    /// Default settings for users that haven't changed anything yet
+    pub async fn get(&self, user_id: u32) -> UserSettings {
+        self.data
+            .read()
+            .await
+            .users
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn users(&self) -> Vec<(u32, UserSettings)> {
+        let data = self.data.read().await;
+        data.users
+            .iter()
+            .map(|(id, settings)| (*id, settings.clone()))
+            .collect()
+    }
+
+    /// Changes the settings of a user and writes them to disk before returning
+    pub async fn update(
+        &self,
+        user_id: u32,
+        change: impl FnOnce(&mut UserSettings),
+    ) -> Result<UserSettings, StoreError> {
+        self.modify(|data| {
+            let settings = data.users.entry(user_id).or_default();
+            change(settings);
+            settings.clone()
+        })
+        .await
+    }
+
+    pub async fn snapshot(&self, user_id: u32) -> Option<Timetable> {
+        self.data.read().await.snapshots.get(&user_id).cloned()
+    }
+
+    pub async fn set_snapshot(&self, user_id: u32, timetable: Timetable) -> Result<(), StoreError> {
+        self.modify(|data| {
+            data.snapshots.insert(user_id, timetable);
+        })
+        .await
+    }
+
+    /// Stores the timetable of this visit and returns the one of the previous visit. Visits without
+    /// changes don't write the file.
+    pub async fn replace_visit(
+        &self,
+        user_id: u32,
+        timetable: Timetable,
+    ) -> Result<Option<Timetable>, StoreError> {
+        if self.data.read().await.visits.get(&user_id) == Some(&timetable) {
+            return Ok(Some(timetable));
+        }
+        self.modify(|data| data.visits.insert(user_id, timetable))
+            .await
+    }
+
+    pub async fn alert_token(&self, user_id: u32) -> Option<String> {
+        self.data.read().await.alert_tokens.get(&user_id).cloned()
+    }
+
+    /// `None` removes the token, which stops change alerts for the user
+    pub async fn set_alert_token(
+        &self,
+        user_id: u32,
+        token: Option<String>,
+    ) -> Result<(), StoreError> {
+        self.modify(|data| match token {
+            Some(token) => {
+                data.alert_tokens.insert(user_id, token);
+            }
+            None => {
+                data.alert_tokens.remove(&user_id);
+            }
+        })
+        .await
+    }
+
+    pub async fn api_token(&self, hash: &str) -> Option<ApiToken> {
+        self.data.read().await.api_tokens.get(hash).cloned()
+    }
+
+    /// Tokens of a user, oldest first
+    pub async fn api_tokens(&self, user_id: u32) -> Vec<ApiTokenInfo> {
+        let data = self.data.read().await;
+        let mut tokens: Vec<ApiTokenInfo> = data
+            .api_tokens
+            .values()
+            .filter(|token| token.user_id == user_id)
+            .map(|token| ApiTokenInfo {
+                id: token.id.clone(),
+                created: token.created,
+            })
+            .collect();
+        tokens.sort_by(|a, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+        tokens
+    }
+
+    pub async fn add_api_token(&self, hash: String, token: ApiToken) -> Result<(), StoreError> {
+        self.modify(|data| {
+            data.api_tokens.insert(hash, token);
+        })
+        .await
+    }
+
+    /// Only removes tokens of `user_id`, so users can't revoke the tokens of others
+    pub async fn revoke_api_token(&self, user_id: u32, id: &str) -> Result<(), StoreError> {
+        self.modify(|data| {
+            data.api_tokens
+                .retain(|_, token| token.user_id != user_id || token.id != id)
+        })
+        .await
+    }
+
+    /// Replaces any unconfirmed address of the same user
+    pub async fn add_email_confirmation(
+        &self,
+        hash: String,
+        confirmation: EmailConfirmation,
+    ) -> Result<(), StoreError> {
+        self.modify(|data| {
+            data.email_confirmations
+                .retain(|_, other| other.user_id != confirmation.user_id);
+            data.email_confirmations.insert(hash, confirmation);
+        })
+        .await
+    }
+
+    /// Sets the address as the email of the user, if the link is still valid. Links work once.
+    pub async fn confirm_email(&self, hash: &str, today: Date) -> Result<Option<u32>, StoreError> {
+        self.modify(|data| {
+            let confirmation = data
+                .email_confirmations
+                .remove(hash)
+                .filter(|c| today - c.created <= time::Duration::days(EMAIL_CONFIRMATION_DAYS))?;
+            let settings = data.users.entry(confirmation.user_id).or_default();
+            settings.notifications.email = Some(confirmation.address);
+            Some(confirmation.user_id)
+        })
+        .await
+    }
+
+    /// The change is made to a copy, which only replaces the data in memory once it is written, so
+    /// a failed write changes nothing
+    async fn modify<T>(&self, change: impl FnOnce(&mut StoreData) -> T) -> Result<T, StoreError> {
+        let mut data = self.data.write().await;
+        let mut changed = data.clone();
+        let result = change(&mut changed);
+        self.persist(&changed).await?;
+        *data = changed;
+        Ok(result)
+    }
+
+    /// Writes to a temporary file first, so a crash can't leave a half-written file behind
+    async fn persist(&self, data: &StoreData) -> Result<(), StoreError> {
+        let temp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec_pretty(data)?).await?;
+        tokio::fs::rename(&temp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+/// Provided to server functions with the store and the user making the request
+#[derive(Clone)]
+pub struct StoreContext {
+    pub store: SettingsStore,
+    pub user_id: Option<u32>,
+    /// Refresh token of the user sealed with the server key, stored when change alerts are enabled
+    pub alert_token: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::test_util::{column, lesson, temp_dir, timetable};
+
+    fn stored_version(path: &std::path::Path) -> Option<u64> {
+        let content = std::fs::read_to_string(path).unwrap();
+        serde_json::from_str::<Value>(&content).unwrap()["version"].as_u64()
+    }
+
+    #[tokio::test]
+    async fn files_without_a_version_are_migrated() {
+        let path = temp_dir("store-migration").join("settings.json");
+        std::fs::write(&path, "{}").unwrap();
+
+        let store = SettingsStore::open(&path).await.unwrap();
+
+        assert_eq!(stored_version(&path), Some(MIGRATIONS.len() as u64));
+        assert!(store.users().await.is_empty());
+        assert_eq!(store.snapshot(1).await, None);
+    }
+
+    #[tokio::test]
+    async fn missing_maps_are_empty() {
+        let path = temp_dir("store-defaults").join("settings.json");
+        std::fs::write(&path, r#"{"version": 1, "users": {"1": {}}}"#).unwrap();
+
+        let store = SettingsStore::open(&path).await.unwrap();
+
+        assert_eq!(store.get(1).await, UserSettings::default());
+        assert_eq!(store.alert_token(1).await, None);
+        assert!(store.api_tokens(1).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn newer_files_are_not_opened() {
+        let path = temp_dir("store-newer").join("settings.json");
+        let version = MIGRATIONS.len() + 1;
+        std::fs::write(&path, format!(r#"{{"version": {version}, "users": {{}}}}"#)).unwrap();
+
+        let result = SettingsStore::open(&path).await;
+
+        assert!(matches!(result, Err(StoreError::UnknownVersion(v)) if v == version as u64));
+    }
+
+    #[tokio::test]
+    async fn changes_are_read_back_after_a_restart() {
+        let path = temp_dir("store-round-trip").join("settings.json");
+        let snapshot =
+            timetable(&[1], vec![column(date!(2023 - 01 - 16), vec![Some(lesson("M"))])]);
+
+        let store = SettingsStore::open(&path).await.unwrap();
+        let settings = store
+            .update(1, |settings| settings.default_course = Some(42))
+            .await
+            .unwrap();
+        store.set_snapshot(1, snapshot.clone()).await.unwrap();
+        store
+            .set_alert_token(1, Some("sealed".to_owned()))
+            .await
+            .unwrap();
+
+        let reopened = SettingsStore::open(&path).await.unwrap();
+        assert_eq!(reopened.get(1).await, settings);
+        assert_eq!(reopened.get(1).await.default_course, Some(42));
+        assert_eq!(reopened.snapshot(1).await, Some(snapshot));
+        assert_eq!(reopened.alert_token(1).await.as_deref(), Some("sealed"));
+
+        reopened.set_alert_token(1, None).await.unwrap();
+        let reopened = SettingsStore::open(&path).await.unwrap();
+        assert_eq!(reopened.alert_token(1).await, None);
+    }
+
+    #[tokio::test]
+    async fn failed_writes_change_nothing() {
+        let dir = temp_dir("store-failed-write");
+        let store = SettingsStore::open(dir.join("settings.json"))
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let result = store
+            .update(1, |settings| settings.default_course = Some(42))
+            .await;
+
+        assert!(matches!(result, Err(StoreError::Io(_))));
+        assert_eq!(store.get(1).await.default_course, None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Logging, request spans and Prometheus metrics of the server.
+//!
+//! `RUST_LOG` filters the output (`info` by default) and `LOG_FORMAT=json` switches to one JSON
+//! object per line. Records of the `log` crate, which the shared code uses, end up in the span of
+//! the request they belong to.
+
+use std::{future::Future, time::Instant};
+
+use axum::{extract::MatchedPath, middleware::Next, response::Response};
+use hmac::{Hmac, Mac};
+use http::Request;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sha2::Sha256;
+use tracing::{field::Empty, Instrument};
+use tracing_subscriber::EnvFilter;
+
+/// Buckets of all `*_seconds` histograms. Homeworker sometimes takes several seconds in the morning.
+const LATENCY_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Installs the subscriber and the metrics recorder. The handle renders `/metrics`.
+pub fn init() -> PrometheusHandle {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => subscriber.json().init(),
+        _ => subscriber.init(),
+    }
+
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("seconds".to_owned()), LATENCY_BUCKETS)
+        .expect("latency buckets are empty")
+        .install_recorder()
+        .expect("couldn't install the metrics recorder")
+}
+
+/// Stable per deployment, so the requests of one user can be followed without logging their id.
+/// Keyed with `HW_SECRET`, so the ids can't be recovered by hashing all of them. The first 8 bytes
+/// of the HMAC are enough to tell users apart in the logs.
+pub fn user_hash(user_id: u32) -> String {
+    let secret = std::env::var("HW_SECRET").unwrap_or_default();
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(&user_id.to_be_bytes());
+    mac.finalize().into_bytes()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Adds the user to the span of the current request, once it is known
+pub fn record_user(user_id: u32) {
+    tracing::Span::current().record("user", user_hash(user_id).as_str());
+}
+
+/// Runs every request in a span with its route, and counts and times it by route and status.
+/// Added as a route layer, so only routes are tracked and not the static files of the fallback.
+pub async fn track_request<B>(request: Request<B>, next: Next<B>) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unknown".to_owned(), |path| path.as_str().to_owned());
+    let method = request.method().to_string();
+    let span = tracing::info_span!("request", %method, %route, user = Empty, status = Empty);
+
+    let start = Instant::now();
+    let response = next.run(request).instrument(span.clone()).await;
+    let seconds = start.elapsed().as_secs_f64();
+
+    let status = response.status().as_u16().to_string();
+    span.record("status", status.as_str());
+    span.in_scope(|| tracing::debug!(seconds, "Request finished"));
+
+    metrics::increment_counter!(
+        "http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status
+    );
+    metrics::histogram!(
+        "http_request_duration_seconds",
+        seconds,
+        "method" => method,
+        "route" => route
+    );
+
+    response
+}
+
+/// Short name of the error for logs and metrics, e.g. to tell timeouts from rejected tokens
+pub fn error_kind(error: &homeworker::Error) -> &'static str {
+    match error {
+        homeworker::Error::RequestError(error) if error.is_timeout() => "timeout",
+        homeworker::Error::RequestError(error) if error.is_connect() => "connect",
+        homeworker::Error::RequestError(error) if error.is_decode() => "decode",
+        homeworker::Error::RequestError(_) => "request",
+        homeworker::Error::ApiError(_) => "api",
+    }
+}
+
+/// Times a call to Homeworker. `operation` names the endpoint in the span and the metrics.
+pub async fn upstream<T>(
+    operation: &'static str,
+    call: impl Future<Output = Result<T, homeworker::Error>>,
+) -> Result<T, homeworker::Error> {
+    let span = tracing::info_span!("homeworker", operation, error_kind = Empty);
+
+    let start = Instant::now();
+    let result = call.instrument(span.clone()).await;
+    let seconds = start.elapsed().as_secs_f64();
+
+    let outcome = match &result {
+        Ok(_) => "ok",
+        Err(error) => {
+            let kind = error_kind(error);
+            span.record("error_kind", kind);
+            span.in_scope(|| tracing::warn!(seconds, "Homeworker call failed: {:?}", error));
+            kind
+        }
+    };
+    metrics::histogram!(
+        "homeworker_request_duration_seconds",
+        seconds,
+        "operation" => operation,
+        "outcome" => outcome
+    );
+
+    result
+}
+
+/// `source` tells the refresh of user cookies from the ones of API tokens and the display account
+pub fn token_refresh(source: &'static str, success: bool) {
+    metrics::increment_counter!(
+        "token_refreshes_total",
+        "source" => source,
+        "result" => if success { "success" } else { "failure" }
+    );
+}
+
+/// Hit rates of the in-memory caches, e.g. the display token or the user id cookie
+pub fn cache_lookup(cache: &'static str, hit: bool) {
+    metrics::increment_counter!(
+        "cache_lookups_total",
+        "cache" => cache,
+        "result" => if hit { "hit" } else { "miss" }
+    );
+}
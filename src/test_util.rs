@@ -0,0 +1,65 @@
+//! Helpers shared by the unit tests of several modules
+
+use std::path::PathBuf;
+
+use time::{macros::time, Date};
+
+use crate::types::timetable::{Lesson, LessonStatus, Period, Subject, Timetable, TimetableColumn};
+
+/// Empty directory for the files of one test, unique per test name and process
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("desktop-timetable-{name}-{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn subject(short_name: &str) -> Subject {
+    Subject {
+        full_name: short_name.to_owned(),
+        short_name: short_name.to_owned(),
+        teacher: "Teacher".to_owned(),
+        room: "101".to_owned(),
+        color: (255, 255, 255),
+    }
+}
+
+pub fn lesson(short_name: &str) -> Lesson {
+    Lesson {
+        subject: subject(short_name),
+        status: LessonStatus::Normal,
+        course: None,
+    }
+}
+
+pub fn with_status(short_name: &str, status: LessonStatus) -> Lesson {
+    Lesson {
+        status,
+        ..lesson(short_name)
+    }
+}
+
+/// Named after the weekday of `date`
+pub fn column(date: Date, lessons: Vec<Option<Lesson>>) -> TimetableColumn {
+    TimetableColumn {
+        name: date.weekday().to_string(),
+        date: Some(date),
+        lessons,
+        appointments: vec![],
+        holiday: None,
+    }
+}
+
+/// `groups` are the number of periods between two breaks. All periods have the same time, only
+/// their positions matter.
+pub fn timetable(groups: &[usize], columns: Vec<TimetableColumn>) -> Timetable {
+    let period = Period {
+        start: time!(8:00),
+        end: time!(8:45),
+    };
+    Timetable {
+        times: groups.iter().map(|count| vec![period; *count]).collect(),
+        columns,
+        week_type: None,
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::types::timetable::{Lesson, LessonStatus, Period, Timetable};
+
+/// Free periods and used rooms of several timetables of the same week, per day and position
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Availability {
+    pub times: Vec<Vec<Period>>,
+    pub days: Vec<Date>,
+    /// Number of timetables, the most `free` can be
+    pub timetables: usize,
+    /// Indexed by day, then position: how many timetables have no lesson
+    pub free: Vec<Vec<usize>>,
+    /// Every room used in any of the timetables during the week. Rooms none of them use are unknown.
+    pub rooms: BTreeSet<String>,
+    /// Indexed by day, then position
+    pub occupied: Vec<Vec<BTreeSet<String>>>,
+}
+
+/// Cancelled lessons free their period and room, substitutions may take place in another room
+fn taking_place(lesson: &Lesson) -> Option<&str> {
+    match &lesson.status {
+        LessonStatus::Normal => Some(&lesson.subject.room),
+        LessonStatus::Cancelled => None,
+        LessonStatus::Replaced(subject) => Some(&subject.room),
+    }
+}
+
+impl Availability {
+    /// The periods are taken from the first timetable, as all courses of a school share them
+    pub fn new(timetables: &[Timetable]) -> Availability {
+        let times = timetables
+            .first()
+            .map(|timetable| timetable.times.clone())
+            .unwrap_or_default();
+        let positions = times.concat().len();
+
+        let days: Vec<Date> = timetables
+            .iter()
+            .flat_map(|timetable| &timetable.columns)
+            .filter_map(|column| column.date)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut free = vec![vec![timetables.len(); positions]; days.len()];
+        let mut occupied = vec![vec![BTreeSet::new(); positions]; days.len()];
+        let mut rooms = BTreeSet::new();
+
+        for timetable in timetables {
+            for (day_index, day) in days.iter().enumerate() {
+                // Parallel columns of a day count once, a timetable is busy if any of them has a lesson
+                let mut busy = vec![false; positions];
+                for column in timetable.columns.iter().filter(|c| c.date == Some(*day)) {
+                    for (position, lesson) in column.lessons.iter().enumerate().take(positions) {
+                        let Some(room) = lesson.as_ref().and_then(taking_place) else {
+                            continue;
+                        };
+                        busy[position] = true;
+                        if !room.is_empty() {
+                            rooms.insert(room.to_owned());
+                            occupied[day_index][position].insert(room.to_owned());
+                        }
+                    }
+                }
+                for (position, busy) in busy.into_iter().enumerate() {
+                    if busy {
+                        free[day_index][position] -= 1;
+                    }
+                }
+            }
+        }
+
+        Availability {
+            times,
+            days,
+            timetables: timetables.len(),
+            free,
+            rooms,
+            occupied,
+        }
+    }
+
+    /// Everyone is free in this period
+    pub fn all_free(&self, day: usize, position: usize) -> bool {
+        self.free[day][position] == self.timetables
+    }
+
+    pub fn free_rooms(&self, day: usize, position: usize) -> Vec<&str> {
+        self.rooms
+            .difference(&self.occupied[day][position])
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::test_util::{column, lesson, subject, timetable, with_status};
+
+    const MONDAY: Date = date!(2023 - 01 - 16);
+    const TUESDAY: Date = date!(2023 - 01 - 17);
+
+    fn in_room(short_name: &str, room: &str) -> Lesson {
+        let mut lesson = lesson(short_name);
+        lesson.subject.room = room.to_owned();
+        lesson
+    }
+
+    #[test]
+    fn counts_the_free_timetables_per_period() {
+        let first = timetable(&[3], vec![column(MONDAY, vec![Some(lesson("M")), None, None])]);
+        let second = timetable(
+            &[3],
+            vec![
+                column(MONDAY, vec![Some(lesson("D")), Some(lesson("D")), None]),
+                column(TUESDAY, vec![None, None, None]),
+            ],
+        );
+
+        let availability = Availability::new(&[first, second]);
+
+        assert_eq!(availability.days, [MONDAY, TUESDAY]);
+        assert_eq!(availability.timetables, 2);
+        assert_eq!(availability.free, [vec![0, 1, 2], vec![2, 2, 2]]);
+        assert!(availability.all_free(0, 2));
+        assert!(!availability.all_free(0, 1));
+    }
+
+    #[test]
+    fn parallel_columns_of_a_day_count_once() {
+        let parallel = timetable(
+            &[2],
+            vec![
+                column(MONDAY, vec![Some(lesson("M")), None]),
+                column(MONDAY, vec![Some(lesson("E")), Some(lesson("E"))]),
+            ],
+        );
+        let other = timetable(&[2], vec![column(MONDAY, vec![None, None])]);
+
+        let availability = Availability::new(&[parallel, other]);
+
+        assert_eq!(availability.days, [MONDAY]);
+        assert_eq!(availability.free, [vec![1, 1]]);
+    }
+
+    #[test]
+    fn cancelled_lessons_free_their_room_and_replacements_use_theirs() {
+        let mut replacement = subject("E");
+        replacement.room = "B12".to_owned();
+        let timetable = timetable(
+            &[3],
+            vec![column(
+                MONDAY,
+                vec![
+                    Some(with_status("M", LessonStatus::Cancelled)),
+                    Some(with_status("D", LessonStatus::Replaced(replacement))),
+                    Some(in_room("PH", "A1")),
+                ],
+            )],
+        );
+
+        let availability = Availability::new(&[timetable]);
+
+        assert_eq!(availability.free, [vec![1, 0, 0]]);
+        assert_eq!(availability.rooms, BTreeSet::from(["A1".to_owned(), "B12".to_owned()]));
+        // The cancelled lesson's room isn't known to be used at all
+        assert_eq!(availability.free_rooms(0, 0), ["A1", "B12"]);
+        assert_eq!(availability.free_rooms(0, 1), ["A1"]);
+        assert_eq!(availability.free_rooms(0, 2), ["B12"]);
+    }
+
+    #[test]
+    fn lessons_without_a_room_are_busy_without_a_room() {
+        let timetable = timetable(&[2], vec![column(MONDAY, vec![Some(in_room("SP", "")), None])]);
+
+        let availability = Availability::new(&[timetable]);
+
+        assert_eq!(availability.free, [vec![0, 1]]);
+        assert!(availability.rooms.is_empty());
+    }
+
+    #[test]
+    fn uses_the_periods_of_the_first_timetable() {
+        let short = timetable(&[2], vec![column(MONDAY, vec![None, None])]);
+        let long = timetable(&[3], vec![column(MONDAY, vec![None, None, Some(lesson("M"))])]);
+
+        let availability = Availability::new(&[short, long]);
+
+        assert_eq!(availability.times.concat().len(), 2);
+        assert_eq!(availability.free, [vec![2, 2]]);
+    }
+
+    #[test]
+    fn nothing_to_compare() {
+        let availability = Availability::new(&[]);
+
+        assert!(availability.days.is_empty());
+        assert_eq!(availability.timetables, 0);
+    }
+}
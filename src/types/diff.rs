@@ -0,0 +1,462 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::types::timetable::{Lesson, LessonStatus, Period, Subject, Timetable};
+
+/// Where a lesson is: the column, identified by name and date, and its positions in the grid.
+/// Double lessons span two positions.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Slot {
+    pub column: String,
+    pub date: Option<Date>,
+    pub positions: Range<usize>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum LessonChange {
+    Added {
+        slot: Slot,
+        lesson: Lesson,
+    },
+    Removed {
+        slot: Slot,
+        lesson: Lesson,
+    },
+    Moved {
+        from: Slot,
+        to: Slot,
+        lesson: Lesson,
+    },
+    Cancelled {
+        slot: Slot,
+        lesson: Lesson,
+    },
+    Replaced {
+        slot: Slot,
+        lesson: Lesson,
+        replacement: Subject,
+    },
+    /// A cancellation or replacement was taken back
+    Restored {
+        slot: Slot,
+        lesson: Lesson,
+    },
+    /// Same subject, but e.g. a different room or teacher
+    Changed {
+        slot: Slot,
+        old: Lesson,
+        new: Lesson,
+    },
+}
+
+/// A period at `position` that was added, removed or has different times
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct PeriodChange {
+    pub position: usize,
+    pub old: Option<Period>,
+    pub new: Option<Period>,
+}
+
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TimetableDiff {
+    pub lessons: Vec<LessonChange>,
+    pub periods: Vec<PeriodChange>,
+}
+
+impl TimetableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.lessons.is_empty() && self.periods.is_empty()
+    }
+}
+
+/// Consecutive equal lessons of a column, compared as one
+#[derive(Clone)]
+struct Block {
+    slot: Slot,
+    lesson: Lesson,
+}
+
+fn blocks(timetable: &Timetable) -> Vec<Block> {
+    let mut blocks: Vec<Block> = vec![];
+    for column in &timetable.columns {
+        for (position, lesson) in column.lessons.iter().enumerate() {
+            let Some(lesson) = lesson else { continue };
+            match blocks.last_mut() {
+                Some(block)
+                    if block.slot.column == column.name
+                        && block.slot.date == column.date
+                        && block.slot.positions.end == position
+                        && block.lesson == *lesson =>
+                {
+                    block.slot.positions.end += 1;
+                }
+                _ => blocks.push(Block {
+                    slot: Slot {
+                        column: column.name.clone(),
+                        date: column.date,
+                        positions: position..position + 1,
+                    },
+                    lesson: lesson.clone(),
+                }),
+            }
+        }
+    }
+    blocks
+}
+
+/// Parts of `block` outside of the `covered` positions, e.g. the second half of a double lesson
+/// that only exists on one side
+fn uncovered(block: &Block, covered: &[Range<usize>]) -> Vec<Block> {
+    let mut parts: Vec<Block> = vec![];
+    for position in block.slot.positions.clone() {
+        if covered.iter().any(|range| range.contains(&position)) {
+            continue;
+        }
+        match parts.last_mut() {
+            Some(part) if part.slot.positions.end == position => part.slot.positions.end += 1,
+            _ => parts.push(Block {
+                slot: Slot {
+                    positions: position..position + 1,
+                    ..block.slot.clone()
+                },
+                lesson: block.lesson.clone(),
+            }),
+        }
+    }
+    parts
+}
+
+/// The positions both slots share, if one lies within the other in the same column. Cancelling
+/// one half of a double lesson splits its block, and the halves still belong to the old block.
+fn nested(a: &Slot, b: &Slot) -> Option<Slot> {
+    let contains = |outer: &Range<usize>, inner: &Range<usize>| {
+        outer.start <= inner.start && inner.end <= outer.end
+    };
+    (a.column == b.column
+        && a.date == b.date
+        && (contains(&a.positions, &b.positions) || contains(&b.positions, &a.positions)))
+    .then(|| Slot {
+        positions: a.positions.start.max(b.positions.start)..a.positions.end.min(b.positions.end),
+        ..a.clone()
+    })
+}
+
+/// Changes of a lesson that stayed in `slot`
+fn changes_in_place(slot: Slot, old_lesson: &Lesson, new_lesson: &Lesson) -> Vec<LessonChange> {
+    let mut changes = vec![];
+    if old_lesson.status != new_lesson.status {
+        changes.push(match &new_lesson.status {
+            LessonStatus::Cancelled => LessonChange::Cancelled {
+                slot: slot.clone(),
+                lesson: new_lesson.clone(),
+            },
+            LessonStatus::Replaced(replacement) => LessonChange::Replaced {
+                slot: slot.clone(),
+                lesson: new_lesson.clone(),
+                replacement: replacement.clone(),
+            },
+            LessonStatus::Normal => LessonChange::Restored {
+                slot: slot.clone(),
+                lesson: new_lesson.clone(),
+            },
+        });
+    }
+    if !same_lesson(old_lesson, new_lesson) {
+        changes.push(LessonChange::Changed {
+            slot,
+            old: old_lesson.clone(),
+            new: new_lesson.clone(),
+        });
+    }
+    changes
+}
+
+/// The same lesson, apart from its status
+fn same_lesson(a: &Lesson, b: &Lesson) -> bool {
+    a.subject == b.subject && a.course == b.course
+}
+
+fn same_subject(a: &Lesson, b: &Lesson) -> bool {
+    a.subject.short_name == b.subject.short_name && a.course == b.course
+}
+
+/// Changes from `old` to `new`. Lessons of the same subject are matched in place first, if the
+/// positions of one lie within the other. Lessons and parts of them left over on both sides are
+/// moves if they are the same lesson.
+pub fn diff(old: &Timetable, new: &Timetable) -> TimetableDiff {
+    let mut old_blocks = blocks(old);
+    let new_blocks = blocks(new);
+    let mut lessons = vec![];
+
+    // Lessons dropped for holidays aren't changes anyone needs to hear about
+    let holidays: Vec<Date> = new
+        .columns
+        .iter()
+        .filter(|column| column.holiday.is_some())
+        .filter_map(|column| column.date)
+        .collect();
+    old_blocks.retain(|block| {
+        block
+            .slot
+            .date
+            .map_or(true, |date| !holidays.contains(&date))
+    });
+
+    // Same place and subject, only the status or details changed
+    let mut old_covered: Vec<Vec<Range<usize>>> = vec![vec![]; old_blocks.len()];
+    let mut new_left = vec![];
+    for new_block in &new_blocks {
+        let mut covered = vec![];
+        for (old_block, old_covered) in old_blocks.iter().zip(&mut old_covered) {
+            if !same_subject(&old_block.lesson, &new_block.lesson) {
+                continue;
+            }
+            let Some(slot) = nested(&old_block.slot, &new_block.slot) else {
+                continue;
+            };
+            covered.push(slot.positions.clone());
+            old_covered.push(slot.positions.clone());
+            lessons.extend(changes_in_place(slot, &old_block.lesson, &new_block.lesson));
+        }
+        new_left.extend(uncovered(new_block, &covered));
+    }
+    let mut old_blocks: Vec<Block> = old_blocks
+        .iter()
+        .zip(&old_covered)
+        .flat_map(|(block, covered)| uncovered(block, covered))
+        .collect();
+
+    for new_block in new_left {
+        match old_blocks
+            .iter()
+            .position(|old_block| same_lesson(&old_block.lesson, &new_block.lesson))
+        {
+            Some(index) => lessons.push(LessonChange::Moved {
+                from: old_blocks.remove(index).slot,
+                to: new_block.slot,
+                lesson: new_block.lesson,
+            }),
+            None => lessons.push(LessonChange::Added {
+                slot: new_block.slot,
+                lesson: new_block.lesson,
+            }),
+        }
+    }
+    lessons.extend(old_blocks.into_iter().map(|block| LessonChange::Removed {
+        slot: block.slot,
+        lesson: block.lesson,
+    }));
+
+    let old_periods = old.times.concat();
+    let new_periods = new.times.concat();
+    let periods = (0..old_periods.len().max(new_periods.len()))
+        .filter_map(|position| {
+            let (old, new) = (old_periods.get(position), new_periods.get(position));
+            (old != new).then(|| PeriodChange {
+                position,
+                old: old.copied(),
+                new: new.copied(),
+            })
+        })
+        .collect();
+
+    TimetableDiff { lessons, periods }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, time};
+
+    use super::*;
+    use crate::test_util::{self, column, lesson, subject, with_status};
+
+    const MONDAY: Date = date!(2023 - 01 - 16);
+
+    /// One monday with four periods
+    fn timetable(lessons: Vec<Option<Lesson>>) -> Timetable {
+        test_util::timetable(&[4], vec![column(MONDAY, lessons)])
+    }
+
+    fn slot(positions: Range<usize>) -> Slot {
+        Slot {
+            column: "Monday".to_owned(),
+            date: Some(MONDAY),
+            positions,
+        }
+    }
+
+    #[test]
+    fn equal_timetables_have_no_changes() {
+        let old = timetable(vec![Some(lesson("M")), Some(lesson("M")), None, None]);
+
+        assert!(diff(&old, &old.clone()).is_empty());
+    }
+
+    #[test]
+    fn lessons_are_added_and_removed() {
+        let old = timetable(vec![Some(lesson("M")), None, None, None]);
+        let new = timetable(vec![None, None, Some(lesson("D")), None]);
+
+        assert_eq!(
+            diff(&old, &new).lessons,
+            [
+                LessonChange::Added {
+                    slot: slot(2..3),
+                    lesson: lesson("D"),
+                },
+                LessonChange::Removed {
+                    slot: slot(0..1),
+                    lesson: lesson("M"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn double_lessons_are_moved_as_one() {
+        let old = timetable(vec![Some(lesson("M")), Some(lesson("M")), None, None]);
+        let new = timetable(vec![None, None, Some(lesson("M")), Some(lesson("M"))]);
+
+        assert_eq!(
+            diff(&old, &new).lessons,
+            [LessonChange::Moved {
+                from: slot(0..2),
+                to: slot(2..4),
+                lesson: lesson("M"),
+            }]
+        );
+    }
+
+    #[test]
+    fn status_changes_stay_in_place() {
+        let replacement = subject("E");
+        let old = timetable(vec![
+            Some(lesson("M")),
+            Some(lesson("D")),
+            Some(with_status("E", LessonStatus::Cancelled)),
+            None,
+        ]);
+        let new = timetable(vec![
+            Some(with_status("M", LessonStatus::Cancelled)),
+            Some(with_status("D", LessonStatus::Replaced(replacement.clone()))),
+            Some(lesson("E")),
+            None,
+        ]);
+
+        assert_eq!(
+            diff(&old, &new).lessons,
+            [
+                LessonChange::Cancelled {
+                    slot: slot(0..1),
+                    lesson: with_status("M", LessonStatus::Cancelled),
+                },
+                LessonChange::Replaced {
+                    slot: slot(1..2),
+                    lesson: with_status("D", LessonStatus::Replaced(replacement.clone())),
+                    replacement,
+                },
+                LessonChange::Restored {
+                    slot: slot(2..3),
+                    lesson: lesson("E"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn other_rooms_are_changes() {
+        let mut moved = lesson("M");
+        moved.subject.room = "202".to_owned();
+        let old = timetable(vec![Some(lesson("M")), None, None, None]);
+        let new = timetable(vec![Some(moved.clone()), None, None, None]);
+
+        assert_eq!(
+            diff(&old, &new).lessons,
+            [LessonChange::Changed {
+                slot: slot(0..1),
+                old: lesson("M"),
+                new: moved,
+            }]
+        );
+    }
+
+    #[test]
+    fn cancelling_half_of_a_double_lesson_is_one_cancellation() {
+        let old = timetable(vec![Some(lesson("M")), Some(lesson("M")), None, None]);
+        let new = timetable(vec![
+            Some(lesson("M")),
+            Some(with_status("M", LessonStatus::Cancelled)),
+            None,
+            None,
+        ]);
+
+        assert_eq!(
+            diff(&old, &new).lessons,
+            [LessonChange::Cancelled {
+                slot: slot(1..2),
+                lesson: with_status("M", LessonStatus::Cancelled),
+            }]
+        );
+        // And taking it back is one restoration
+        assert_eq!(
+            diff(&new, &old).lessons,
+            [LessonChange::Restored {
+                slot: slot(1..2),
+                lesson: lesson("M"),
+            }]
+        );
+    }
+
+    #[test]
+    fn shortening_a_double_lesson_removes_the_rest() {
+        let old = timetable(vec![Some(lesson("M")), Some(lesson("M")), None, None]);
+        let new = timetable(vec![Some(lesson("M")), None, None, None]);
+
+        assert_eq!(
+            diff(&old, &new).lessons,
+            [LessonChange::Removed {
+                slot: slot(1..2),
+                lesson: lesson("M"),
+            }]
+        );
+    }
+
+    #[test]
+    fn lessons_on_new_holidays_are_not_removed() {
+        let old = timetable(vec![Some(lesson("M")), None, None, None]);
+        let mut new = timetable(vec![None, None, None, None]);
+        new.columns[0].holiday = Some("Winterferien".to_owned());
+
+        assert!(diff(&old, &new).lessons.is_empty());
+    }
+
+    #[test]
+    fn period_changes_are_listed_by_position() {
+        let old = timetable(vec![None; 4]);
+        let mut new = old.clone();
+        let later = Period {
+            start: time!(8:15),
+            end: time!(9:00),
+        };
+        new.times[0][1] = later;
+        new.times[0].pop();
+
+        assert_eq!(
+            diff(&old, &new).periods,
+            [
+                PeriodChange {
+                    position: 1,
+                    old: Some(old.times[0][1]),
+                    new: Some(later),
+                },
+                PeriodChange {
+                    position: 3,
+                    old: Some(old.times[0][3]),
+                    new: None,
+                },
+            ]
+        );
+    }
+}
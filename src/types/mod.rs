@@ -1 +1,5 @@
+pub mod availability;
+pub mod diff;
+pub mod overrides;
+pub mod settings;
 pub mod timetable;
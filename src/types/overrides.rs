@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::timetable::{Lesson, LessonStatus, Subject, Timetable, WeekType};
+
+/// Personal changes to how a subject is shown. Unset fields keep the value from the source.
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SubjectOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<(u8, u8, u8)>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    /// Only shown in A- or B-weeks, for subjects the source lists every week
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub week_type: Option<WeekType>,
+}
+
+/// Overrides keyed by the short name the source uses for the subject, e.g. "PH-LK2"
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SubjectOverrides(pub BTreeMap<String, SubjectOverride>);
+
+impl SubjectOverrides {
+    pub fn apply_subject(&self, mut subject: Subject) -> Subject {
+        if let Some(o) = self.0.get(&subject.short_name) {
+            if let Some(full_name) = &o.full_name {
+                subject.full_name = full_name.clone();
+            }
+            if let Some(short_name) = &o.short_name {
+                subject.short_name = short_name.clone();
+            }
+            if let Some(color) = o.color {
+                subject.color = color;
+            }
+        }
+        subject
+    }
+
+    /// Removes the lessons of subjects limited to the other week type. Runs after `apply`, so
+    /// subjects are also found by their overridden short name. Does nothing if the week type of
+    /// `timetable` isn't known.
+    pub fn for_week_type(&self, mut timetable: Timetable) -> Timetable {
+        let Some(week_type) = timetable.week_type else {
+            return timetable;
+        };
+        let other_week = |lesson: &Lesson| {
+            self.0.iter().any(|(key, o)| {
+                (*key == lesson.subject.short_name
+                    || o.short_name.as_ref() == Some(&lesson.subject.short_name))
+                    && o.week_type.map_or(false, |only| only != week_type)
+            })
+        };
+        for column in &mut timetable.columns {
+            for slot in &mut column.lessons {
+                if slot.as_ref().map_or(false, other_week) {
+                    *slot = None;
+                }
+            }
+        }
+        timetable
+    }
+
+    /// Returns `None` for lessons of hidden subjects
+    pub fn apply(&self, lesson: Lesson) -> Option<Lesson> {
+        if self
+            .0
+            .get(&lesson.subject.short_name)
+            .map_or(false, |o| o.hidden)
+        {
+            return None;
+        }
+
+        Some(Lesson {
+            subject: self.apply_subject(lesson.subject),
+            status: match lesson.status {
+                LessonStatus::Replaced(subject) => {
+                    LessonStatus::Replaced(self.apply_subject(subject))
+                }
+                status => status,
+            },
+            course: lesson.course,
+        })
+    }
+}
+
+/// Parses colors like `#f59e0b`
+pub fn parse_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |range: std::ops::Range<usize>| u8::from_str_radix(hex.get(range)?, 16).ok();
+    Some((channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+pub fn format_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use time::Weekday;
+
+use crate::{i18n::Locale, types::overrides::SubjectOverrides};
+
+/// Preferences of a user, stored on the server. Fields need a default, so stored settings stay
+/// readable when fields are added.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    /// Homeworker course shown when no course is selected, instead of the first membership
+    pub default_course: Option<u32>,
+    pub overrides: SubjectOverrides,
+    pub notifications: NotificationSettings,
+    /// Days shown in the timetable, monday to friday unless the school also teaches on saturdays
+    pub weekdays: Vec<Weekday>,
+    /// Language of alerts and emails, the one the settings were last saved in
+    pub locale: Option<Locale>,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        UserSettings {
+            default_course: None,
+            overrides: SubjectOverrides::default(),
+            notifications: NotificationSettings::default(),
+            weekdays: vec![
+                Weekday::Monday,
+                Weekday::Tuesday,
+                Weekday::Wednesday,
+                Weekday::Thursday,
+                Weekday::Friday,
+            ],
+            locale: None,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    /// Browser notifications while the dashboard is open
+    pub browser: bool,
+    /// How long before the start of a lesson its reminder is shown
+    pub minutes_before: u8,
+    /// The server checks the timetable for changes and alerts the targets below
+    pub changes: bool,
+    /// Discord or Matrix (hookshot) webhook
+    pub webhook_url: Option<String>,
+    /// Only set once the address was confirmed, see `store::EmailConfirmation`
+    pub email: Option<String>,
+    /// One per browser that enabled push
+    pub push_subscriptions: Vec<PushSubscription>,
+}
+
+/// Web Push subscription of a browser, as returned by `PushSubscription.toJSON()`
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub keys: PushKeys,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct PushKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            browser: false,
+            minutes_before: 5,
+            changes: false,
+            webhook_url: None,
+            email: None,
+            push_subscriptions: vec![],
+        }
+    }
+}
@@ -1,27 +1,59 @@
 use serde::{Deserialize, Serialize};
+use time::{Date, Duration, Time, Weekday};
 
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Timetable {
-    pub times: Vec<Vec<String>>,
+    pub times: Vec<Vec<Period>>,
     pub columns: Vec<TimetableColumn>,
+    /// Only known for schools with alternating weeks
+    #[serde(default)]
+    pub week_type: Option<WeekType>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Period {
+    pub start: Time,
+    pub end: Time,
 }
 
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct TimetableColumn {
     pub name: String,
+    /// Used for the localised column header. Columns without a date show their name instead.
+    pub date: Option<Date>,
     pub lessons: Vec<Option<Lesson>>,
+    /// Events that don't fit into the period grid
+    #[serde(default)]
+    pub appointments: Vec<Appointment>,
+    /// Name of the holidays the day is part of. Holidays have no lessons.
+    #[serde(default)]
+    pub holiday: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub struct Appointment {
+    pub title: String,
+    /// Both are `None` for all-day appointments
+    pub start: Option<Time>,
+    pub end: Option<Time>,
+    pub location: String,
 }
 
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct GroupedTimetableColumn {
     pub name: String,
+    pub date: Option<Date>,
     pub lessons: Vec<Vec<Option<Lesson>>>,
+    pub appointments: Vec<Appointment>,
+    pub holiday: Option<String>,
 }
 
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Lesson {
     pub subject: Subject,
     pub status: LessonStatus,
+    /// Name of the course this lesson belongs to. Only set in views that combine several courses.
+    pub course: Option<String>,
 }
 
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
@@ -33,9 +65,442 @@ pub struct Subject {
     pub color: (u8, u8, u8),
 }
 
+/// Schools with alternating weeks call them A- and B-weeks
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum WeekType {
+    A,
+    B,
+}
+
+impl WeekType {
+    pub fn parse(week_type: &str) -> Option<WeekType> {
+        match week_type.trim().to_uppercase().as_str() {
+            "A" => Some(WeekType::A),
+            "B" => Some(WeekType::B),
+            _ => None,
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum LessonStatus {
     Normal,
     Cancelled,
     Replaced(Subject),
 }
+
+impl TimetableColumn {
+    /// Parallel columns of a day share its date. Columns without a date are a day of their own.
+    pub fn same_date(&self, other: &TimetableColumn) -> bool {
+        self.date.is_some() && self.date == other.date
+    }
+}
+
+impl Timetable {
+    /// Length of the period grid. Every column has exactly this many lesson slots.
+    pub fn period_count(&self) -> usize {
+        self.times.iter().map(Vec::len).sum()
+    }
+
+    /// Removes all dated columns outside of the week starting at `monday`
+    pub fn week(mut self, monday: Date) -> Timetable {
+        let sunday = monday + Duration::days(6);
+        self.columns.retain(|column| {
+            column
+                .date
+                .map_or(true, |date| date >= monday && date <= sunday)
+        });
+        self
+    }
+
+    /// Keeps the dated columns of `weekdays` in the week starting at `monday`, in the order of their
+    /// dates. Days without a column, e.g. holidays, get an empty one, so they are still shown.
+    pub fn with_weekdays(mut self, monday: Date, weekdays: &[Weekday]) -> Timetable {
+        self.columns.retain(|column| {
+            column
+                .date
+                .map_or(true, |date| weekdays.contains(&date.weekday()))
+        });
+        let period_count = self.period_count();
+        for day in 0..7 {
+            let date = monday + Duration::days(day);
+            if weekdays.contains(&date.weekday())
+                && !self.columns.iter().any(|column| column.date == Some(date))
+            {
+                self.columns.push(TimetableColumn {
+                    name: date.weekday().to_string(),
+                    date: Some(date),
+                    lessons: vec![None; period_count],
+                    appointments: vec![],
+                    holiday: None,
+                });
+            }
+        }
+        // Stable, so parallel columns of a day stay in order. Columns without a date go last.
+        self.columns
+            .sort_by_key(|column| (column.date.is_none(), column.date));
+        self
+    }
+
+    /// No lessons and no holidays, e.g. a week that isn't planned yet
+    pub fn is_empty(&self) -> bool {
+        self.columns
+            .iter()
+            .all(|column| column.holiday.is_none() && column.lessons.iter().all(Option::is_none))
+    }
+
+    /// Leaves out the columns of holidays, e.g. for exports
+    pub fn without_holidays(mut self) -> Timetable {
+        self.columns.retain(|column| column.holiday.is_none());
+        self
+    }
+
+    /// The first column of every day with the number of parallel columns it has, so each date
+    /// gets a single header. Columns are grouped if they follow each other and share their date.
+    pub fn days(&self) -> Vec<(&TimetableColumn, usize)> {
+        let mut days: Vec<(&TimetableColumn, usize)> = vec![];
+        for column in &self.columns {
+            match days.last_mut() {
+                Some((first, lanes)) if first.same_date(column) => *lanes += 1,
+                _ => days.push((column, 1)),
+            }
+        }
+        days
+    }
+
+    /// First and last date of the dated columns
+    pub fn date_range(&self) -> Option<(Date, Date)> {
+        let mut dates = self.columns.iter().filter_map(|column| column.date);
+        let first = dates.next()?;
+        Some(dates.fold((first, first), |(min, max), date| (min.min(date), max.max(date))))
+    }
+
+    /// Merges the timetables of several courses into one. Lessons are attributed to their course, and
+    /// overlapping lessons are moved into parallel columns of the same day.
+    ///
+    /// The times are taken from the timetable with the most periods, as the courses of a school share
+    /// their periods, but some courses end earlier.
+    pub fn merge(timetables: Vec<(String, Timetable)>) -> Timetable {
+        let times = timetables
+            .iter()
+            .map(|(_, timetable)| timetable)
+            .rev()
+            .max_by_key(|timetable| timetable.period_count())
+            .map(|timetable| timetable.times.clone())
+            .unwrap_or_default();
+        let period_count: usize = times.iter().map(Vec::len).sum();
+
+        // Columns are matched by date, or by name for columns without one
+        let same_day = |a: &TimetableColumn, b: &TimetableColumn| match (a.date, b.date) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => a.name == b.name,
+            _ => false,
+        };
+
+        let mut days: Vec<&TimetableColumn> = vec![];
+        for column in timetables
+            .iter()
+            .flat_map(|(_, timetable)| &timetable.columns)
+        {
+            if !days.iter().any(|day| same_day(day, column)) {
+                days.push(column);
+            }
+        }
+        // Like `with_weekdays`, columns without a date go last
+        days.sort_by_key(|day| (day.date.is_none(), day.date));
+
+        let mut columns = vec![];
+        for day in days {
+            let mut lanes: Vec<Vec<Option<Lesson>>> = vec![];
+            let mut appointments = vec![];
+
+            for (course, timetable) in &timetables {
+                let Some(column) = timetable.columns.iter().find(|c| same_day(c, day)) else {
+                    continue;
+                };
+                appointments.extend(column.appointments.iter().cloned());
+
+                // Consecutive positions with the same lesson are placed together, so double lessons
+                // don't get split across lanes
+                if column.lessons[period_count.min(column.lessons.len())..]
+                    .iter()
+                    .any(Option::is_some)
+                {
+                    log::warn!(
+                        "Lessons of {course} on {} are outside of the period grid",
+                        column.name
+                    );
+                }
+                let lessons = &column.lessons[..column.lessons.len().min(period_count)];
+                let mut position = 0;
+                while position < lessons.len() {
+                    let Some(lesson) = &lessons[position] else {
+                        position += 1;
+                        continue;
+                    };
+                    let length = lessons[position..]
+                        .iter()
+                        .take_while(|other| other.as_ref() == Some(lesson))
+                        .count();
+                    let range = position..position + length;
+
+                    let lane = match lanes
+                        .iter()
+                        .position(|lane| range.clone().all(|i| lane[i].is_none()))
+                    {
+                        Some(lane) => lane,
+                        None => {
+                            lanes.push(vec![None; period_count]);
+                            lanes.len() - 1
+                        }
+                    };
+
+                    let lane = &mut lanes[lane];
+                    let lesson = Lesson {
+                        course: Some(course.clone()),
+                        ..lesson.clone()
+                    };
+                    range.for_each(|i| lane[i] = Some(lesson.clone()));
+
+                    position += length;
+                }
+            }
+
+            let holiday = timetables
+                .iter()
+                .flat_map(|(_, timetable)| &timetable.columns)
+                .filter(|column| same_day(column, day))
+                .find_map(|column| column.holiday.clone());
+
+            // Keep free days visible
+            if lanes.is_empty() {
+                lanes.push(vec![None; period_count]);
+            }
+
+            for (i, lessons) in lanes.into_iter().enumerate() {
+                columns.push(TimetableColumn {
+                    name: match i {
+                        0 => day.name.clone(),
+                        _ => format!("{} ({})", day.name, i + 1),
+                    },
+                    date: day.date,
+                    lessons,
+                    // Shown once per day, above the first lane
+                    appointments: match i {
+                        0 => std::mem::take(&mut appointments),
+                        _ => vec![],
+                    },
+                    holiday: holiday.clone(),
+                });
+            }
+        }
+
+        let week_type = timetables
+            .iter()
+            .find_map(|(_, timetable)| timetable.week_type);
+
+        Timetable {
+            times,
+            columns,
+            week_type,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::test_util::{self, column, lesson};
+
+    /// Two groups of three periods
+    fn timetable(columns: Vec<TimetableColumn>) -> Timetable {
+        test_util::timetable(&[3, 3], columns)
+    }
+
+    fn short_names(column: &TimetableColumn) -> Vec<Option<&str>> {
+        column
+            .lessons
+            .iter()
+            .map(|lesson| {
+                lesson
+                    .as_ref()
+                    .map(|lesson| lesson.subject.short_name.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_keeps_long_lessons_in_one_lane() {
+        let monday = date!(2023 - 01 - 16);
+        let project = timetable(vec![column(
+            monday,
+            vec![
+                Some(lesson("P")),
+                Some(lesson("P")),
+                Some(lesson("P")),
+                Some(lesson("P")),
+                None,
+                None,
+            ],
+        )]);
+        let sports = timetable(vec![column(
+            monday,
+            vec![None, None, None, None, Some(lesson("S")), Some(lesson("S"))],
+        )]);
+
+        let merged = Timetable::merge(vec![("5a".to_owned(), project), ("5b".to_owned(), sports)]);
+
+        assert_eq!(merged.columns.len(), 1);
+        assert_eq!(
+            short_names(&merged.columns[0]),
+            [
+                Some("P"),
+                Some("P"),
+                Some("P"),
+                Some("P"),
+                Some("S"),
+                Some("S")
+            ]
+        );
+        assert_eq!(
+            merged.columns[0].lessons[3]
+                .as_ref()
+                .unwrap()
+                .course
+                .as_deref(),
+            Some("5a")
+        );
+        assert_eq!(
+            merged.columns[0].lessons[4]
+                .as_ref()
+                .unwrap()
+                .course
+                .as_deref(),
+            Some("5b")
+        );
+    }
+
+    #[test]
+    fn merge_moves_overlapping_lessons_into_parallel_lanes() {
+        let monday = date!(2023 - 01 - 16);
+        let first = timetable(vec![column(
+            monday,
+            vec![
+                Some(lesson("M")),
+                Some(lesson("M")),
+                Some(lesson("M")),
+                None,
+                None,
+                None,
+            ],
+        )]);
+        let second = timetable(vec![column(
+            monday,
+            vec![None, None, Some(lesson("E")), Some(lesson("E")), None, None],
+        )]);
+
+        let merged = Timetable::merge(vec![("5a".to_owned(), first), ("5b".to_owned(), second)]);
+
+        assert_eq!(merged.columns.len(), 2);
+        assert!(merged
+            .columns
+            .iter()
+            .all(|column| column.lessons.len() == 6));
+        assert_eq!(
+            short_names(&merged.columns[0]),
+            [Some("M"), Some("M"), Some("M"), None, None, None]
+        );
+        // The double lesson isn't split, although its second half would fit into the first lane
+        assert_eq!(short_names(&merged.columns[1]), [None, None, Some("E"), Some("E"), None, None]);
+    }
+
+    #[test]
+    fn merge_keeps_free_days_and_sorts_by_date() {
+        let monday = date!(2023 - 01 - 16);
+        let tuesday = date!(2023 - 01 - 17);
+        let first = timetable(vec![column(tuesday, vec![Some(lesson("M")); 6])]);
+        let second = timetable(vec![column(monday, vec![None; 6])]);
+
+        let merged = Timetable::merge(vec![("5a".to_owned(), first), ("5b".to_owned(), second)]);
+
+        let dates: Vec<_> = merged.columns.iter().map(|column| column.date).collect();
+        assert_eq!(dates, [Some(monday), Some(tuesday)]);
+        assert_eq!(merged.columns[0].lessons, vec![None; 6]);
+    }
+
+    #[test]
+    fn merge_uses_the_longest_period_grid() {
+        let monday = date!(2023 - 01 - 16);
+        let short = test_util::timetable(&[2], vec![column(monday, vec![Some(lesson("M")); 2])]);
+        let long = timetable(vec![column(
+            monday,
+            vec![None, None, None, None, None, Some(lesson("E"))],
+        )]);
+
+        let merged = Timetable::merge(vec![("5a".to_owned(), short), ("5b".to_owned(), long)]);
+
+        assert_eq!(merged.period_count(), 6);
+        assert_eq!(merged.columns.len(), 1);
+        assert_eq!(
+            short_names(&merged.columns[0]),
+            [Some("M"), Some("M"), None, None, None, Some("E")]
+        );
+    }
+
+    #[test]
+    fn merge_puts_columns_without_a_date_last() {
+        let monday = date!(2023 - 01 - 16);
+        let mut extra = column(monday, vec![None; 6]);
+        extra.date = None;
+        extra.name = "Extra".to_owned();
+        let first = timetable(vec![extra]);
+        let second = timetable(vec![column(monday, vec![Some(lesson("M")); 6])]);
+
+        let merged = Timetable::merge(vec![("5a".to_owned(), first), ("5b".to_owned(), second)]);
+        let dates: Vec<_> = merged.columns.iter().map(|column| column.date).collect();
+
+        assert_eq!(dates, [Some(monday), None]);
+        assert_eq!(
+            merged
+                .clone()
+                .with_weekdays(monday, &[Weekday::Monday])
+                .columns,
+            merged.columns
+        );
+    }
+
+    #[test]
+    fn days_have_one_entry_per_date() {
+        let monday = date!(2023 - 01 - 16);
+        let tuesday = date!(2023 - 01 - 17);
+        let first = timetable(vec![
+            column(monday, vec![Some(lesson("M")); 6]),
+            column(tuesday, vec![Some(lesson("D")); 6]),
+        ]);
+        let second = timetable(vec![column(monday, vec![Some(lesson("E")); 6])]);
+
+        let merged = Timetable::merge(vec![("5a".to_owned(), first), ("5b".to_owned(), second)]);
+        let days: Vec<_> = merged
+            .days()
+            .into_iter()
+            .map(|(column, lanes)| (column.date, lanes))
+            .collect();
+
+        assert_eq!(merged.columns.len(), 3);
+        assert_eq!(days, [(Some(monday), 2), (Some(tuesday), 1)]);
+    }
+
+    #[test]
+    fn days_without_date_stay_separate() {
+        let mut first = column(date!(2023 - 01 - 16), vec![None; 6]);
+        first.date = None;
+        let mut second = first.clone();
+        second.name = "Extra".to_owned();
+
+        let timetable = timetable(vec![first, second]);
+
+        assert_eq!(timetable.days().len(), 2);
+    }
+}